@@ -4,31 +4,132 @@
 #include "pb_encode.h"
 #include "pb_decode.h"
 #include "calc.pb.h"
+#include "calc_service.h"
 #include "trace_util.h"
 
-// Decode CalcRequest from `in` and fill CalcResponse into `out`
-bool calc_handle_request(const uint8_t *in, size_t in_len,
-                         uint8_t *out, size_t out_cap, size_t *out_len)
+__attribute__((weak)) uint64_t calc_now_ns(void)
+{
+    return 0;
+}
+
+bool calc_encode_error(rpmsg_calc_v1_Status code, const char *detail,
+                       const rpmsg_calc_v1_TraceCtx *trace,
+                       uint8_t *out, size_t out_cap, size_t *out_len)
+{
+    rpmsg_calc_v1_CalcError err = rpmsg_calc_v1_CalcError_init_zero;
+
+    err.code = code;
+    if (detail) {
+        strncpy(err.detail, detail, sizeof(err.detail) - 1);
+    }
+    if (trace) {
+        err.has_trace = true;
+        trace_copy(&err.trace, trace);
+    }
+
+    pb_ostream_t os = pb_ostream_from_buffer(out, out_cap);
+    if(!pb_encode(&os, rpmsg_calc_v1_CalcError_fields, &err)) {
+        return false;
+    }
+    *out_len = os.bytes_written;
+    return true;
+}
+
+/* Checked u32 arithmetic; mirrors calc::evaluate on the Linux side */
+static rpmsg_calc_v1_Status calc_eval(rpmsg_calc_v1_Op op, uint32_t a, uint32_t b,
+                                      uint32_t *result)
+{
+    switch (op) {
+    case rpmsg_calc_v1_Op_OP_SUM:
+        if (a > UINT32_MAX - b) return rpmsg_calc_v1_Status_STATUS_OVERFLOW;
+        *result = a + b;
+        return rpmsg_calc_v1_Status_STATUS_OK;
+    case rpmsg_calc_v1_Op_OP_SUB:
+        if (a < b) return rpmsg_calc_v1_Status_STATUS_OVERFLOW;
+        *result = a - b;
+        return rpmsg_calc_v1_Status_STATUS_OK;
+    case rpmsg_calc_v1_Op_OP_MUL:
+        if ((uint64_t)a * b > UINT32_MAX) return rpmsg_calc_v1_Status_STATUS_OVERFLOW;
+        *result = a * b;
+        return rpmsg_calc_v1_Status_STATUS_OK;
+    case rpmsg_calc_v1_Op_OP_DIV:
+        if (b == 0) return rpmsg_calc_v1_Status_STATUS_DIV_BY_ZERO;
+        *result = a / b;
+        return rpmsg_calc_v1_Status_STATUS_OK;
+    default:
+        return rpmsg_calc_v1_Status_STATUS_UNSUPPORTED_OP;
+    }
+}
+
+static const char *status_detail(rpmsg_calc_v1_Status st)
+{
+    switch (st) {
+    case rpmsg_calc_v1_Status_STATUS_UNSUPPORTED_OP: return "unsupported op";
+    case rpmsg_calc_v1_Status_STATUS_DIV_BY_ZERO:    return "division by zero";
+    case rpmsg_calc_v1_Status_STATUS_OVERFLOW:       return "overflow";
+    default:                                         return "";
+    }
+}
+
+// Decode CalcRequest from `in` and fill CalcResponse (or CalcError) into `out`
+uint8_t calc_handle_request(const uint8_t *in, size_t in_len, uint64_t rx_ns,
+                            uint8_t *out, size_t out_cap, size_t *out_len)
 {
     rpmsg_calc_v1_CalcRequest req = rpmsg_calc_v1_CalcRequest_init_zero;
     rpmsg_calc_v1_CalcResponse resp = rpmsg_calc_v1_CalcResponse_init_zero;
 
     pb_istream_t is = pb_istream_from_buffer(in, in_len);
     if(!pb_decode(&is, rpmsg_calc_v1_CalcRequest_fields, &req)) {
-        return false;
+        return calc_encode_error(rpmsg_calc_v1_Status_STATUS_DECODE_ERROR,
+                                 PB_GET_ERROR(&is), NULL,
+                                 out, out_cap, out_len) ? CALC_TYPE_ERR : 0;
     }
 
-    resp.sum = req.a + req.b;
+    const rpmsg_calc_v1_TraceCtx *trace = req.has_trace ? &req.trace : NULL;
+    uint64_t t0 = calc_now_ns();
+    rpmsg_calc_v1_Status st = calc_eval(req.op, req.a, req.b, &resp.result);
+    resp.r5_compute_ns = calc_now_ns() - t0;
+    if (st != rpmsg_calc_v1_Status_STATUS_OK) {
+        return calc_encode_error(st, status_detail(st), trace,
+                                 out, out_cap, out_len) ? CALC_TYPE_ERR : 0;
+    }
 
-    if (req.has_trace) {
+    if (trace) {
         resp.has_trace = true;
-        trace_copy(&resp.trace, &req.trace); // echo back
+        trace_copy(&resp.trace, trace); // echo back
     }
+    resp.r5_rx_ns = rx_ns;
+    resp.r5_tx_ns = calc_now_ns();
 
     pb_ostream_t os = pb_ostream_from_buffer(out, out_cap);
     if(!pb_encode(&os, rpmsg_calc_v1_CalcResponse_fields, &resp)) {
-        return false;
+        return 0;
     }
     *out_len = os.bytes_written;
-    return true;
+    return CALC_TYPE_RESP;
+}
+
+uint8_t calc_handle_time_sync(const uint8_t *in, size_t in_len, uint64_t rx_ns,
+                              uint8_t *out, size_t out_cap, size_t *out_len)
+{
+    rpmsg_calc_v1_TimeSyncRequest req = rpmsg_calc_v1_TimeSyncRequest_init_zero;
+    rpmsg_calc_v1_TimeSyncResponse resp = rpmsg_calc_v1_TimeSyncResponse_init_zero;
+
+    pb_istream_t is = pb_istream_from_buffer(in, in_len);
+    if(!pb_decode(&is, rpmsg_calc_v1_TimeSyncRequest_fields, &req)) {
+        return calc_encode_error(rpmsg_calc_v1_Status_STATUS_DECODE_ERROR,
+                                 PB_GET_ERROR(&is), NULL,
+                                 out, out_cap, out_len) ? CALC_TYPE_ERR : 0;
+    }
+
+    resp.t1_ns = req.t1_ns;
+    resp.t2_ns = rx_ns;
+    resp.t3_ns = calc_now_ns();
+
+    pb_ostream_t os = pb_ostream_from_buffer(out, out_cap);
+    if(!pb_encode(&os, rpmsg_calc_v1_TimeSyncResponse_fields, &resp)) {
+        return 0;
+    }
+    *out_len = os.bytes_written;
+    return CALC_TYPE_TIME_RESP;
 }
@@ -2,6 +2,31 @@
 #include <stddef.h>
 #include <stdint.h>
 #include <stdbool.h>
+#include "calc.pb.h"
 
-bool calc_handle_request(const uint8_t *in, size_t in_len,
-                         uint8_t *out, size_t out_cap, size_t *out_len);
+/* Frame types written by the service (match wire::TYPE_* on Linux) */
+#define CALC_TYPE_RESP      2
+#define CALC_TYPE_ERR       3
+#define CALC_TYPE_TIME_RESP 5
+
+/* R5 clock in ns for the CalcResponse timing fields. The weak default
+ * returns 0 (no timing reported); firmware overrides it with its timer. */
+uint64_t calc_now_ns(void);
+
+/* Decode a CalcRequest from `in` and encode the reply into `out`. `rx_ns` is
+ * the calc_now_ns() reading taken when the request frame arrived.
+ * Returns the frame type of the reply (CALC_TYPE_RESP, or CALC_TYPE_ERR when
+ * the request was rejected), or 0 if no reply fits in `out`. */
+uint8_t calc_handle_request(const uint8_t *in, size_t in_len, uint64_t rx_ns,
+                            uint8_t *out, size_t out_cap, size_t *out_len);
+
+/* Answer a TimeSyncRequest: t1 echoed, t2 = `rx_ns`, t3 read just before
+ * encoding. Returns CALC_TYPE_TIME_RESP, CALC_TYPE_ERR if the request does
+ * not decode, or 0 if no reply fits in `out`. */
+uint8_t calc_handle_time_sync(const uint8_t *in, size_t in_len, uint64_t rx_ns,
+                              uint8_t *out, size_t out_cap, size_t *out_len);
+
+/* Encode a CalcError reply. `trace` may be NULL. */
+bool calc_encode_error(rpmsg_calc_v1_Status code, const char *detail,
+                       const rpmsg_calc_v1_TraceCtx *trace,
+                       uint8_t *out, size_t out_cap, size_t *out_len);
@@ -0,0 +1,79 @@
+#include <string.h>
+
+#include "r5/frag_reasm.h"
+
+#define HDR_LEN CALC_FRAME_HDR_LEN
+
+void calc_reasm_init(calc_reasm_t *r)
+{
+    r->active = false;
+}
+
+static bool is_v2_frame(const uint8_t *f, size_t flen)
+{
+    if (flen < HDR_LEN) return false;
+    if (f[0] != 0xA5 || f[1] != 0x5A || f[2] != 0x02) return false;
+    if (calc_crc8(f, HDR_LEN - 1) != f[9]) return false;
+
+    uint16_t len = (uint16_t)f[5] << 8 | (uint16_t)f[6];
+    if ((size_t)HDR_LEN + len + calc_csum_len(f[4]) > flen) return false;
+    return calc_csum_ok(f[4], f + HDR_LEN, len);
+}
+
+calc_reasm_result calc_reasm_push(calc_reasm_t *r, const uint8_t *f,
+                                  size_t flen, uint64_t now_ns,
+                                  const uint8_t **msg, size_t *msg_len)
+{
+    if (!is_v2_frame(f, flen) || !(f[4] & CALC_FRAG_MASK)) {
+        *msg = f;
+        *msg_len = flen;
+        return CALC_REASM_PASS;
+    }
+
+    if (r->active && now_ns && now_ns - r->started_ns > CALC_REASM_TIMEOUT_NS)
+        r->active = false;
+
+    uint8_t typ = f[3];
+    uint8_t pos = f[4] & CALC_FRAG_MASK;
+    uint16_t len = (uint16_t)f[5] << 8 | (uint16_t)f[6];
+    uint16_t seq = (uint16_t)f[7] << 8 | (uint16_t)f[8];
+    if (len < CALC_FRAG_HDR_LEN) return CALC_REASM_DROP;
+    const uint8_t *p = f + HDR_LEN;
+    uint16_t index = (uint16_t)p[0] << 8 | (uint16_t)p[1];
+    uint16_t total = (uint16_t)p[2] << 8 | (uint16_t)p[3];
+    const uint8_t *chunk = p + CALC_FRAG_HDR_LEN;
+    uint16_t chunk_len = len - CALC_FRAG_HDR_LEN;
+
+    if (pos == CALC_FRAG_FIRST) {
+        /* A new message replaces the one in progress */
+        r->active = false;
+        if (index != 0 || total > CALC_REASM_MAX) return CALC_REASM_DROP;
+        r->active = true;
+        r->typ = typ;
+        r->flags = f[4] & (uint8_t)~CALC_FRAG_MASK;
+        r->seq = seq;
+        r->total = total;
+        r->next_index = 0;
+        r->have = 0;
+        r->started_ns = now_ns;
+    } else if (pos != CALC_FRAG_MIDDLE && pos != CALC_FRAG_LAST) {
+        return CALC_REASM_DROP;
+    }
+
+    if (!r->active || typ != r->typ || seq != r->seq) return CALC_REASM_DROP;
+    if (index != r->next_index || total != r->total ||
+        (size_t)r->have + chunk_len > r->total) {
+        r->active = false;
+        return CALC_REASM_DROP;
+    }
+    memcpy(r->frame + HDR_LEN + r->have, chunk, chunk_len);
+    r->have += chunk_len;
+    r->next_index++;
+    if (pos != CALC_FRAG_LAST) return CALC_REASM_MORE;
+
+    r->active = false;
+    if (r->have != r->total) return CALC_REASM_DROP;
+    *msg = r->frame;
+    *msg_len = calc_seal_frame(r->frame, r->typ, r->flags, r->seq, r->total);
+    return CALC_REASM_DONE;
+}
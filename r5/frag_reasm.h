@@ -0,0 +1,56 @@
+#pragma once
+#include <stddef.h>
+#include <stdint.h>
+#include <stdbool.h>
+#include "r5/frame_decode.h"
+
+/* Fragment flags in the v2 header (match wire::FLAG_FRAG_* on Linux). Each
+ * fragment's payload starts with [index BE16][total BE16], the fragment
+ * number from 0 and the length of the original payload. */
+#define CALC_FRAG_FIRST   0x01
+#define CALC_FRAG_MIDDLE  0x02
+#define CALC_FRAG_LAST    0x04
+#define CALC_FRAG_MASK    0x07
+#define CALC_FRAG_HDR_LEN 4
+
+/* Largest payload reassembled. One message is reassembled at a time. */
+#ifndef CALC_REASM_MAX
+#define CALC_REASM_MAX 1024
+#endif
+
+/* A message whose fragments stop arriving is dropped after this long. Only
+ * applies with a clock (calc_now_ns() != 0); without one the next first
+ * fragment replaces the stale message. */
+#ifndef CALC_REASM_TIMEOUT_NS
+#define CALC_REASM_TIMEOUT_NS 100000000ull
+#endif
+
+typedef enum {
+    CALC_REASM_PASS, /* not a fragment: handle the frame as is */
+    CALC_REASM_MORE, /* fragment stored, waiting for the rest */
+    CALC_REASM_DONE, /* message complete: handle the rebuilt frame */
+    CALC_REASM_DROP, /* fragment discarded */
+} calc_reasm_result;
+
+typedef struct {
+    bool active;
+    uint8_t typ;
+    uint8_t flags; /* of the original frame, fragment bits cleared */
+    uint16_t seq;
+    uint16_t total;
+    uint16_t next_index;
+    uint16_t have;
+    uint64_t started_ns;
+    /* The rebuilt frame: header, payload, checksum */
+    uint8_t frame[CALC_FRAME_HDR_LEN + CALC_REASM_MAX + CALC_FRAME_CRC_LEN];
+} calc_reasm_t;
+
+void calc_reasm_init(calc_reasm_t *r);
+
+/* Feed one received frame. On PASS `*msg` is `frame` itself, on DONE the
+ * rebuilt frame inside `r` (valid until the next push); either way hand
+ * `*msg` to calc_handle_frame. Frames that fail their header CRC or checksum
+ * PASS, so the decoder reports them as usual. */
+calc_reasm_result calc_reasm_push(calc_reasm_t *r, const uint8_t *frame,
+                                  size_t frame_len, uint64_t now_ns,
+                                  const uint8_t **msg, size_t *msg_len);
@@ -0,0 +1,205 @@
+#include <string.h>
+
+#include "r5/frame_auth.h"
+
+#define HDR_LEN CALC_FRAME_HDR_LEN
+
+/* SHA-256 (FIPS 180-4) */
+typedef struct {
+    uint32_t h[8];
+    uint8_t block[64];
+    size_t fill;
+    uint64_t total;
+} sha256_t;
+
+static const uint32_t K[64] = {
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+};
+
+#define ROR(x, n) ((x) >> (n) | (x) << (32 - (n)))
+
+static void sha256_block(sha256_t *s, const uint8_t *p)
+{
+    uint32_t w[64];
+    for (int i = 0; i < 16; i++)
+        w[i] = (uint32_t)p[4 * i] << 24 | (uint32_t)p[4 * i + 1] << 16 |
+               (uint32_t)p[4 * i + 2] << 8 | (uint32_t)p[4 * i + 3];
+    for (int i = 16; i < 64; i++) {
+        uint32_t s0 = ROR(w[i - 15], 7) ^ ROR(w[i - 15], 18) ^ (w[i - 15] >> 3);
+        uint32_t s1 = ROR(w[i - 2], 17) ^ ROR(w[i - 2], 19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16] + s0 + w[i - 7] + s1;
+    }
+    uint32_t a = s->h[0], b = s->h[1], c = s->h[2], d = s->h[3];
+    uint32_t e = s->h[4], f = s->h[5], g = s->h[6], h = s->h[7];
+    for (int i = 0; i < 64; i++) {
+        uint32_t t1 = h + (ROR(e, 6) ^ ROR(e, 11) ^ ROR(e, 25)) + ((e & f) ^ (~e & g)) + K[i] + w[i];
+        uint32_t t2 = (ROR(a, 2) ^ ROR(a, 13) ^ ROR(a, 22)) + ((a & b) ^ (a & c) ^ (b & c));
+        h = g; g = f; f = e; e = d + t1;
+        d = c; c = b; b = a; a = t1 + t2;
+    }
+    s->h[0] += a; s->h[1] += b; s->h[2] += c; s->h[3] += d;
+    s->h[4] += e; s->h[5] += f; s->h[6] += g; s->h[7] += h;
+}
+
+static void sha256_init(sha256_t *s)
+{
+    static const uint32_t h0[8] = {
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a,
+        0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+    };
+    memcpy(s->h, h0, sizeof h0);
+    s->fill = 0;
+    s->total = 0;
+}
+
+static void sha256_update(sha256_t *s, const uint8_t *p, size_t n)
+{
+    s->total += n;
+    while (n--) {
+        s->block[s->fill++] = *p++;
+        if (s->fill == 64) {
+            sha256_block(s, s->block);
+            s->fill = 0;
+        }
+    }
+}
+
+static void sha256_final(sha256_t *s, uint8_t out[32])
+{
+    uint64_t bits = s->total * 8;
+    uint8_t pad = 0x80;
+    sha256_update(s, &pad, 1);
+    pad = 0;
+    while (s->fill != 56)
+        sha256_update(s, &pad, 1);
+    for (int i = 7; i >= 0; i--) {
+        uint8_t b = (uint8_t)(bits >> (8 * i));
+        sha256_update(s, &b, 1);
+    }
+    for (int i = 0; i < 8; i++) {
+        out[4 * i] = (uint8_t)(s->h[i] >> 24);
+        out[4 * i + 1] = (uint8_t)(s->h[i] >> 16);
+        out[4 * i + 2] = (uint8_t)(s->h[i] >> 8);
+        out[4 * i + 3] = (uint8_t)s->h[i];
+    }
+}
+
+/* HMAC (RFC 2104); keys over one block are hashed first */
+void calc_hmac_sha256(const uint8_t *key, size_t key_len,
+                      const uint8_t *p, size_t n, uint8_t mac[32])
+{
+    uint8_t k[64] = {0};
+    sha256_t s;
+    if (key_len > sizeof k) {
+        sha256_init(&s);
+        sha256_update(&s, key, key_len);
+        sha256_final(&s, k);
+    } else {
+        memcpy(k, key, key_len);
+    }
+
+    uint8_t pad[64];
+    for (int i = 0; i < 64; i++) pad[i] = k[i] ^ 0x36;
+    sha256_init(&s);
+    sha256_update(&s, pad, sizeof pad);
+    sha256_update(&s, p, n);
+    uint8_t inner[32];
+    sha256_final(&s, inner);
+
+    for (int i = 0; i < 64; i++) pad[i] = k[i] ^ 0x5c;
+    sha256_init(&s);
+    sha256_update(&s, pad, sizeof pad);
+    sha256_update(&s, inner, sizeof inner);
+    sha256_final(&s, mac);
+}
+
+void calc_auth_init(calc_auth_t *a, const uint8_t *key, size_t key_len)
+{
+    a->key = key;
+    a->key_len = key_len;
+    a->highest = 0;
+    a->seen = 0;
+}
+
+/* Accept `counter` once, as auth::ReplayWindow does */
+static bool replay_check(calc_auth_t *a, uint64_t counter)
+{
+    if (counter > a->highest) {
+        uint64_t shift = counter - a->highest;
+        a->seen = shift >= CALC_AUTH_WINDOW ? 0 : a->seen << shift;
+        a->seen |= 1;
+        a->highest = counter;
+        return true;
+    }
+    uint64_t age = a->highest - counter;
+    if (counter == 0 || age >= CALC_AUTH_WINDOW) return false;
+    if (a->seen & (1ull << age)) return false;
+    a->seen |= 1ull << age;
+    return true;
+}
+
+bool calc_auth_open(calc_auth_t *a, const uint8_t *f, size_t flen,
+                    uint8_t *out, size_t out_cap, size_t *out_len,
+                    uint64_t *counter)
+{
+    if (flen < HDR_LEN) return false;
+    if (f[0] != 0xA5 || f[1] != 0x5A || f[2] != 0x02) return false;
+    if (calc_crc8(f, HDR_LEN - 1) != f[9]) return false;
+    uint8_t flags = f[4];
+    if (!(flags & CALC_FLAG_AUTH)) return false;
+
+    uint16_t len = (uint16_t)f[5] << 8 | (uint16_t)f[6];
+    if ((size_t)HDR_LEN + len + calc_csum_len(flags) > flen) return false;
+    if (!calc_csum_ok(flags, f + HDR_LEN, len)) return false;
+    if (len < CALC_AUTH_LEN) return false;
+
+    uint16_t msg_len = len - CALC_AUTH_LEN;
+    const uint8_t *c = f + HDR_LEN + msg_len;
+    uint8_t mac[32];
+    calc_hmac_sha256(a->key, a->key_len, f, HDR_LEN + msg_len + CALC_AUTH_COUNTER_LEN, mac);
+    uint8_t diff = 0;
+    for (int i = 0; i < CALC_AUTH_TAG_LEN; i++)
+        diff |= mac[i] ^ c[CALC_AUTH_COUNTER_LEN + i];
+    if (diff) return false;
+
+    uint64_t n = 0;
+    for (int i = 0; i < CALC_AUTH_COUNTER_LEN; i++)
+        n = n << 8 | c[i];
+    if (!replay_check(a, n)) return false;
+
+    if (out_cap < (size_t)HDR_LEN + msg_len + CALC_FRAME_CRC_LEN) return false;
+    memmove(out + HDR_LEN, f + HDR_LEN, msg_len);
+    *out_len = calc_seal_frame(out, f[3], flags & (uint8_t)~CALC_FLAG_AUTH,
+                               (uint16_t)f[7] << 8 | f[8], msg_len);
+    *counter = n;
+    return true;
+}
+
+size_t calc_auth_seal(const calc_auth_t *a, uint8_t *f, size_t flen,
+                      size_t cap, uint64_t counter)
+{
+    if (flen < HDR_LEN) return 0;
+    uint8_t typ = f[3];
+    uint8_t flags = f[4] | CALC_FLAG_AUTH;
+    uint16_t len = (uint16_t)f[5] << 8 | (uint16_t)f[6];
+    uint16_t seq = (uint16_t)f[7] << 8 | (uint16_t)f[8];
+    if ((size_t)HDR_LEN + len + CALC_AUTH_LEN + calc_csum_len(flags) > cap) return 0;
+
+    uint8_t *c = f + HDR_LEN + len;
+    for (int i = 0; i < CALC_AUTH_COUNTER_LEN; i++)
+        c[i] = (uint8_t)(counter >> (8 * (CALC_AUTH_COUNTER_LEN - 1 - i)));
+    /* Seal once for the final header, tag it, then again for the checksum */
+    memset(c + CALC_AUTH_COUNTER_LEN, 0, CALC_AUTH_TAG_LEN);
+    calc_seal_frame(f, typ, flags, seq, (uint16_t)(len + CALC_AUTH_LEN));
+    uint8_t mac[32];
+    calc_hmac_sha256(a->key, a->key_len, f, HDR_LEN + len + CALC_AUTH_COUNTER_LEN, mac);
+    memcpy(c + CALC_AUTH_COUNTER_LEN, mac, CALC_AUTH_TAG_LEN);
+    return calc_seal_frame(f, typ, flags, seq, (uint16_t)(len + CALC_AUTH_LEN));
+}
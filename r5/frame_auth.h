@@ -0,0 +1,43 @@
+#pragma once
+#include <stddef.h>
+#include <stdint.h>
+#include <stdbool.h>
+#include "r5/frame_decode.h"
+
+/* Authenticated v2 frames (match wire::seal_auth on Linux). Flag 0x80 marks
+ * a payload ending in [counter BE64][HMAC-SHA256 truncated to 16 bytes]; the
+ * tag covers the header, message and counter. The header length includes
+ * the block, so calc_reasm_push handles these frames unchanged. */
+#define CALC_FLAG_AUTH        0x80
+#define CALC_AUTH_COUNTER_LEN 8
+#define CALC_AUTH_TAG_LEN     16
+#define CALC_AUTH_LEN         (CALC_AUTH_COUNTER_LEN + CALC_AUTH_TAG_LEN)
+
+/* Counters within this many of the highest accepted are let in once. */
+#define CALC_AUTH_WINDOW 64
+
+typedef struct {
+    const uint8_t *key;
+    size_t key_len;
+    uint64_t highest; /* highest accepted counter, 0 before the first */
+    uint64_t seen;    /* bit i: highest - i was accepted */
+} calc_auth_t;
+
+void calc_auth_init(calc_auth_t *a, const uint8_t *key, size_t key_len);
+
+/* Check the tag and counter of `frame` and write it to `out` without the
+ * authentication block, ready for calc_handle_frame. `*counter` is for
+ * calc_auth_seal. False for frames that are not authenticated with the key
+ * or replay a counter; they get no reply. */
+bool calc_auth_open(calc_auth_t *a, const uint8_t *frame, size_t frame_len,
+                    uint8_t *out, size_t out_cap, size_t *out_len,
+                    uint64_t *counter);
+
+/* Authenticate the v2 frame of `frame_len` bytes in `frame`, in place, with
+ * `counter`. Returns the new length, or 0 if it does not fit in `cap`. */
+size_t calc_auth_seal(const calc_auth_t *a, uint8_t *frame, size_t frame_len,
+                      size_t cap, uint64_t counter);
+
+/* HMAC-SHA256 of `n` bytes, in full. */
+void calc_hmac_sha256(const uint8_t *key, size_t key_len,
+                      const uint8_t *p, size_t n, uint8_t mac[32]);
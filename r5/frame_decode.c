@@ -1,21 +1,154 @@
 #include "r5/calc_service.h"
+#include "r5/frame_decode.h"
 
 #define SYNC_HI 0xA5
 #define SYNC_LO 0x5A
-#define VER     0x01
-#define TYPE_CALC_REQ 1
+#define VER     0x02
+#define TYPE_CALC_REQ  1
+#define TYPE_TIME_REQ  4
+#define HDR_LEN CALC_FRAME_HDR_LEN
+#define CRC_LEN CALC_FRAME_CRC_LEN
+
+/* CRC-8, poly 0x07, init 0 (header check) */
+uint8_t calc_crc8(const uint8_t *p, size_t n)
+{
+    uint8_t crc = 0;
+    while (n--) {
+        crc ^= *p++;
+        for (int i = 0; i < 8; i++)
+            crc = (crc & 0x80) ? (uint8_t)((crc << 1) ^ 0x07) : (uint8_t)(crc << 1);
+    }
+    return crc;
+}
+
+/* CRC-32 (IEEE, reflected), same as crc32fast on the Linux side */
+uint32_t calc_crc32(const uint8_t *p, size_t n)
+{
+    uint32_t crc = 0xFFFFFFFFu;
+    while (n--) {
+        crc ^= *p++;
+        for (int i = 0; i < 8; i++)
+            crc = (crc & 1u) ? (crc >> 1) ^ 0xEDB88320u : crc >> 1;
+    }
+    return ~crc;
+}
+
+/* CRC-32C (Castagnoli, reflected) */
+__attribute__((weak)) uint32_t calc_crc32c(const uint8_t *p, size_t n)
+{
+    uint32_t crc = 0xFFFFFFFFu;
+    while (n--) {
+        crc ^= *p++;
+        for (int i = 0; i < 8; i++)
+            crc = (crc & 1u) ? (crc >> 1) ^ 0x82F63B78u : crc >> 1;
+    }
+    return ~crc;
+}
+
+/* CRC-16/CCITT-FALSE, poly 0x1021, init 0xFFFF */
+uint16_t calc_crc16_ccitt(const uint8_t *p, size_t n)
+{
+    uint16_t crc = 0xFFFF;
+    while (n--) {
+        crc ^= (uint16_t)(*p++ << 8);
+        for (int i = 0; i < 8; i++)
+            crc = (crc & 0x8000) ? (uint16_t)((crc << 1) ^ 0x1021) : (uint16_t)(crc << 1);
+    }
+    return crc;
+}
+
+size_t calc_csum_len(uint8_t flags)
+{
+    switch (flags & CALC_CSUM_MASK) {
+    case CALC_CSUM_CRC16: return 2;
+    case CALC_CSUM_NONE:  return 0;
+    default:              return 4;
+    }
+}
+
+static uint32_t csum(uint8_t flags, const uint8_t *p, size_t n)
+{
+    switch (flags & CALC_CSUM_MASK) {
+    case CALC_CSUM_CRC32:  return calc_crc32(p, n);
+    case CALC_CSUM_CRC32C: return calc_crc32c(p, n);
+    case CALC_CSUM_CRC16:  return calc_crc16_ccitt(p, n);
+    default:               return 0;
+    }
+}
+
+bool calc_csum_ok(uint8_t flags, const uint8_t *p, size_t n)
+{
+    uint32_t want = csum(flags, p, n);
+    const uint8_t *c = p + n;
+    for (size_t i = 0; i < calc_csum_len(flags); i++)
+        if (c[i] != (uint8_t)(want >> (8 * i))) return false;
+    return true;
+}
+
+size_t calc_seal_frame(uint8_t *out, uint8_t typ, uint8_t flags,
+                       uint16_t seq, uint16_t len)
+{
+    out[0] = SYNC_HI;
+    out[1] = SYNC_LO;
+    out[2] = VER;
+    out[3] = typ;
+    out[4] = flags;
+    out[5] = (uint8_t)(len >> 8);
+    out[6] = (uint8_t)len;
+    out[7] = (uint8_t)(seq >> 8);
+    out[8] = (uint8_t)seq;
+    out[9] = calc_crc8(out, HDR_LEN - 1);
+
+    uint32_t crc = csum(flags, out + HDR_LEN, len);
+    uint8_t *c = out + HDR_LEN + len;
+    size_t n = calc_csum_len(flags);
+    for (size_t i = 0; i < n; i++)
+        c[i] = (uint8_t)(crc >> (8 * i));
+    return (size_t)HDR_LEN + len + n;
+}
 
 bool calc_handle_frame(const uint8_t *f, size_t flen,
                        uint8_t *out, size_t out_cap, size_t *out_len)
 {
-    if (flen < 10) return false;
+    return calc_handle_frame_at(f, flen, calc_now_ns(), out, out_cap, out_len);
+}
+
+bool calc_handle_frame_at(const uint8_t *f, size_t flen, uint64_t rx_ns,
+                          uint8_t *out, size_t out_cap, size_t *out_len)
+{
+    if (flen < HDR_LEN) return false;
     if (f[0] != SYNC_HI || f[1] != SYNC_LO) return false;
-    /* uint8_t ver = f[2]; */
+    if (f[2] != VER) return false;
+    if (calc_crc8(f, HDR_LEN - 1) != f[9]) return false;
+
     uint8_t typ = f[3];
-    uint16_t len = (uint16_t)f[4] << 8 | (uint16_t)f[5];
-    if (typ != TYPE_CALC_REQ) return false;
-    if (10u + len > flen) return false;
+    uint8_t csum_flags = f[4] & CALC_CSUM_MASK;
+    uint16_t len = (uint16_t)f[5] << 8 | (uint16_t)f[6];
+    uint16_t seq = (uint16_t)f[7] << 8 | (uint16_t)f[8];
+    if (typ != TYPE_CALC_REQ && typ != TYPE_TIME_REQ) return false;
+    if ((size_t)HDR_LEN + len + calc_csum_len(csum_flags) > flen) return false;
+
+    if (out_cap < HDR_LEN + CRC_LEN) return false;
+    uint8_t *body = out + HDR_LEN;
+    size_t body_cap = out_cap - HDR_LEN - CRC_LEN;
+    size_t resp_len = 0;
+    uint8_t resp_typ;
+
+    /* The header CRC vouches for seq, so a bad payload still gets a reply */
+    const uint8_t *payload = f + HDR_LEN;
+    if (!calc_csum_ok(csum_flags, payload, len)) {
+        if (!calc_encode_error(rpmsg_calc_v1_Status_STATUS_DECODE_ERROR,
+                               "payload crc", NULL, body, body_cap, &resp_len))
+            return false;
+        resp_typ = CALC_TYPE_ERR;
+    } else if (typ == TYPE_TIME_REQ) {
+        resp_typ = calc_handle_time_sync(payload, len, rx_ns, body, body_cap, &resp_len);
+        if (!resp_typ) return false;
+    } else {
+        resp_typ = calc_handle_request(payload, len, rx_ns, body, body_cap, &resp_len);
+        if (!resp_typ) return false;
+    }
 
-    const uint8_t *payload = f + 10;
-    return calc_handle_request(payload, len, out, out_cap, out_len);
+    *out_len = calc_seal_frame(out, resp_typ, csum_flags, seq, (uint16_t)resp_len);
+    return true;
 }
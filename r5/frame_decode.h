@@ -3,5 +3,44 @@
 #include <stdint.h>
 #include <stdbool.h>
 
+#define CALC_FRAME_HDR_LEN 10
+#define CALC_FRAME_CRC_LEN 4 /* longest payload checksum */
+
+/* Payload checksum, selected by flags bits 0x30 (match checksum::ChecksumKind
+ * on Linux). A reply carries the checksum of its request. */
+#define CALC_CSUM_MASK   0x30
+#define CALC_CSUM_CRC32  0x00
+#define CALC_CSUM_CRC32C 0x10
+#define CALC_CSUM_CRC16  0x20
+#define CALC_CSUM_NONE   0x30
+
+/* v2 frame: [SYNC 0xA5 0x5A][ver=2][type][flags][len BE16][seq BE16][crc8]
+ *           [payload(len)][checksum(payload) LE, 4, 2 or 0 bytes]
+ * On success `out` holds a complete v2 response frame echoing the request seq
+ * and checksum. */
 bool calc_handle_frame(const uint8_t *frame, size_t frame_len,
                        uint8_t *out, size_t out_cap, size_t *out_len);
+
+/* As calc_handle_frame, for a frame that arrived at calc_now_ns() == rx_ns
+ * and was queued before being handled. */
+bool calc_handle_frame_at(const uint8_t *frame, size_t frame_len, uint64_t rx_ns,
+                          uint8_t *out, size_t out_cap, size_t *out_len);
+
+/* CRC-8 (poly 0x07, header check) and the payload checksums: CRC-32 (IEEE),
+ * CRC-32C (Castagnoli) and CRC-16/CCITT-FALSE. calc_crc32c is weak, so a
+ * target with a CRC unit can replace the bitwise one. */
+uint8_t calc_crc8(const uint8_t *p, size_t n);
+uint32_t calc_crc32(const uint8_t *p, size_t n);
+uint32_t calc_crc32c(const uint8_t *p, size_t n);
+uint16_t calc_crc16_ccitt(const uint8_t *p, size_t n);
+
+/* Trailer length of the checksum `flags` selects. */
+size_t calc_csum_len(uint8_t flags);
+
+/* Whether the trailer after the `n` bytes at `p` matches them. */
+bool calc_csum_ok(uint8_t flags, const uint8_t *p, size_t n);
+
+/* Write the header and the checksum `flags` selects around the `len` payload
+ * bytes already at out + CALC_FRAME_HDR_LEN. Returns the frame length. */
+size_t calc_seal_frame(uint8_t *out, uint8_t typ, uint8_t flags,
+                       uint16_t seq, uint16_t len);
@@ -6,7 +6,7 @@
 #error Regenerate this file with the current version of nanopb generator.
 #endif
 
-PB_BIND(rpmsg_calc_v1_TraceHeader, rpmsg_calc_v1_TraceHeader, AUTO)
+PB_BIND(rpmsg_calc_v1_TraceCtx, rpmsg_calc_v1_TraceCtx, AUTO)
 
 
 PB_BIND(rpmsg_calc_v1_CalcRequest, rpmsg_calc_v1_CalcRequest, AUTO)
@@ -15,4 +15,14 @@ PB_BIND(rpmsg_calc_v1_CalcRequest, rpmsg_calc_v1_CalcRequest, AUTO)
 PB_BIND(rpmsg_calc_v1_CalcResponse, rpmsg_calc_v1_CalcResponse, AUTO)
 
 
+PB_BIND(rpmsg_calc_v1_CalcError, rpmsg_calc_v1_CalcError, AUTO)
+
+
+PB_BIND(rpmsg_calc_v1_TimeSyncRequest, rpmsg_calc_v1_TimeSyncRequest, AUTO)
+
+
+PB_BIND(rpmsg_calc_v1_TimeSyncResponse, rpmsg_calc_v1_TimeSyncResponse, AUTO)
+
+
+
 
@@ -9,85 +9,204 @@
 #error Regenerate this file with the current version of nanopb generator.
 #endif
 
+/* Enum definitions */
+/* u32 arithmetic is checked on both sides: overflow/underflow -> STATUS_OVERFLOW,
+ b == 0 for OP_DIV -> STATUS_DIV_BY_ZERO, OP_DIV truncates toward zero. */
+typedef enum _rpmsg_calc_v1_Op {
+    rpmsg_calc_v1_Op_OP_SUM = 0,
+    rpmsg_calc_v1_Op_OP_SUB = 1,
+    rpmsg_calc_v1_Op_OP_MUL = 2,
+    rpmsg_calc_v1_Op_OP_DIV = 3
+} rpmsg_calc_v1_Op;
+
+/* R5 status codes carried in CalcError (frame type 3) */
+typedef enum _rpmsg_calc_v1_Status {
+    rpmsg_calc_v1_Status_STATUS_OK = 0,
+    rpmsg_calc_v1_Status_STATUS_DECODE_ERROR = 1,
+    rpmsg_calc_v1_Status_STATUS_UNSUPPORTED_OP = 2,
+    rpmsg_calc_v1_Status_STATUS_DIV_BY_ZERO = 3,
+    rpmsg_calc_v1_Status_STATUS_OVERFLOW = 4
+} rpmsg_calc_v1_Status;
+
 /* Struct definitions */
-typedef struct _rpmsg_calc_v1_TraceHeader {
-    pb_callback_t id; /* 16 bytes trace_id */
-    uint64_t ts_ns; /* timestamp (ns) */
-    pb_callback_t span_id; /* 8 bytes span_id */
-} rpmsg_calc_v1_TraceHeader;
+typedef PB_BYTES_ARRAY_T(16) rpmsg_calc_v1_TraceCtx_trace_id_t;
+typedef PB_BYTES_ARRAY_T(8) rpmsg_calc_v1_TraceCtx_span_id_t;
+/* W3C trace context: 16-byte trace_id, 8-byte span_id, trace-flags (bit 0 =
+ sampled) and the tracestate header, cut to whole list-members that fit. */
+typedef struct _rpmsg_calc_v1_TraceCtx {
+    rpmsg_calc_v1_TraceCtx_trace_id_t trace_id;
+    rpmsg_calc_v1_TraceCtx_span_id_t span_id;
+    uint32_t flags;
+    char tracestate[64];
+} rpmsg_calc_v1_TraceCtx;
 
 typedef struct _rpmsg_calc_v1_CalcRequest {
+    rpmsg_calc_v1_Op op;
     uint32_t a;
     uint32_t b;
     bool has_trace;
-    rpmsg_calc_v1_TraceHeader trace;
+    rpmsg_calc_v1_TraceCtx trace;
 } rpmsg_calc_v1_CalcRequest;
 
+/* r5_*_ns are R5 clock readings (any epoch, 0 = no clock): request received,
+ reply sent, and time spent computing in between. */
 typedef struct _rpmsg_calc_v1_CalcResponse {
-    uint32_t sum;
+    uint32_t result;
+    uint64_t r5_rx_ns;
+    uint64_t r5_tx_ns;
+    uint64_t r5_compute_ns;
     bool has_trace;
-    rpmsg_calc_v1_TraceHeader trace;
+    rpmsg_calc_v1_TraceCtx trace;
 } rpmsg_calc_v1_CalcResponse;
 
+typedef struct _rpmsg_calc_v1_CalcError {
+    rpmsg_calc_v1_Status code;
+    char detail[64];
+    bool has_trace;
+    rpmsg_calc_v1_TraceCtx trace;
+} rpmsg_calc_v1_CalcError;
+
+/* NTP-style clock sync (frame types 4 and 5). Linux sends t1 from
+ CLOCK_MONOTONIC; the R5 echoes it with t2 (request received) and t3 (reply
+ sent) from its own clock, 0 if it has none. */
+typedef struct _rpmsg_calc_v1_TimeSyncRequest {
+    uint64_t t1_ns;
+} rpmsg_calc_v1_TimeSyncRequest;
+
+typedef struct _rpmsg_calc_v1_TimeSyncResponse {
+    uint64_t t1_ns;
+    uint64_t t2_ns;
+    uint64_t t3_ns;
+} rpmsg_calc_v1_TimeSyncResponse;
+
 
 #ifdef __cplusplus
 extern "C" {
 #endif
 
+/* Helper constants for enums */
+#define _rpmsg_calc_v1_Op_MIN rpmsg_calc_v1_Op_OP_SUM
+#define _rpmsg_calc_v1_Op_MAX rpmsg_calc_v1_Op_OP_DIV
+#define _rpmsg_calc_v1_Op_ARRAYSIZE ((rpmsg_calc_v1_Op)(rpmsg_calc_v1_Op_OP_DIV+1))
+
+#define _rpmsg_calc_v1_Status_MIN rpmsg_calc_v1_Status_STATUS_OK
+#define _rpmsg_calc_v1_Status_MAX rpmsg_calc_v1_Status_STATUS_OVERFLOW
+#define _rpmsg_calc_v1_Status_ARRAYSIZE ((rpmsg_calc_v1_Status)(rpmsg_calc_v1_Status_STATUS_OVERFLOW+1))
+
+
+#define rpmsg_calc_v1_CalcRequest_op_ENUMTYPE rpmsg_calc_v1_Op
+
+
+#define rpmsg_calc_v1_CalcError_code_ENUMTYPE rpmsg_calc_v1_Status
+
+
 /* Initializer values for message structs */
-#define rpmsg_calc_v1_TraceHeader_init_default   {{{NULL}, NULL}, 0, {{NULL}, NULL}}
-#define rpmsg_calc_v1_CalcRequest_init_default   {0, 0, false, rpmsg_calc_v1_TraceHeader_init_default}
-#define rpmsg_calc_v1_CalcResponse_init_default  {0, false, rpmsg_calc_v1_TraceHeader_init_default}
-#define rpmsg_calc_v1_TraceHeader_init_zero      {{{NULL}, NULL}, 0, {{NULL}, NULL}}
-#define rpmsg_calc_v1_CalcRequest_init_zero      {0, 0, false, rpmsg_calc_v1_TraceHeader_init_zero}
-#define rpmsg_calc_v1_CalcResponse_init_zero     {0, false, rpmsg_calc_v1_TraceHeader_init_zero}
+#define rpmsg_calc_v1_TraceCtx_init_default      {{0, {0}}, {0, {0}}, 0, ""}
+#define rpmsg_calc_v1_CalcRequest_init_default   {_rpmsg_calc_v1_Op_MIN, 0, 0, false, rpmsg_calc_v1_TraceCtx_init_default}
+#define rpmsg_calc_v1_CalcResponse_init_default  {0, 0, 0, 0, false, rpmsg_calc_v1_TraceCtx_init_default}
+#define rpmsg_calc_v1_CalcError_init_default     {_rpmsg_calc_v1_Status_MIN, "", false, rpmsg_calc_v1_TraceCtx_init_default}
+#define rpmsg_calc_v1_TimeSyncRequest_init_default {0}
+#define rpmsg_calc_v1_TimeSyncResponse_init_default {0, 0, 0}
+#define rpmsg_calc_v1_TraceCtx_init_zero         {{0, {0}}, {0, {0}}, 0, ""}
+#define rpmsg_calc_v1_CalcRequest_init_zero      {_rpmsg_calc_v1_Op_MIN, 0, 0, false, rpmsg_calc_v1_TraceCtx_init_zero}
+#define rpmsg_calc_v1_CalcResponse_init_zero     {0, 0, 0, 0, false, rpmsg_calc_v1_TraceCtx_init_zero}
+#define rpmsg_calc_v1_CalcError_init_zero        {_rpmsg_calc_v1_Status_MIN, "", false, rpmsg_calc_v1_TraceCtx_init_zero}
+#define rpmsg_calc_v1_TimeSyncRequest_init_zero  {0}
+#define rpmsg_calc_v1_TimeSyncResponse_init_zero {0, 0, 0}
 
 /* Field tags (for use in manual encoding/decoding) */
-#define rpmsg_calc_v1_TraceHeader_id_tag         1
-#define rpmsg_calc_v1_TraceHeader_ts_ns_tag      2
-#define rpmsg_calc_v1_TraceHeader_span_id_tag    3
-#define rpmsg_calc_v1_CalcRequest_a_tag          1
-#define rpmsg_calc_v1_CalcRequest_b_tag          2
-#define rpmsg_calc_v1_CalcRequest_trace_tag      3
-#define rpmsg_calc_v1_CalcResponse_sum_tag       1
-#define rpmsg_calc_v1_CalcResponse_trace_tag     2
+#define rpmsg_calc_v1_TraceCtx_trace_id_tag      1
+#define rpmsg_calc_v1_TraceCtx_span_id_tag       2
+#define rpmsg_calc_v1_TraceCtx_flags_tag         3
+#define rpmsg_calc_v1_TraceCtx_tracestate_tag    4
+#define rpmsg_calc_v1_CalcRequest_op_tag         1
+#define rpmsg_calc_v1_CalcRequest_a_tag          2
+#define rpmsg_calc_v1_CalcRequest_b_tag          3
+#define rpmsg_calc_v1_CalcRequest_trace_tag      100
+#define rpmsg_calc_v1_CalcResponse_result_tag    1
+#define rpmsg_calc_v1_CalcResponse_r5_rx_ns_tag  2
+#define rpmsg_calc_v1_CalcResponse_r5_tx_ns_tag  3
+#define rpmsg_calc_v1_CalcResponse_r5_compute_ns_tag 4
+#define rpmsg_calc_v1_CalcResponse_trace_tag     100
+#define rpmsg_calc_v1_CalcError_code_tag         1
+#define rpmsg_calc_v1_CalcError_detail_tag       2
+#define rpmsg_calc_v1_CalcError_trace_tag        100
+#define rpmsg_calc_v1_TimeSyncRequest_t1_ns_tag  1
+#define rpmsg_calc_v1_TimeSyncResponse_t1_ns_tag 1
+#define rpmsg_calc_v1_TimeSyncResponse_t2_ns_tag 2
+#define rpmsg_calc_v1_TimeSyncResponse_t3_ns_tag 3
 
 /* Struct field encoding specification for nanopb */
-#define rpmsg_calc_v1_TraceHeader_FIELDLIST(X, a) \
-X(a, CALLBACK, SINGULAR, BYTES,    id,                1) \
-X(a, STATIC,   SINGULAR, UINT64,   ts_ns,             2) \
-X(a, CALLBACK, SINGULAR, BYTES,    span_id,           3)
-#define rpmsg_calc_v1_TraceHeader_CALLBACK pb_default_field_callback
-#define rpmsg_calc_v1_TraceHeader_DEFAULT NULL
+#define rpmsg_calc_v1_TraceCtx_FIELDLIST(X, a) \
+X(a, STATIC,   SINGULAR, BYTES,    trace_id,          1) \
+X(a, STATIC,   SINGULAR, BYTES,    span_id,           2) \
+X(a, STATIC,   SINGULAR, UINT32,   flags,             3) \
+X(a, STATIC,   SINGULAR, STRING,   tracestate,        4)
+#define rpmsg_calc_v1_TraceCtx_CALLBACK NULL
+#define rpmsg_calc_v1_TraceCtx_DEFAULT NULL
 
 #define rpmsg_calc_v1_CalcRequest_FIELDLIST(X, a_) \
-X(a_, STATIC,   SINGULAR, UINT32,   a,                 1) \
-X(a_, STATIC,   SINGULAR, UINT32,   b,                 2) \
-X(a_, STATIC,   OPTIONAL, MESSAGE,  trace,             3)
+X(a_, STATIC,   SINGULAR, UENUM,    op,                1) \
+X(a_, STATIC,   SINGULAR, UINT32,   a,                 2) \
+X(a_, STATIC,   SINGULAR, UINT32,   b,                 3) \
+X(a_, STATIC,   OPTIONAL, MESSAGE,  trace,           100)
 #define rpmsg_calc_v1_CalcRequest_CALLBACK NULL
 #define rpmsg_calc_v1_CalcRequest_DEFAULT NULL
-#define rpmsg_calc_v1_CalcRequest_trace_MSGTYPE rpmsg_calc_v1_TraceHeader
+#define rpmsg_calc_v1_CalcRequest_trace_MSGTYPE rpmsg_calc_v1_TraceCtx
 
 #define rpmsg_calc_v1_CalcResponse_FIELDLIST(X, a) \
-X(a, STATIC,   SINGULAR, UINT32,   sum,               1) \
-X(a, STATIC,   OPTIONAL, MESSAGE,  trace,             2)
+X(a, STATIC,   SINGULAR, UINT32,   result,            1) \
+X(a, STATIC,   SINGULAR, UINT64,   r5_rx_ns,          2) \
+X(a, STATIC,   SINGULAR, UINT64,   r5_tx_ns,          3) \
+X(a, STATIC,   SINGULAR, UINT64,   r5_compute_ns,     4) \
+X(a, STATIC,   OPTIONAL, MESSAGE,  trace,           100)
 #define rpmsg_calc_v1_CalcResponse_CALLBACK NULL
 #define rpmsg_calc_v1_CalcResponse_DEFAULT NULL
-#define rpmsg_calc_v1_CalcResponse_trace_MSGTYPE rpmsg_calc_v1_TraceHeader
-
-extern const pb_msgdesc_t rpmsg_calc_v1_TraceHeader_msg;
+#define rpmsg_calc_v1_CalcResponse_trace_MSGTYPE rpmsg_calc_v1_TraceCtx
+
+#define rpmsg_calc_v1_CalcError_FIELDLIST(X, a) \
+X(a, STATIC,   SINGULAR, UENUM,    code,              1) \
+X(a, STATIC,   SINGULAR, STRING,   detail,            2) \
+X(a, STATIC,   OPTIONAL, MESSAGE,  trace,           100)
+#define rpmsg_calc_v1_CalcError_CALLBACK NULL
+#define rpmsg_calc_v1_CalcError_DEFAULT NULL
+#define rpmsg_calc_v1_CalcError_trace_MSGTYPE rpmsg_calc_v1_TraceCtx
+
+#define rpmsg_calc_v1_TimeSyncRequest_FIELDLIST(X, a) \
+X(a, STATIC,   SINGULAR, UINT64,   t1_ns,             1)
+#define rpmsg_calc_v1_TimeSyncRequest_CALLBACK NULL
+#define rpmsg_calc_v1_TimeSyncRequest_DEFAULT NULL
+
+#define rpmsg_calc_v1_TimeSyncResponse_FIELDLIST(X, a) \
+X(a, STATIC,   SINGULAR, UINT64,   t1_ns,             1) \
+X(a, STATIC,   SINGULAR, UINT64,   t2_ns,             2) \
+X(a, STATIC,   SINGULAR, UINT64,   t3_ns,             3)
+#define rpmsg_calc_v1_TimeSyncResponse_CALLBACK NULL
+#define rpmsg_calc_v1_TimeSyncResponse_DEFAULT NULL
+
+extern const pb_msgdesc_t rpmsg_calc_v1_TraceCtx_msg;
 extern const pb_msgdesc_t rpmsg_calc_v1_CalcRequest_msg;
 extern const pb_msgdesc_t rpmsg_calc_v1_CalcResponse_msg;
+extern const pb_msgdesc_t rpmsg_calc_v1_CalcError_msg;
+extern const pb_msgdesc_t rpmsg_calc_v1_TimeSyncRequest_msg;
+extern const pb_msgdesc_t rpmsg_calc_v1_TimeSyncResponse_msg;
 
 /* Defines for backwards compatibility with code written before nanopb-0.4.0 */
-#define rpmsg_calc_v1_TraceHeader_fields &rpmsg_calc_v1_TraceHeader_msg
+#define rpmsg_calc_v1_TraceCtx_fields &rpmsg_calc_v1_TraceCtx_msg
 #define rpmsg_calc_v1_CalcRequest_fields &rpmsg_calc_v1_CalcRequest_msg
 #define rpmsg_calc_v1_CalcResponse_fields &rpmsg_calc_v1_CalcResponse_msg
+#define rpmsg_calc_v1_CalcError_fields &rpmsg_calc_v1_CalcError_msg
+#define rpmsg_calc_v1_TimeSyncRequest_fields &rpmsg_calc_v1_TimeSyncRequest_msg
+#define rpmsg_calc_v1_TimeSyncResponse_fields &rpmsg_calc_v1_TimeSyncResponse_msg
 
 /* Maximum encoded size of messages (where known) */
-/* rpmsg_calc_v1_TraceHeader_size depends on runtime parameters */
-/* rpmsg_calc_v1_CalcRequest_size depends on runtime parameters */
-/* rpmsg_calc_v1_CalcResponse_size depends on runtime parameters */
+#define RPMSG_CALC_V1_CALC_PB_H_MAX_SIZE         rpmsg_calc_v1_CalcError_size
+#define rpmsg_calc_v1_CalcError_size             169
+#define rpmsg_calc_v1_CalcRequest_size           116
+#define rpmsg_calc_v1_CalcResponse_size          141
+#define rpmsg_calc_v1_TimeSyncRequest_size       11
+#define rpmsg_calc_v1_TimeSyncResponse_size      33
+#define rpmsg_calc_v1_TraceCtx_size              99
 
 #ifdef __cplusplus
 } /* extern "C" */
@@ -0,0 +1,168 @@
+/* Host driver for the R5 frame path, for tests on Linux (tests/r5_c.rs).
+ *
+ * Reads one received frame per line from stdin, in hex, optionally after
+ * "@<ns> " to set the clock the reassembler sees:
+ *
+ *     @150000000 A55A0201010...
+ *
+ * and answers each with the reassembler's verdict and the reply in hex, or
+ * "-" when the R5 stays silent:
+ *
+ *     MORE -
+ *     DONE A55A02020000020009F4082A2151BB52
+ *
+ * With "--key KEY" frames go through calc_auth_open after reassembly, and
+ * replies are sealed with the request's counter, as on the R5.
+ *
+ * Other modes answer one line each:
+ *
+ *     host-frames crc         HEX            -> CRC-32 CRC-32C CRC-16
+ *     host-frames hmac        KEY MSG        -> HMAC-SHA256
+ *     host-frames seal KEY    COUNTER FRAME  -> sealed frame, or "-"
+ *     host-frames open KEY    FRAME          -> COUNTER FRAME, or "-"
+ *
+ * "open" keeps one replay window across its lines.
+ */
+#include <ctype.h>
+#include <inttypes.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "r5/frag_reasm.h"
+#include "r5/frame_auth.h"
+#include "r5/frame_decode.h"
+
+#define FRAME_MAX 4096
+
+static const char *const verdicts[] = {"PASS", "MORE", "DONE", "DROP"};
+
+static size_t unhex(const char *s, uint8_t *out, size_t cap)
+{
+    size_t n = 0;
+    while (n < cap && isxdigit((unsigned char)s[0]) && isxdigit((unsigned char)s[1]) &&
+           sscanf(s, "%2hhx", &out[n]) == 1) {
+        n++;
+        s += 2;
+    }
+    return n;
+}
+
+static void put_hex(const uint8_t *p, size_t n)
+{
+    for (size_t i = 0; i < n; i++) printf("%02X", p[i]);
+}
+
+static char line[2 * FRAME_MAX + 64];
+static uint8_t in[FRAME_MAX], out[FRAME_MAX], plain[FRAME_MAX];
+static uint8_t key[FRAME_MAX];
+
+/* The text after the first space in `p`, or "" */
+static char *next_word(char *p)
+{
+    char *sp = strchr(p, ' ');
+    return sp ? sp + 1 : p + strlen(p);
+}
+
+static int crcs(void)
+{
+    while (fgets(line, sizeof line, stdin)) {
+        size_t n = unhex(line, in, sizeof in);
+        printf("%08" PRIX32 " %08" PRIX32 " %04X\n", calc_crc32(in, n),
+               calc_crc32c(in, n), (unsigned)calc_crc16_ccitt(in, n));
+    }
+    return 0;
+}
+
+static int hmacs(void)
+{
+    uint8_t mac[32];
+    while (fgets(line, sizeof line, stdin)) {
+        size_t key_len = unhex(line, key, sizeof key);
+        size_t n = unhex(next_word(line), in, sizeof in);
+        calc_hmac_sha256(key, key_len, in, n, mac);
+        put_hex(mac, sizeof mac);
+        putchar('\n');
+    }
+    return 0;
+}
+
+static int seals(const calc_auth_t *auth)
+{
+    while (fgets(line, sizeof line, stdin)) {
+        char *p = line;
+        uint64_t counter = strtoull(p, &p, 10);
+        size_t n = unhex(next_word(p), in, sizeof in);
+        n = calc_auth_seal(auth, in, n, sizeof in, counter);
+        if (n) put_hex(in, n);
+        else putchar('-');
+        putchar('\n');
+    }
+    return 0;
+}
+
+static int opens(calc_auth_t *auth)
+{
+    while (fgets(line, sizeof line, stdin)) {
+        size_t n = unhex(line, in, sizeof in), plain_len;
+        uint64_t counter;
+        if (calc_auth_open(auth, in, n, plain, sizeof plain, &plain_len, &counter)) {
+            printf("%" PRIu64 " ", counter);
+            put_hex(plain, plain_len);
+        } else {
+            putchar('-');
+        }
+        putchar('\n');
+    }
+    return 0;
+}
+
+int main(int argc, char **argv)
+{
+    static calc_reasm_t reasm;
+    static calc_auth_t auth;
+    bool authenticate = false;
+    uint64_t now_ns = 0;
+    if (argc > 1 && strcmp(argv[1], "crc") == 0) return crcs();
+    if (argc > 1 && strcmp(argv[1], "hmac") == 0) return hmacs();
+    if (argc > 2) {
+        calc_auth_init(&auth, key, unhex(argv[2], key, sizeof key));
+        if (strcmp(argv[1], "seal") == 0) return seals(&auth);
+        if (strcmp(argv[1], "open") == 0) return opens(&auth);
+        authenticate = strcmp(argv[1], "--key") == 0;
+        if (!authenticate) {
+            fprintf(stderr, "usage: host-frames [crc|hmac|seal KEY|open KEY|--key KEY]\n");
+            return 2;
+        }
+    }
+    calc_reasm_init(&reasm);
+
+    while (fgets(line, sizeof line, stdin)) {
+        char *p = line;
+        if (*p == '@') {
+            now_ns = strtoull(p + 1, &p, 10);
+            while (*p == ' ') p++;
+        }
+        size_t n = unhex(p, in, sizeof in);
+
+        const uint8_t *msg;
+        size_t msg_len, out_len;
+        uint64_t counter = 0;
+        calc_reasm_result res = calc_reasm_push(&reasm, in, n, now_ns, &msg, &msg_len);
+        printf("%s ", verdicts[res]);
+        bool whole = res == CALC_REASM_PASS || res == CALC_REASM_DONE;
+        if (whole && authenticate) {
+            whole = calc_auth_open(&auth, msg, msg_len, plain, sizeof plain, &msg_len, &counter);
+            msg = plain;
+        }
+        if (whole && calc_handle_frame(msg, msg_len, out, sizeof out, &out_len) &&
+            (!authenticate || (out_len = calc_auth_seal(&auth, out, out_len, sizeof out, counter)))) {
+            put_hex(out, out_len);
+        } else {
+            putchar('-');
+        }
+        putchar('\n');
+        fflush(stdout);
+    }
+    return 0;
+}
@@ -2,7 +2,7 @@
 #include <string.h>
 #include "calc.pb.h"
 
-typedef rpmsg_calc_v1_TraceHeader TraceHdr;
+typedef rpmsg_calc_v1_TraceCtx TraceHdr;
 
 /* Echo whatever trace the decoder produced */
 static inline void trace_copy(TraceHdr *dst, const TraceHdr *src) { *dst = *src; }
@@ -0,0 +1,199 @@
+//! Authenticated v2 frames, for links where other processes can reach the
+//! rpmsg device.
+//!
+//! A CRC only catches accidents. With a pre-shared [`AuthKey`],
+//! [`wire::seal_auth`] sets `flags` bit 0x80 and appends a counter and an
+//! HMAC-SHA256 tag, truncated to 16 bytes, to the payload:
+//!
+//! ```text
+//! [header][message][counter(8, BE)][tag(16)][checksum]
+//! ```
+//!
+//! The tag covers the header, message and counter. The header length covers
+//! the whole block, so fragmentation and the stream decoder need no changes.
+//! Each sender counts up, and each receiver keeps a [`ReplayWindow`] of the
+//! counters it has accepted. The R5 answers with the counter of the request.
+//! [`AuthTransport`] does all of this for a link, and drops every frame that
+//! fails with [`FrameError::Auth`].
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::metrics::count_decode_error;
+use crate::transport::Transport;
+use crate::wire::{self, AUTH_TAG_LEN};
+use crate::FrameError;
+
+/// Shortest key accepted: 128 bits.
+pub const MIN_KEY_LEN: usize = 16;
+
+/// How far below the highest accepted counter a late frame may be.
+pub const REPLAY_WINDOW: u64 = 64;
+
+/// Pre-shared HMAC-SHA256 key. `Debug` does not show it.
+#[derive(Clone, PartialEq, Eq)]
+pub struct AuthKey(Vec<u8>);
+
+impl fmt::Debug for AuthKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AuthKey(..)")
+    }
+}
+
+impl AuthKey {
+    /// `None` if `key` is shorter than [`MIN_KEY_LEN`].
+    pub fn new(key: &[u8]) -> Option<Self> {
+        (key.len() >= MIN_KEY_LEN).then(|| Self(key.to_vec()))
+    }
+
+    pub fn from_hex(s: &str) -> Option<Self> {
+        Self::new(&hex::decode(s.trim()).ok()?)
+    }
+
+    /// Read a key file: the key in hex, surrounding whitespace ignored.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        Self::from_hex(&text).ok_or_else(|| {
+            let why = format!("expected at least {MIN_KEY_LEN} bytes in hex");
+            io::Error::new(io::ErrorKind::InvalidData, why)
+        })
+    }
+
+    fn mac(&self, parts: &[&[u8]]) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.0).expect("HMAC takes any key length");
+        for part in parts {
+            mac.update(part);
+        }
+        mac
+    }
+
+    /// HMAC-SHA256 of `parts` back to back, truncated to [`AUTH_TAG_LEN`].
+    pub fn tag(&self, parts: &[&[u8]]) -> [u8; AUTH_TAG_LEN] {
+        let full = self.mac(parts).finalize().into_bytes();
+        full[..AUTH_TAG_LEN].try_into().unwrap()
+    }
+
+    /// Whether `tag` is [`tag`](Self::tag) of `parts`, compared in constant
+    /// time.
+    pub fn verify(&self, parts: &[&[u8]], tag: &[u8]) -> bool {
+        tag.len() == AUTH_TAG_LEN && self.mac(parts).verify_truncated_left(tag).is_ok()
+    }
+}
+
+/// Counters accepted so far: the highest, and which of the
+/// [`REPLAY_WINDOW`] below it. Counter 0 is never accepted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplayWindow {
+    highest: u64,
+    /// Bit `i` set: `highest - i` was accepted.
+    seen: u64,
+}
+
+impl ReplayWindow {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn highest(&self) -> u64 {
+        self.highest
+    }
+
+    /// Accept `counter` if it has not been seen and is not too old. Only
+    /// call this for frames whose tag checked out.
+    pub fn check(&mut self, counter: u64) -> Result<(), FrameError> {
+        if counter > self.highest {
+            let shift = counter - self.highest;
+            self.seen = if shift >= REPLAY_WINDOW {
+                0
+            } else {
+                self.seen << shift
+            };
+            self.seen |= 1;
+            self.highest = counter;
+            return Ok(());
+        }
+        let age = self.highest - counter;
+        if counter == 0 || age >= REPLAY_WINDOW {
+            return Err(FrameError::Auth("counter below replay window"));
+        }
+        if self.seen & 1 << age != 0 {
+            return Err(FrameError::Auth("replayed counter"));
+        }
+        self.seen |= 1 << age;
+        Ok(())
+    }
+}
+
+/// Authenticates every frame sent and drops every frame received that is
+/// not authenticated with the same key, or that replays a counter. A frame
+/// that cannot be sealed, such as a v1 frame, is refused with
+/// [`io::ErrorKind::InvalidInput`] rather than sent in the clear.
+///
+/// Counters start at the current Unix time in microseconds, so a restarted
+/// gateway stays above the R5's window as long as the wall clock does not
+/// go backwards. Put it inside a
+/// [`ChecksumTransport`](crate::checksum::ChecksumTransport), so that the
+/// tag covers the checksum choice, and outside a
+/// [`FragmentingTransport`](crate::fragment::FragmentingTransport).
+pub struct AuthTransport<T> {
+    inner: T,
+    key: AuthKey,
+    next: AtomicU64,
+    window: Mutex<ReplayWindow>,
+}
+
+impl<T: Transport> AuthTransport<T> {
+    pub fn new(inner: T, key: AuthKey) -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_micros() as u64);
+        Self::with_counter(inner, key, now.max(1))
+    }
+
+    /// Start counting at `first` instead.
+    pub fn with_counter(inner: T, key: AuthKey, first: u64) -> Self {
+        Self {
+            inner,
+            key,
+            next: AtomicU64::new(first),
+            window: Mutex::new(ReplayWindow::new()),
+        }
+    }
+
+    fn open(&self, frame: &[u8]) -> Result<Vec<u8>, FrameError> {
+        let (plain, counter) = wire::open_auth(frame, &self.key)?;
+        self.window.lock().unwrap().check(counter)?;
+        Ok(plain)
+    }
+}
+
+#[async_trait]
+impl<T: Transport> Transport for AuthTransport<T> {
+    async fn send(&self, frame: &[u8]) -> io::Result<()> {
+        let counter = self.next.fetch_add(1, Ordering::Relaxed);
+        let frame = wire::seal_auth(frame, &self.key, counter)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        self.inner.send(&frame).await
+    }
+
+    async fn recv(&self) -> io::Result<Vec<u8>> {
+        loop {
+            let frame = self.inner.recv().await?;
+            match self.open(&frame) {
+                Ok(plain) => return Ok(plain),
+                Err(e) => {
+                    tracing::warn!(error = %e, "dropping unauthenticated frame");
+                    count_decode_error(&e);
+                }
+            }
+        }
+    }
+}
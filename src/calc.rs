@@ -0,0 +1,34 @@
+//! Reference evaluator for `CalcRequest`, matching `r5/calc_service.c`.
+//!
+//! Arithmetic is on `u32` and checked: a result that does not fit (including
+//! `a - b` with `b > a`) is [`Status::Overflow`], division by zero is
+//! [`Status::DivByZero`], and division truncates.
+
+use crate::proto::{CalcRequest, Op, Status};
+
+pub fn evaluate(op: Op, a: u32, b: u32) -> Result<u32, Status> {
+    match op {
+        Op::Sum => a.checked_add(b).ok_or(Status::Overflow),
+        Op::Sub => a.checked_sub(b).ok_or(Status::Overflow),
+        Op::Mul => a.checked_mul(b).ok_or(Status::Overflow),
+        Op::Div => a.checked_div(b).ok_or(Status::DivByZero),
+    }
+}
+
+/// Evaluate a decoded request; an `op` outside the enum is
+/// [`Status::UnsupportedOp`].
+pub fn evaluate_request(req: &CalcRequest) -> Result<u32, Status> {
+    let op = Op::try_from(req.op).map_err(|_| Status::UnsupportedOp)?;
+    evaluate(op, req.a, req.b)
+}
+
+/// Parse an op from the CLI/HTTP spelling (`sum`, `+`, `OP_SUM`, ...).
+pub fn parse_op(s: &str) -> Option<Op> {
+    match s.to_ascii_lowercase().as_str() {
+        "sum" | "add" | "+" => Some(Op::Sum),
+        "sub" | "-" => Some(Op::Sub),
+        "mul" | "x" | "*" => Some(Op::Mul),
+        "div" | "/" => Some(Op::Div),
+        _ => Op::from_str_name(s),
+    }
+}
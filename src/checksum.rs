@@ -0,0 +1,188 @@
+//! Payload checksums for v2 frames, chosen per frame by `flags` bits 0x30.
+//!
+//! | bits | checksum                          | trailer |
+//! |------|-----------------------------------|---------|
+//! | 0x00 | CRC-32 (IEEE), as in v1 frames    | 4 bytes |
+//! | 0x10 | CRC-32C (Castagnoli)              | 4 bytes |
+//! | 0x20 | CRC-16/CCITT-FALSE                | 2 bytes |
+//! | 0x30 | none; the header CRC still covers length and seq | 0 |
+//!
+//! The trailer is little-endian. v1 frames have no flags and always carry
+//! CRC-32. A reply uses the checksum of its request, so the sender picks it;
+//! [`ChecksumTransport`] does that for a whole link.
+
+use std::io;
+
+use async_trait::async_trait;
+
+use crate::metrics::count_decode_error;
+use crate::transport::Transport;
+use crate::wire::{self, FLAG_CSUM_MASK};
+use crate::FrameError;
+
+pub trait Checksum: Sync {
+    /// Trailer length in bytes.
+    fn width(&self) -> usize;
+
+    /// The checksum of `data`, in the low [`width`](Self::width) bytes.
+    fn compute(&self, data: &[u8]) -> u32;
+
+    /// The trailer to append after `data`.
+    fn trailer(&self, data: &[u8]) -> Vec<u8> {
+        self.compute(data).to_le_bytes()[..self.width()].to_vec()
+    }
+
+    /// Whether `trailer` matches `data`.
+    fn verify(&self, data: &[u8], trailer: &[u8]) -> bool {
+        trailer == &self.compute(data).to_le_bytes()[..self.width()]
+    }
+}
+
+pub struct Crc32;
+pub struct Crc32c;
+pub struct Crc16Ccitt;
+pub struct NoChecksum;
+
+const CASTAGNOLI: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISCSI);
+const CCITT_FALSE: crc::Crc<u16> = crc::Crc::<u16>::new(&crc::CRC_16_IBM_3740);
+
+impl Checksum for Crc32 {
+    fn width(&self) -> usize {
+        4
+    }
+    fn compute(&self, data: &[u8]) -> u32 {
+        wire::crc32(data)
+    }
+}
+
+impl Checksum for Crc32c {
+    fn width(&self) -> usize {
+        4
+    }
+    fn compute(&self, data: &[u8]) -> u32 {
+        CASTAGNOLI.checksum(data)
+    }
+}
+
+impl Checksum for Crc16Ccitt {
+    fn width(&self) -> usize {
+        2
+    }
+    fn compute(&self, data: &[u8]) -> u32 {
+        u32::from(CCITT_FALSE.checksum(data))
+    }
+}
+
+impl Checksum for NoChecksum {
+    fn width(&self) -> usize {
+        0
+    }
+    fn compute(&self, _: &[u8]) -> u32 {
+        0
+    }
+}
+
+/// The checksum a v2 frame carries, as selected by its flags.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChecksumKind {
+    #[default]
+    Crc32,
+    Crc32c,
+    Crc16Ccitt,
+    None,
+}
+
+impl ChecksumKind {
+    pub const ALL: [Self; 4] = [Self::Crc32, Self::Crc32c, Self::Crc16Ccitt, Self::None];
+
+    pub fn from_flags(flags: u8) -> Self {
+        match flags & FLAG_CSUM_MASK {
+            0x00 => Self::Crc32,
+            0x10 => Self::Crc32c,
+            0x20 => Self::Crc16Ccitt,
+            _ => Self::None,
+        }
+    }
+
+    /// `flags` bits selecting this checksum.
+    pub fn flag_bits(self) -> u8 {
+        match self {
+            Self::Crc32 => 0x00,
+            Self::Crc32c => 0x10,
+            Self::Crc16Ccitt => 0x20,
+            Self::None => 0x30,
+        }
+    }
+
+    pub fn checksum(self) -> &'static dyn Checksum {
+        match self {
+            Self::Crc32 => &Crc32,
+            Self::Crc32c => &Crc32c,
+            Self::Crc16Ccitt => &Crc16Ccitt,
+            Self::None => &NoChecksum,
+        }
+    }
+
+    /// Name used on the command line and in JSON.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Crc32 => "crc32",
+            Self::Crc32c => "crc32c",
+            Self::Crc16Ccitt => "crc16",
+            Self::None => "none",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|k| k.name() == name)
+    }
+}
+
+/// Sends every v2 frame with `kind`'s checksum and drops received v2 frames
+/// that carry another one, so a link cannot be talked down to a weaker check.
+/// v1 frames pass through both ways; a v2 frame that does not unwrap is
+/// refused with [`io::ErrorKind::InvalidInput`]. Put it outside a
+/// [`FragmentingTransport`](crate::fragment::FragmentingTransport), so that
+/// fragments are sized for the checksum they end up with.
+pub struct ChecksumTransport<T> {
+    inner: T,
+    kind: ChecksumKind,
+}
+
+impl<T: Transport> ChecksumTransport<T> {
+    pub fn new(inner: T, kind: ChecksumKind) -> Self {
+        Self { inner, kind }
+    }
+
+    pub fn kind(&self) -> ChecksumKind {
+        self.kind
+    }
+}
+
+#[async_trait]
+impl<T: Transport> Transport for ChecksumTransport<T> {
+    async fn send(&self, frame: &[u8]) -> io::Result<()> {
+        let frame = wire::set_checksum(frame, self.kind)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        self.inner.send(&frame).await
+    }
+
+    async fn recv(&self) -> io::Result<Vec<u8>> {
+        loop {
+            let frame = self.inner.recv().await?;
+            let Ok((header, _)) = wire::parse_v2_header(&frame) else {
+                return Ok(frame);
+            };
+            let got = ChecksumKind::from_flags(header.flags);
+            if got == self.kind {
+                return Ok(frame);
+            }
+            tracing::debug!(
+                got = got.name(),
+                want = self.kind.name(),
+                "dropping frame with another checksum"
+            );
+            count_decode_error(&FrameError::Crc);
+        }
+    }
+}
@@ -0,0 +1,297 @@
+//! Async request/response client for the R5 calc service.
+//!
+//! Requests go out as v2 frames with a fresh sequence id over any
+//! [`Transport`]; a background task receives frames and hands each response
+//! to the caller waiting on its seq.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+use crate::limits::DecodeLimits;
+use crate::message::{decode_reply_with_limits, encode_frame_v2, type_name, WireMessage};
+use crate::metrics::metrics;
+use crate::proto::{CalcRequest, CalcResponse, Op};
+use crate::telemetry;
+use crate::timing::LatencyBreakdown;
+use crate::transport::Transport;
+use crate::wire;
+use crate::{new_trace_ctx, FrameError};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    /// A reply arrived but could not be used (including R5 `CalcError`s).
+    #[error(transparent)]
+    Frame(#[from] FrameError),
+    #[error("no reply within {0:?}")]
+    Timeout(Duration),
+    #[error("connection closed")]
+    Closed,
+    /// Every seq is taken by a request still waiting for its reply.
+    #[error("all {} sequence numbers in flight", u16::MAX)]
+    Busy,
+    #[error("io: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    /// Deadline for a single attempt.
+    pub timeout: Duration,
+    /// Extra attempts for idempotent requests after a timeout.
+    pub retries: u32,
+    /// Delay before the first retry; doubles on each further retry.
+    pub backoff: Duration,
+    /// Checked on every reply before it is decoded.
+    pub limits: DecodeLimits,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(1),
+            retries: 2,
+            backoff: Duration::from_millis(50),
+            limits: DecodeLimits::default(),
+        }
+    }
+}
+
+/// Per-call overrides of [`ClientConfig`].
+#[derive(Debug, Clone)]
+pub struct CallOptions {
+    pub timeout: Duration,
+    pub retries: u32,
+    /// Only idempotent requests are retried.
+    pub idempotent: bool,
+}
+
+type Reply = Result<Vec<u8>, ClientError>;
+
+#[derive(Default)]
+struct Pending {
+    next_seq: u16,
+    waiters: HashMap<u16, oneshot::Sender<Reply>>,
+    closed: bool,
+}
+
+impl Pending {
+    // Next free seq; 0 is skipped since v1 frames report seq 0.
+    fn register(&mut self, tx: oneshot::Sender<Reply>) -> Result<u16, ClientError> {
+        if self.closed {
+            return Err(ClientError::Closed);
+        }
+        for _ in 0..u16::MAX {
+            self.next_seq = self.next_seq.wrapping_add(1);
+            if self.next_seq == 0 {
+                self.next_seq = 1;
+            }
+            if !self.waiters.contains_key(&self.next_seq) {
+                self.waiters.insert(self.next_seq, tx);
+                in_flight().inc();
+                return Ok(self.next_seq);
+            }
+        }
+        Err(ClientError::Busy)
+    }
+
+    fn take(&mut self, seq: u16) -> Option<oneshot::Sender<Reply>> {
+        let tx = self.waiters.remove(&seq)?;
+        in_flight().dec();
+        Some(tx)
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        in_flight().sub(self.waiters.len() as i64);
+    }
+}
+
+fn in_flight() -> prometheus::IntGauge {
+    metrics().in_flight.with_label_values(&["client"])
+}
+
+pub struct GatewayClient {
+    transport: Arc<dyn Transport>,
+    pending: Arc<Mutex<Pending>>,
+    config: ClientConfig,
+    reader: JoinHandle<()>,
+}
+
+impl GatewayClient {
+    /// Start a client on a connected transport (rpmsg chardev, socket, ...).
+    pub fn new(transport: impl Transport + 'static, config: ClientConfig) -> Self {
+        let transport: Arc<dyn Transport> = Arc::new(transport);
+        let pending = Arc::new(Mutex::new(Pending::default()));
+        let reader = tokio::spawn(read_loop(transport.clone(), pending.clone()));
+        Self {
+            transport,
+            pending,
+            config,
+            reader,
+        }
+    }
+
+    pub fn config(&self) -> &ClientConfig {
+        &self.config
+    }
+
+    /// Default call options from the client config.
+    pub fn call_options(&self, idempotent: bool) -> CallOptions {
+        CallOptions {
+            timeout: self.config.timeout,
+            retries: self.config.retries,
+            idempotent,
+        }
+    }
+
+    /// Run `op` on the R5. Calc ops are pure, so they are retried on timeout.
+    /// The request carries the current span's context ([`new_trace_ctx`]),
+    /// so the R5 leg is recorded under it.
+    pub async fn calc(&self, op: Op, a: u32, b: u32) -> Result<CalcResponse, ClientError> {
+        let req = CalcRequest {
+            op: op as i32,
+            a,
+            b,
+            trace: Some(new_trace_ctx()),
+        };
+        self.call(&req, self.call_options(true)).await
+    }
+
+    /// Send `msg` and wait for its reply, retrying idempotent requests with
+    /// exponential backoff. Each attempt uses a new seq, so a late reply to an
+    /// abandoned attempt is dropped rather than misrouted.
+    ///
+    /// If `msg` carries a trace context, the answered attempt is recorded as
+    /// an R5 span under it ([`telemetry::record_r5_span`]). Replies with R5
+    /// timestamps also feed the [`LatencyBreakdown`] histograms.
+    pub async fn call<Req, Resp>(&self, msg: &Req, opts: CallOptions) -> Result<Resp, ClientError>
+    where
+        Req: WireMessage,
+        Resp: WireMessage,
+    {
+        let attempts = if opts.idempotent { opts.retries + 1 } else { 1 };
+        let mut backoff = self.config.backoff;
+        for attempt in 1..=attempts {
+            let sent = SystemTime::now();
+            let start = Instant::now();
+            match self.attempt(msg, opts.timeout).await {
+                Err(ClientError::Timeout(_)) if attempt < attempts => {
+                    tracing::debug!(attempt, ?backoff, "request timed out, retrying");
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                Err(e) => return Err(e),
+                Ok(frame) => {
+                    let (rtt, received) = (start.elapsed(), SystemTime::now());
+                    let reply = decode_reply_with_limits::<Resp>(&frame, &self.config.limits);
+                    let breakdown = reply
+                        .as_ref()
+                        .ok()
+                        .and_then(WireMessage::r5_timing)
+                        .map(|t| LatencyBreakdown::new(rtt, &t));
+                    if let Some(b) = &breakdown {
+                        b.record();
+                        tracing::debug!(queueing = ?b.queueing, processing = ?b.processing,
+                            transit = ?b.transit, "latency breakdown");
+                    }
+                    if let Some(trace) = msg.trace_ctx() {
+                        let name = type_name(Req::TYPE).unwrap_or("unknown");
+                        let error = reply.as_ref().err();
+                        telemetry::record_r5_span(
+                            name,
+                            trace,
+                            sent,
+                            received,
+                            breakdown.as_ref(),
+                            error,
+                        );
+                    }
+                    return Ok(reply?);
+                }
+            }
+        }
+        unreachable!("at least one attempt is made")
+    }
+
+    async fn attempt<Req: WireMessage>(
+        &self,
+        msg: &Req,
+        timeout: Duration,
+    ) -> Result<Vec<u8>, ClientError> {
+        let (tx, rx) = oneshot::channel();
+        let seq = self.pending.lock().unwrap().register(tx)?;
+        let frame = encode_frame_v2(msg, seq);
+
+        let start = Instant::now();
+        if let Err(e) = self.transport.send(&frame).await {
+            self.pending.lock().unwrap().take(seq);
+            return Err(e.into());
+        }
+
+        match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(reply)) => {
+                if reply.is_ok() {
+                    metrics().rtt.observe(start.elapsed().as_secs_f64());
+                }
+                reply
+            }
+            Ok(Err(_)) => Err(ClientError::Closed),
+            Err(_) => {
+                self.pending.lock().unwrap().take(seq);
+                Err(ClientError::Timeout(timeout))
+            }
+        }
+    }
+
+    /// Number of requests waiting for a reply.
+    pub fn in_flight(&self) -> usize {
+        self.pending.lock().unwrap().waiters.len()
+    }
+}
+
+impl Drop for GatewayClient {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+async fn read_loop(transport: Arc<dyn Transport>, pending: Arc<Mutex<Pending>>) {
+    loop {
+        match transport.recv().await {
+            Ok(frame) => dispatch(&pending, frame),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => {
+                tracing::warn!(error = %e, "client recv failed");
+                break;
+            }
+        }
+    }
+
+    let mut p = pending.lock().unwrap();
+    p.closed = true;
+    for (_, tx) in p.waiters.drain() {
+        in_flight().dec();
+        let _ = tx.send(Err(ClientError::Closed));
+    }
+}
+
+fn dispatch(pending: &Mutex<Pending>, frame: Vec<u8>) {
+    let seq = match wire::unwrap_any(&frame) {
+        Ok(f) => f.header.seq,
+        Err(e) => {
+            tracing::debug!(error = %e, "dropping undecodable reply");
+            return;
+        }
+    };
+    match pending.lock().unwrap().take(seq) {
+        Some(tx) => {
+            let _ = tx.send(Ok(frame));
+        }
+        None => tracing::debug!(seq, "dropping reply with no waiting request"),
+    }
+}
@@ -0,0 +1,260 @@
+//! R5 clock offset and drift from NTP-style `TimeSync` exchanges.
+//!
+//! Each exchange gives four readings: t1 (request sent) and t4 (reply
+//! received) on Linux `CLOCK_MONOTONIC`, t2 (request received) and t3 (reply
+//! sent) on the R5 clock (`calc_now_ns`). As in NTP,
+//!
+//! - offset = ((t2 - t1) + (t3 - t4)) / 2, the R5 clock minus ours
+//! - delay = (t4 - t1) - (t3 - t2), the time spent on the link
+//!
+//! The offset is only good to half the delay, so [`ClockSync`] anchors on the
+//! sample with the least delay in its window and fits drift over the rest by
+//! least squares. [`spawn`] runs exchanges in the background and publishes
+//! the estimate ([`current`]); [`telemetry::record_r5_span`] uses it to place
+//! R5 events on the Linux timeline.
+//!
+//! [`telemetry::record_r5_span`]: crate::telemetry::record_r5_span
+
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use tokio::task::JoinHandle;
+
+use crate::client::{ClientError, GatewayClient};
+use crate::proto::{TimeSyncRequest, TimeSyncResponse};
+
+/// Linux `CLOCK_MONOTONIC` in ns, the timebase of t1 and t4.
+pub fn monotonic_ns() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // Cannot fail for CLOCK_MONOTONIC with a valid pointer.
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+/// The four readings of one exchange, in ns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    pub t1: u64,
+    pub t2: u64,
+    pub t3: u64,
+    pub t4: u64,
+}
+
+impl Sample {
+    /// Whether the R5 stamped the reply; firmware without a clock sends 0.
+    pub fn r5_has_clock(&self) -> bool {
+        self.t3 != 0
+    }
+
+    /// R5 clock minus Linux monotonic, assuming a symmetric link.
+    pub fn offset_ns(&self) -> i64 {
+        let out = i128::from(self.t2) - i128::from(self.t1);
+        let back = i128::from(self.t3) - i128::from(self.t4);
+        ((out + back) / 2) as i64
+    }
+
+    /// `None` if the readings are out of order: a reply before its request
+    /// on either clock, or the R5 holding the request longer than the round
+    /// trip took.
+    pub fn delay_ns(&self) -> Option<u64> {
+        let round_trip = self.t4.checked_sub(self.t1)?;
+        let held = self.t3.checked_sub(self.t2)?;
+        round_trip.checked_sub(held)
+    }
+
+    /// Linux time the offset applies to.
+    fn midpoint(&self) -> u64 {
+        self.t1 + (self.t4 - self.t1) / 2
+    }
+
+    fn is_consistent(&self) -> bool {
+        self.r5_has_clock() && self.delay_ns().is_some()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClockEstimate {
+    /// Linux monotonic time the offset was measured at.
+    pub anchor_ns: u64,
+    /// R5 clock minus Linux monotonic at `anchor_ns`.
+    pub offset_ns: i64,
+    /// How much faster the R5 clock runs than ours, in parts per million.
+    pub drift_ppm: f64,
+    /// Round trip of the anchor sample; the offset is good to half of it.
+    pub delay_ns: u64,
+    pub samples: usize,
+}
+
+impl ClockEstimate {
+    /// R5 clock minus Linux monotonic at Linux time `linux_ns`.
+    pub fn offset_at(&self, linux_ns: u64) -> i64 {
+        let since = linux_ns as f64 - self.anchor_ns as f64;
+        self.offset_ns + (since * self.drift_ppm * 1e-6).round() as i64
+    }
+
+    /// The Linux monotonic time of R5 reading `r5_ns`.
+    pub fn r5_to_monotonic(&self, r5_ns: u64) -> u64 {
+        // Solve linux + offset_at(linux) = r5 for linux.
+        let since = i128::from(r5_ns) - i128::from(self.offset_ns) - i128::from(self.anchor_ns);
+        let since = (since as f64 / (1.0 + self.drift_ppm * 1e-6)).round() as i128;
+        (i128::from(self.anchor_ns) + since).max(0) as u64
+    }
+
+    /// The wall-clock time of R5 reading `r5_ns`.
+    pub fn r5_to_system(&self, r5_ns: u64) -> SystemTime {
+        let (now, now_mono) = (SystemTime::now(), monotonic_ns());
+        let mono = self.r5_to_monotonic(r5_ns);
+        if mono <= now_mono {
+            now - Duration::from_nanos(now_mono - mono)
+        } else {
+            now + Duration::from_nanos(mono - now_mono)
+        }
+    }
+}
+
+/// Estimator over the last `window` samples.
+#[derive(Debug, Clone)]
+pub struct ClockSync {
+    window: usize,
+    samples: VecDeque<Sample>,
+}
+
+impl ClockSync {
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.max(1),
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// Add a sample; returns false if it was rejected (no R5 clock, or
+    /// readings out of order).
+    pub fn add(&mut self, sample: Sample) -> bool {
+        if !sample.is_consistent() {
+            return false;
+        }
+        if self.samples.len() == self.window {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+        true
+    }
+
+    /// The current estimate, once there is a sample. Drift needs at least
+    /// two; samples with more than twice the best delay are left out of it.
+    pub fn estimate(&self) -> Option<ClockEstimate> {
+        // `add` only keeps samples that have a delay.
+        let delay = |s: &Sample| s.delay_ns().unwrap_or(u64::MAX);
+        let anchor = self.samples.iter().min_by_key(|s| delay(s))?;
+        let limit = 2 * delay(anchor).max(1_000);
+        let points: Vec<(f64, f64)> = self
+            .samples
+            .iter()
+            .filter(|s| delay(s) <= limit)
+            .map(|s| {
+                let x = s.midpoint() as f64 - anchor.midpoint() as f64;
+                let y = (s.offset_ns() - anchor.offset_ns()) as f64;
+                (x, y)
+            })
+            .collect();
+        let n = points.len() as f64;
+        let (mx, my) = points
+            .iter()
+            .fold((0.0, 0.0), |(sx, sy), (x, y)| (sx + x / n, sy + y / n));
+        let (sxy, sxx) = points.iter().fold((0.0, 0.0), |(sxy, sxx), (x, y)| {
+            (sxy + (x - mx) * (y - my), sxx + (x - mx) * (x - mx))
+        });
+        let drift_ppm = if sxx > 0.0 { sxy / sxx * 1e6 } else { 0.0 };
+        Some(ClockEstimate {
+            anchor_ns: anchor.midpoint(),
+            offset_ns: anchor.offset_ns(),
+            drift_ppm,
+            delay_ns: delay(anchor),
+            samples: self.samples.len(),
+        })
+    }
+}
+
+/// Run one exchange over `client`.
+pub async fn exchange(client: &GatewayClient) -> Result<Sample, ClientError> {
+    let t1 = monotonic_ns();
+    let req = TimeSyncRequest { t1_ns: t1 };
+    // Not retried: a late sample is a bad sample.
+    let resp: TimeSyncResponse = client.call(&req, client.call_options(false)).await?;
+    let t4 = monotonic_ns();
+    Ok(Sample {
+        t1,
+        t2: resp.t2_ns,
+        t3: resp.t3_ns,
+        t4,
+    })
+}
+
+static CURRENT: RwLock<Option<ClockEstimate>> = RwLock::new(None);
+
+/// The estimate last published by [`spawn`] (or [`publish`]).
+pub fn current() -> Option<ClockEstimate> {
+    *CURRENT.read().unwrap()
+}
+
+/// Make `estimate` the process-wide one.
+pub fn publish(estimate: Option<ClockEstimate>) {
+    *CURRENT.write().unwrap() = estimate;
+}
+
+#[derive(Debug, Clone)]
+pub struct SyncConfig {
+    pub interval: Duration,
+    /// Samples kept for the estimate.
+    pub window: usize,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(5),
+            window: 16,
+        }
+    }
+}
+
+/// Exchange with the R5 every `config.interval` and publish the estimate.
+/// Stops if the R5 turns out to have no clock.
+pub fn spawn(client: Arc<GatewayClient>, config: SyncConfig) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut sync = ClockSync::new(config.window);
+        let mut tick = tokio::time::interval(config.interval);
+        loop {
+            tick.tick().await;
+            let sample = match exchange(&client).await {
+                Ok(sample) => sample,
+                Err(e) => {
+                    tracing::debug!(error = %e, "clock sync exchange failed");
+                    continue;
+                }
+            };
+            if !sample.r5_has_clock() {
+                tracing::info!("R5 reports no clock; clock sync stopped");
+                return;
+            }
+            if !sync.add(sample) {
+                tracing::debug!(?sample, "dropping inconsistent clock sample");
+                continue;
+            }
+            let estimate = sync.estimate();
+            if let Some(e) = &estimate {
+                tracing::debug!(
+                    offset_ns = e.offset_ns,
+                    drift_ppm = e.drift_ppm,
+                    delay_ns = e.delay_ns,
+                    "clock estimate"
+                );
+            }
+            publish(estimate);
+        }
+    })
+}
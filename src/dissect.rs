@@ -0,0 +1,518 @@
+//! Byte-by-byte breakdown of a frame for debugging captures.
+//!
+//! [`dissect`] never fails: it labels as much of the input as it can parse
+//! (header fields, each protobuf field with its tag and wire type, unknown
+//! fields, the CRC) and attaches a problem note where something is off.
+//! [`render`] turns the result into an annotated hexdump.
+
+use std::fmt::Write as _;
+use std::ops::Range;
+
+use crate::checksum::ChecksumKind;
+use crate::message::schema;
+use crate::proto::{Op, Status};
+use crate::wire;
+
+/// A labelled byte range, possibly with sub-ranges.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    pub label: String,
+    pub range: Range<usize>,
+    pub value: String,
+    /// Set when these bytes are wrong or could not be parsed.
+    pub problem: Option<String>,
+    pub children: Vec<Node>,
+}
+
+impl Node {
+    fn new(label: impl Into<String>, range: Range<usize>, value: impl Into<String>) -> Self {
+        Self {
+            label: label.into(),
+            range,
+            value: value.into(),
+            problem: None,
+            children: Vec::new(),
+        }
+    }
+
+    fn problem(mut self, problem: impl Into<String>) -> Self {
+        self.problem = Some(problem.into());
+        self
+    }
+
+    /// This node or any descendant has a problem.
+    pub fn has_problem(&self) -> bool {
+        self.problem.is_some() || self.children.iter().any(Node::has_problem)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrcStatus {
+    Ok(u32),
+    Mismatch {
+        got: u32,
+        computed: u32,
+    },
+    /// The frame ends before the CRC.
+    Missing,
+    /// The v2 flags select no checksum.
+    Absent,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dissection {
+    /// Wire version, if the frame got that far.
+    pub version: Option<u8>,
+    pub nodes: Vec<Node>,
+    pub crc: CrcStatus,
+}
+
+impl Dissection {
+    pub fn has_problem(&self) -> bool {
+        !matches!(self.crc, CrcStatus::Ok(_) | CrcStatus::Absent)
+            || self.nodes.iter().any(Node::has_problem)
+    }
+}
+
+/// How a field's value is shown.
+#[derive(Debug, Clone, Copy)]
+pub enum FieldKind {
+    Uint32,
+    Uint64,
+    Op,
+    Status,
+    Bytes,
+    String,
+    Message(&'static str, &'static [FieldDef]),
+}
+
+/// One entry of a message's field schema ([`schema`]), used to label
+/// protobuf fields.
+#[derive(Debug, Clone, Copy)]
+pub struct FieldDef {
+    pub tag: u32,
+    pub name: &'static str,
+    pub kind: FieldKind,
+}
+
+pub(crate) const fn field(tag: u32, name: &'static str, kind: FieldKind) -> FieldDef {
+    FieldDef { tag, name, kind }
+}
+
+/// Break `frame` (v1, SYNC-prefixed v1 or v2) into labelled ranges.
+pub fn dissect(frame: &[u8]) -> Dissection {
+    let mut nodes = Vec::new();
+    let mut at = 0;
+    if frame.starts_with(&wire::SYNC.to_be_bytes()) {
+        nodes.push(Node::new("sync", 0..2, format!("{:#06X}", wire::SYNC)));
+        at = 2;
+    }
+    match frame.get(at) {
+        None => {
+            nodes.push(Node::new("frame", at..at, "").problem("empty"));
+            Dissection {
+                version: None,
+                nodes,
+                crc: CrcStatus::Missing,
+            }
+        }
+        Some(&wire::PROTO_VERSION_V2) if at == 2 => dissect_v2(frame, nodes),
+        Some(&wire::PROTO_VERSION) => dissect_v1(frame, at, nodes),
+        Some(&v) => {
+            nodes.push(Node::new("version", at..at + 1, v.to_string()).problem("unknown version"));
+            rest(frame, at + 1, "not parsed", &mut nodes);
+            Dissection {
+                version: Some(v),
+                nodes,
+                crc: CrcStatus::Missing,
+            }
+        }
+    }
+}
+
+fn dissect_v1(frame: &[u8], at: usize, mut nodes: Vec<Node>) -> Dissection {
+    nodes.push(Node::new("version", at..at + 1, "1"));
+    let Some(&typ) = frame.get(at + 1) else {
+        nodes.push(Node::new("type", at + 1..at + 1, "").problem("truncated"));
+        return Dissection {
+            version: Some(1),
+            nodes,
+            crc: CrcStatus::Missing,
+        };
+    };
+    nodes.push(type_node(typ, at + 1));
+    let start = at + 2;
+    if frame.len() < start + 4 {
+        rest(frame, start, "truncated", &mut nodes);
+        return Dissection {
+            version: Some(1),
+            nodes,
+            crc: CrcStatus::Missing,
+        };
+    }
+    let end = frame.len() - 4;
+    nodes.push(payload_node(frame, typ, start..end));
+    let crc = crc_node(frame, start..end, ChecksumKind::Crc32, &mut nodes);
+    Dissection {
+        version: Some(1),
+        nodes,
+        crc,
+    }
+}
+
+fn dissect_v2(frame: &[u8], mut nodes: Vec<Node>) -> Dissection {
+    let version = Some(wire::PROTO_VERSION_V2);
+    nodes.push(Node::new("version", 2..3, "2"));
+    if frame.len() < wire::V2_HEADER_LEN {
+        rest(frame, 3, "header truncated", &mut nodes);
+        return Dissection {
+            version,
+            nodes,
+            crc: CrcStatus::Missing,
+        };
+    }
+    let typ = frame[3];
+    let len = u16::from_be_bytes([frame[5], frame[6]]) as usize;
+    let seq = u16::from_be_bytes([frame[7], frame[8]]);
+    nodes.push(type_node(typ, 3));
+    nodes.push(Node::new("flags", 4..5, format!("{:#04X}", frame[4])));
+    nodes.push(Node::new("length", 5..7, len.to_string()));
+    nodes.push(Node::new("seq", 7..9, seq.to_string()));
+    let computed = wire::crc8(&frame[..9]);
+    let mut hcrc = Node::new("header crc", 9..10, format!("{:#04X}", frame[9]));
+    if computed != frame[9] {
+        hcrc = hcrc.problem(format!("mismatch, computed {computed:#04X}"));
+    }
+    nodes.push(hcrc);
+
+    let kind = ChecksumKind::from_flags(frame[4]);
+    let start = wire::V2_HEADER_LEN;
+    let end = start + len;
+    let trailer_end = end + kind.checksum().width();
+    if frame.len() < trailer_end {
+        let why = format!("truncated, header says {len} payload bytes");
+        rest(frame, start, &why, &mut nodes);
+        return Dissection {
+            version,
+            nodes,
+            crc: CrcStatus::Missing,
+        };
+    }
+    if frame[4] & wire::FLAG_FRAG_MASK != 0 {
+        nodes.push(fragment_node(frame, start..end));
+    } else if frame[4] & wire::FLAG_AUTH != 0 {
+        match end.checked_sub(wire::AUTH_LEN).filter(|&at| at >= start) {
+            Some(at) => {
+                nodes.push(payload_node(frame, typ, start..at));
+                nodes.push(auth_node(frame, at..end));
+            }
+            None => nodes.push(
+                Node::new("payload", start..end, "authenticated")
+                    .problem("short authentication block"),
+            ),
+        }
+    } else {
+        nodes.push(payload_node(frame, typ, start..end));
+    }
+    let crc = crc_node(frame, start..end, kind, &mut nodes);
+    if frame.len() > trailer_end {
+        nodes.push(Node::new(
+            "trailing",
+            trailer_end..frame.len(),
+            format!("{} bytes ignored", frame.len() - trailer_end),
+        ));
+    }
+    Dissection {
+        version,
+        nodes,
+        crc,
+    }
+}
+
+fn type_node(typ: u8, at: usize) -> Node {
+    match schema(typ) {
+        Some((name, _)) => Node::new("type", at..at + 1, format!("{typ} ({name})")),
+        None => Node::new("type", at..at + 1, typ.to_string()).problem("unknown type"),
+    }
+}
+
+fn payload_node(frame: &[u8], typ: u8, range: Range<usize>) -> Node {
+    let (name, fields) = schema(typ).unwrap_or(("unknown", &[]));
+    let mut node = Node::new("payload", range.clone(), name);
+    node.children = walk_fields(&frame[range.clone()], fields, range.start);
+    node
+}
+
+/// A fragment's payload is a slice of someone else's protobuf; only its
+/// sub-header is labelled.
+fn fragment_node(frame: &[u8], range: Range<usize>) -> Node {
+    let mut node = Node::new("payload", range.clone(), "fragment");
+    let at = range.start;
+    if range.len() < wire::FRAG_HEADER_LEN {
+        return node.problem("short fragment header");
+    }
+    let index = u16::from_be_bytes([frame[at], frame[at + 1]]);
+    let total = u16::from_be_bytes([frame[at + 2], frame[at + 3]]);
+    let chunk = at + wire::FRAG_HEADER_LEN..range.end;
+    node.children = vec![
+        Node::new("index", at..at + 2, index.to_string()),
+        Node::new("total", at + 2..at + 4, total.to_string()),
+        Node::new("chunk", chunk.clone(), format!("{} bytes", chunk.len())),
+    ];
+    node
+}
+
+/// The counter and tag of an authenticated frame. Checking the tag takes
+/// the key, which a capture does not come with.
+fn auth_node(frame: &[u8], range: Range<usize>) -> Node {
+    let at = range.start;
+    let counter = u64::from_be_bytes(frame[at..at + 8].try_into().unwrap());
+    let tag = at + wire::AUTH_COUNTER_LEN..range.end;
+    let mut node = Node::new("auth", range, "hmac-sha256/128, not checked");
+    node.children = vec![
+        Node::new("counter", at..at + 8, counter.to_string()),
+        Node::new("tag", tag.clone(), hex::encode_upper(&frame[tag])),
+    ];
+    node
+}
+
+fn crc_node(
+    frame: &[u8],
+    payload: Range<usize>,
+    kind: ChecksumKind,
+    nodes: &mut Vec<Node>,
+) -> CrcStatus {
+    let width = kind.checksum().width();
+    if width == 0 {
+        return CrcStatus::Absent;
+    }
+    let at = payload.end;
+    let mut le = [0; 4];
+    le[..width].copy_from_slice(&frame[at..at + width]);
+    let got = u32::from_le_bytes(le);
+    let computed = kind.checksum().compute(&frame[payload]);
+    let digits = 2 + 2 * width;
+    let mut node = Node::new(kind.name(), at..at + width, format!("{got:#0digits$X}"));
+    let status = if got == computed {
+        CrcStatus::Ok(got)
+    } else {
+        node = node.problem(format!("mismatch, computed {computed:#0digits$X}"));
+        CrcStatus::Mismatch { got, computed }
+    };
+    nodes.push(node);
+    status
+}
+
+fn rest(frame: &[u8], at: usize, why: &str, nodes: &mut Vec<Node>) {
+    let at = at.min(frame.len());
+    let value = format!("{} bytes", frame.len() - at);
+    nodes.push(Node::new("rest", at..frame.len(), value).problem(why));
+}
+
+/// Protobuf wire types.
+const WT_VARINT: u8 = 0;
+const WT_I64: u8 = 1;
+const WT_LEN: u8 = 2;
+const WT_I32: u8 = 5;
+
+fn wire_type_name(wt: u8) -> &'static str {
+    match wt {
+        WT_VARINT => "varint",
+        WT_I64 => "i64",
+        WT_LEN => "len",
+        3 => "start group",
+        4 => "end group",
+        WT_I32 => "i32",
+        _ => "invalid",
+    }
+}
+
+/// Read a varint at `buf[at..]`, returning the value and its length.
+pub fn read_varint(buf: &[u8], at: usize) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for (i, &b) in buf.get(at..)?.iter().take(10).enumerate() {
+        value |= u64::from(b & 0x7F) << (7 * i);
+        if b & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+/// Label each protobuf field in `buf`; `base` is the offset of `buf` in the frame.
+pub fn walk_fields(buf: &[u8], schema: &[FieldDef], base: usize) -> Vec<Node> {
+    let mut nodes = Vec::new();
+    let mut at = 0;
+    while at < buf.len() {
+        let Some((key, key_len)) = read_varint(buf, at) else {
+            nodes.push(bad_bytes(buf, at, base, "truncated or overlong tag varint"));
+            break;
+        };
+        let tag = (key >> 3) as u32;
+        let wt = (key & 7) as u8;
+        let def = schema.iter().find(|f| f.tag == tag);
+        let label = match def {
+            Some(f) => format!("{} ({tag})", f.name),
+            None => format!("unknown ({tag})"),
+        };
+        let tag_node = Node::new(
+            "tag",
+            base + at..base + at + key_len,
+            format!("field {tag}, {}", wire_type_name(wt)),
+        );
+        let body = at + key_len;
+        let parsed = match wt {
+            WT_VARINT => read_varint(buf, body).map(|(v, n)| {
+                let value = def.map_or(v.to_string(), |f| show_varint(f.kind, v));
+                (
+                    n,
+                    vec![Node::new("value", base + body..base + body + n, value)],
+                )
+            }),
+            WT_I64 => fixed(buf, body, 8, base),
+            WT_I32 => fixed(buf, body, 4, base),
+            WT_LEN => read_varint(buf, body).and_then(|(len, n)| {
+                let start = body + n;
+                let end = start.checked_add(usize::try_from(len).ok()?)?;
+                let data = buf.get(start..end)?;
+                let len_node = Node::new("length", base + body..base + start, len.to_string());
+                let mut value = Node::new("value", base + start..base + end, "");
+                match def.map(|f| f.kind) {
+                    Some(FieldKind::Message(name, fields)) => {
+                        value.value = name.to_string();
+                        value.children = walk_fields(data, fields, base + start);
+                    }
+                    Some(FieldKind::String) => {
+                        value.value = format!("{:?}", String::from_utf8_lossy(data));
+                    }
+                    _ => value.value = hex::encode(data),
+                }
+                Some((end - body, vec![len_node, value]))
+            }),
+            _ => None,
+        };
+        let Some((n, mut children)) = parsed else {
+            let why = if wt > WT_I32 || wt == 3 || wt == 4 {
+                format!("unsupported wire type {wt}")
+            } else {
+                format!("truncated {}", wire_type_name(wt))
+            };
+            nodes.push(bad_bytes(buf, at, base, &why));
+            break;
+        };
+        children.insert(0, tag_node);
+        let end = body + n;
+        let summary = children.last().map(|c| c.value.clone()).unwrap_or_default();
+        let mut node = Node::new(label, base + at..base + end, summary);
+        node.children = children;
+        if def.is_some_and(|f| !kind_accepts(f.kind, wt)) {
+            node = node.problem(format!("unexpected wire type {}", wire_type_name(wt)));
+        }
+        nodes.push(node);
+        at = end;
+    }
+    nodes
+}
+
+fn fixed(buf: &[u8], at: usize, n: usize, base: usize) -> Option<(usize, Vec<Node>)> {
+    let data = buf.get(at..at + n)?;
+    let mut le = [0u8; 8];
+    le[..n].copy_from_slice(data);
+    let value = u64::from_le_bytes(le).to_string();
+    Some((n, vec![Node::new("value", base + at..base + at + n, value)]))
+}
+
+fn bad_bytes(buf: &[u8], at: usize, base: usize, why: &str) -> Node {
+    Node::new(
+        "garbage",
+        base + at..base + buf.len(),
+        format!("{} bytes", buf.len() - at),
+    )
+    .problem(why)
+}
+
+/// Whether a field of `kind` may be sent with wire type `wt`.
+pub(crate) fn kind_accepts(kind: FieldKind, wt: u8) -> bool {
+    match kind {
+        FieldKind::Uint32 | FieldKind::Uint64 | FieldKind::Op | FieldKind::Status => {
+            wt == WT_VARINT
+        }
+        FieldKind::Bytes | FieldKind::String | FieldKind::Message(..) => wt == WT_LEN,
+    }
+}
+
+fn show_varint(kind: FieldKind, v: u64) -> String {
+    let name = match kind {
+        FieldKind::Op => Op::try_from(v as i32).ok().map(|op| op.as_str_name()),
+        FieldKind::Status => Status::try_from(v as i32).ok().map(|st| st.as_str_name()),
+        _ => None,
+    };
+    match name {
+        Some(name) => format!("{v} ({name})"),
+        None => v.to_string(),
+    }
+}
+
+/// Bytes shown per hexdump line.
+const ROW: usize = 8;
+
+/// Annotated hexdump: one line per labelled range, children indented, and a
+/// closing CRC verdict.
+pub fn render(frame: &[u8], d: &Dissection) -> String {
+    let mut out = String::new();
+    for node in &d.nodes {
+        render_node(&mut out, frame, node, 0);
+    }
+    let verdict = match d.crc {
+        CrcStatus::Ok(crc) => format!("crc ok ({crc:#010X})"),
+        CrcStatus::Mismatch { got, computed } => {
+            format!("crc MISMATCH (frame {got:#010X}, computed {computed:#010X})")
+        }
+        CrcStatus::Missing => "crc missing".to_string(),
+        CrcStatus::Absent => "no checksum".to_string(),
+    };
+    let _ = writeln!(out, "{verdict}");
+    out
+}
+
+fn render_node(out: &mut String, frame: &[u8], node: &Node, depth: usize) {
+    let bytes = frame.get(node.range.clone()).unwrap_or(&[]);
+    let mut rows = bytes.chunks(ROW);
+    let first = rows.next().unwrap_or(&[]);
+    let label = format!("{:indent$}{}", "", node.label, indent = depth * 2);
+    let _ = write!(
+        out,
+        "{:04X}  {:<width$}  {:<24} {}",
+        node.range.start,
+        hex_row(first),
+        label,
+        node.value,
+        width = ROW * 3 - 1
+    );
+    if let Some(problem) = &node.problem {
+        let _ = write!(out, "  !! {problem}");
+    }
+    out.push('\n');
+    // A node with children shows its first row only; the children cover the rest.
+    if node.children.is_empty() {
+        for (i, row) in rows.enumerate() {
+            let _ = writeln!(
+                out,
+                "{:04X}  {}",
+                node.range.start + (i + 1) * ROW,
+                hex_row(row)
+            );
+        }
+    }
+    for child in &node.children {
+        render_node(out, frame, child, depth + 1);
+    }
+}
+
+fn hex_row(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{b:02X}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,478 @@
+//! In-process stand-in for the R5, for tests and demos without hardware.
+//!
+//! [`R5Peer`] answers frames the way `r5/frame_decode.c` + `r5/calc_service.c`
+//! do: v2 calc and time-sync requests only, fragments reassembled first, seq
+//! and checksum kind echoed, checked arithmetic, `CalcError` replies, and a
+//! `STATUS_DECODE_ERROR` reply when the header is good but the payload
+//! checksum or protobuf is not. Anything else is dropped without a reply.
+//! Older firmware images, reply latency, an R5 clock, a link key as in
+//! `r5/frame_auth.c` and faults can be configured on top.
+
+use std::io;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use prost::encoding::encode_varint;
+use prost::Message;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tokio::task::JoinHandle;
+
+use crate::auth::{AuthKey, ReplayWindow};
+use crate::calc::evaluate_request;
+use crate::checksum::ChecksumKind;
+use crate::dissect::{self, FieldDef, FieldKind};
+use crate::fragment::{Reassembler, ReassemblyConfig};
+use crate::message::{encode_frame_v2, WireMessage};
+use crate::proto::{
+    CalcError, CalcRequest, CalcResponse, Op, Status, TimeSyncRequest, TimeSyncResponse, TraceCtx,
+};
+use crate::trace_context::TRACESTATE_MAX;
+use crate::transport::{MemoryTransport, Transport};
+use crate::wire;
+
+/// `CALC_REASM_MAX` and `CALC_REASM_TIMEOUT_NS` from `r5/frag_reasm.h`.
+const REASM_MAX: usize = 1024;
+const REASM_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// nanopb field limits from `r5/proto/nanopb.options`.
+const TRACE_ID_MAX: usize = 16;
+const SPAN_ID_MAX: usize = 8;
+const DETAIL_MAX: usize = 63;
+
+/// Which R5 image to behave like.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Firmware {
+    /// The first image: v1 frames, always `a + b` (wrapping), trace echoed,
+    /// and anything it cannot decode silently dropped.
+    Legacy,
+    /// v2 frames and `CalcError`s, but only `OP_SUM` is implemented.
+    SumOnly,
+    /// What `r5/` builds today.
+    #[default]
+    Current,
+}
+
+/// Faults applied on top of the firmware behaviour.
+#[derive(Debug, Clone, Default)]
+pub struct Faults {
+    /// Probability that a request gets no reply.
+    pub drop: f64,
+    /// Probability that one byte of a reply is flipped.
+    pub corrupt: f64,
+    /// Reject every decodable request with this status.
+    pub force_status: Option<Status>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct R5Config {
+    pub firmware: Firmware,
+    /// Delay before each request is handled; requests are handled one at a
+    /// time. With `clock` set it shows up as R5 queueing.
+    pub latency: Duration,
+    /// Stamp `CalcResponse` with R5 timing, as firmware that overrides
+    /// `calc_now_ns` does. Off by default, like the weak `calc_now_ns`.
+    pub clock: bool,
+    pub faults: Faults,
+    /// Seed for fault injection, so failing runs can be replayed.
+    pub seed: u64,
+    /// Only answer requests authenticated with this key, and authenticate
+    /// the replies, as `Current` firmware built with `r5/frame_auth.c` does.
+    pub auth: Option<AuthKey>,
+}
+
+pub struct R5Peer {
+    config: R5Config,
+    rng: Mutex<StdRng>,
+    epoch: Instant,
+    reasm: Mutex<Reassembler>,
+    replay: Mutex<ReplayWindow>,
+}
+
+impl R5Peer {
+    pub fn new(config: R5Config) -> Self {
+        let rng = Mutex::new(StdRng::seed_from_u64(config.seed));
+        Self {
+            config,
+            rng,
+            epoch: Instant::now(),
+            reasm: Mutex::new(r5_reassembler()),
+            replay: Mutex::new(ReplayWindow::new()),
+        }
+    }
+
+    pub fn config(&self) -> &R5Config {
+        &self.config
+    }
+
+    /// The R5 clock (`calc_now_ns`): ns since the peer was created, or 0
+    /// without `clock`.
+    fn now_ns(&self) -> u64 {
+        if self.config.clock {
+            (self.epoch.elapsed().as_nanos() as u64).max(1)
+        } else {
+            0
+        }
+    }
+
+    /// The reply to one received frame, or `None` if the R5 would stay silent.
+    pub fn handle_frame(&self, frame: &[u8]) -> Option<Vec<u8>> {
+        self.handle_frame_at(frame, self.now_ns())
+    }
+
+    /// As [`handle_frame`](Self::handle_frame), for a frame that arrived at
+    /// `rx_ns` on the R5 clock and was queued until now.
+    fn handle_frame_at(&self, frame: &[u8], rx_ns: u64) -> Option<Vec<u8>> {
+        // Fragments are collected silently, as `r5/frag_reasm.c` does: one
+        // message at a time, a first fragment replacing the one in progress,
+        // and timeouts only with a clock.
+        let whole;
+        let frame = if self.config.firmware == Firmware::Current {
+            let mut reasm = self.reasm.lock().unwrap();
+            let first = wire::unwrap_any(frame)
+                .is_ok_and(|f| f.header.flags & wire::FLAG_FRAG_MASK == wire::FLAG_FRAG_FIRST);
+            if first {
+                *reasm = r5_reassembler();
+            }
+            let now = if self.config.clock {
+                Instant::now()
+            } else {
+                self.epoch
+            };
+            whole = reasm.push_at(frame.to_vec(), now).ok()??;
+            &whole[..]
+        } else {
+            frame
+        };
+        // Unauthenticated and replayed frames get no reply at all.
+        let auth = self.config.auth.as_ref();
+        let opened;
+        let (frame, counter) = match auth {
+            Some(key) if self.config.firmware == Firmware::Current => {
+                let (plain, counter) = wire::open_auth(frame, key).ok()?;
+                self.replay.lock().unwrap().check(counter).ok()?;
+                opened = plain;
+                (&opened[..], Some(counter))
+            }
+            _ => (frame, None),
+        };
+        let faults = &self.config.faults;
+        if faults.drop > 0.0 && self.rng.lock().unwrap().gen_bool(faults.drop.min(1.0)) {
+            return None;
+        }
+        let mut reply = match self.config.firmware {
+            Firmware::Legacy => self.legacy_reply(frame)?,
+            Firmware::SumOnly | Firmware::Current => self.v2_reply(frame, rx_ns)?,
+        };
+        if let (Some(key), Some(counter)) = (auth, counter) {
+            reply = wire::seal_auth(&reply, key, counter).expect("replies are well-formed");
+        }
+        if faults.corrupt > 0.0 {
+            let mut rng = self.rng.lock().unwrap();
+            if rng.gen_bool(faults.corrupt.min(1.0)) {
+                let i = rng.gen_range(0..reply.len());
+                reply[i] ^= 1 << rng.gen_range(0..8);
+            }
+        }
+        Some(reply)
+    }
+
+    fn legacy_reply(&self, frame: &[u8]) -> Option<Vec<u8>> {
+        // Frames reach us SYNC-stripped from a transport, but raw ones are fine too.
+        let frame = frame
+            .strip_prefix(&wire::SYNC.to_be_bytes()[..])
+            .unwrap_or(frame);
+        let payload = wire::unwrap_v1_req(frame).ok()?;
+        let req = CalcRequest::decode(payload).ok()?;
+        let resp = CalcResponse {
+            result: req.a.wrapping_add(req.b),
+            trace: req.trace,
+            ..Default::default()
+        };
+        Some(wire::wrap_v1_resp(&resp.encode_to_vec()))
+    }
+
+    /// The reply carries the request's checksum.
+    fn v2_reply(&self, frame: &[u8], rx_ns: u64) -> Option<Vec<u8>> {
+        let (header, _) = wire::parse_v2_header(frame).ok()?;
+        // Older images only know crc32 and read any other trailer as a bad one.
+        let kind = match self.config.firmware {
+            Firmware::Current => ChecksumKind::from_flags(header.flags),
+            _ => ChecksumKind::Crc32,
+        };
+        let reply = self.v2_answer(frame, kind, rx_ns)?;
+        Some(wire::set_checksum(&reply, kind).expect("replies are well-formed"))
+    }
+
+    fn v2_answer(&self, frame: &[u8], kind: ChecksumKind, rx_ns: u64) -> Option<Vec<u8>> {
+        let (header, len) = wire::parse_v2_header(frame).ok()?;
+        let time_sync =
+            header.typ == wire::TYPE_TIME_REQ && self.config.firmware == Firmware::Current;
+        let checksum = kind.checksum();
+        let end = wire::V2_HEADER_LEN + len;
+        if !(header.typ == wire::TYPE_REQ || time_sync) || frame.len() < end + checksum.width() {
+            return None;
+        }
+        let seq = header.seq;
+        let payload = &frame[wire::V2_HEADER_LEN..end];
+        // The header CRC vouches for seq, so a bad payload still gets a reply.
+        if !checksum.verify(payload, &frame[end..end + checksum.width()]) {
+            return Some(error_frame(seq, Status::DecodeError, "payload crc", None));
+        }
+        if time_sync {
+            return Some(self.time_sync_reply(payload, seq, rx_ns));
+        }
+        let req = match decode_request(payload) {
+            Ok(req) => req,
+            Err(detail) => return Some(error_frame(seq, Status::DecodeError, detail, None)),
+        };
+
+        let t0 = self.now_ns();
+        let result = match self.config.faults.force_status {
+            Some(status) => Err(status),
+            None if self.config.firmware == Firmware::SumOnly && req.op != Op::Sum as i32 => {
+                Err(Status::UnsupportedOp)
+            }
+            None => evaluate_request(&req),
+        };
+        let compute_ns = self.now_ns() - t0;
+        Some(match result {
+            Ok(result) => encode_frame_v2(
+                &CalcResponse {
+                    result,
+                    r5_rx_ns: rx_ns,
+                    r5_tx_ns: self.now_ns(),
+                    r5_compute_ns: compute_ns,
+                    trace: req.trace,
+                },
+                seq,
+            ),
+            Err(status) => error_frame(seq, status, status_detail(status), req.trace),
+        })
+    }
+
+    fn time_sync_reply(&self, payload: &[u8], seq: u16, rx_ns: u64) -> Vec<u8> {
+        match nanopb_decode::<TimeSyncRequest>(payload) {
+            Ok(req) => encode_frame_v2(
+                &TimeSyncResponse {
+                    t1_ns: req.t1_ns,
+                    t2_ns: rx_ns,
+                    t3_ns: self.now_ns(),
+                },
+                seq,
+            ),
+            Err(detail) => error_frame(seq, Status::DecodeError, detail, None),
+        }
+    }
+
+    /// Answer frames from `transport` until the peer goes away.
+    pub async fn serve(&self, transport: &dyn Transport) -> io::Result<()> {
+        loop {
+            let frame = match transport.recv().await {
+                Ok(frame) => frame,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            let rx_ns = self.now_ns();
+            if !self.config.latency.is_zero() {
+                tokio::time::sleep(self.config.latency).await;
+            }
+            let Some(reply) = self.handle_frame_at(&frame, rx_ns) else {
+                continue;
+            };
+            transport.send(&reply).await?;
+        }
+    }
+
+    pub fn spawn(self, transport: impl Transport + 'static) -> JoinHandle<io::Result<()>> {
+        tokio::spawn(async move { self.serve(&transport).await })
+    }
+
+    /// Serve on one end of a [`MemoryTransport`] pair and return the other.
+    pub fn loopback(self) -> (MemoryTransport, JoinHandle<io::Result<()>>) {
+        let (host, r5) = MemoryTransport::pair();
+        (host, self.spawn(r5))
+    }
+}
+
+fn r5_reassembler() -> Reassembler {
+    Reassembler::new(ReassemblyConfig {
+        timeout: REASM_TIMEOUT,
+        max_len: REASM_MAX,
+        max_pending: REASM_MAX,
+    })
+}
+
+/// Decode as nanopb would, reporting failures with the matching
+/// `PB_GET_ERROR` text where there is one.
+fn nanopb_decode<M: WireMessage>(payload: &[u8]) -> Result<M, &'static str> {
+    let mut known = Vec::with_capacity(payload.len());
+    nanopb_check(payload, M::FIELDS, &mut known)?;
+    // What is left is what nanopb takes and prost does not: a string that
+    // is not UTF-8.
+    M::decode(known.as_slice()).map_err(|_| "io error")
+}
+
+/// Walk `buf` as `pb_decode` does, field by field against `fields`, and
+/// return the error it would stop at. The fields it keeps go to `out`;
+/// unknown ones are skipped, so prost never sees what nanopb would not.
+fn nanopb_check(buf: &[u8], fields: &[FieldDef], out: &mut Vec<u8>) -> Result<(), &'static str> {
+    let mut at = 0;
+    while at < buf.len() {
+        let key = nanopb_varint32(buf, &mut at)?;
+        if key >> 3 == 0 {
+            return Err("zero tag");
+        }
+        let wt = (key & 7) as u8;
+        let Some(def) = fields.iter().find(|f| f.tag == key >> 3) else {
+            nanopb_skip(buf, &mut at, wt)?;
+            continue;
+        };
+        if !dissect::kind_accepts(def.kind, wt) {
+            return Err("wrong wire type");
+        }
+        encode_varint(u64::from(key), out);
+        let start = at;
+        match def.kind {
+            // Enums without negative values are `UENUM`s, stored as uint32.
+            FieldKind::Uint32 | FieldKind::Op | FieldKind::Status => {
+                if nanopb_varint(buf, &mut at)? > u64::from(u32::MAX) {
+                    return Err("integer too large");
+                }
+            }
+            FieldKind::Uint64 => {
+                nanopb_varint(buf, &mut at)?;
+            }
+            FieldKind::Bytes | FieldKind::String => {
+                let len = nanopb_varint32(buf, &mut at)?;
+                encode_varint(u64::from(len), out);
+                out.extend_from_slice(nanopb_read(buf, &mut at, len as usize)?);
+                continue;
+            }
+            FieldKind::Message(_, inner) => {
+                let len = nanopb_varint32(buf, &mut at)? as usize;
+                if buf.len() - at < len {
+                    return Err("parent stream too short");
+                }
+                let mut msg = Vec::with_capacity(len);
+                nanopb_check(&buf[at..at + len], inner, &mut msg)?;
+                at += len;
+                encode_varint(msg.len() as u64, out);
+                out.extend_from_slice(&msg);
+                continue;
+            }
+        }
+        out.extend_from_slice(&buf[start..at]);
+    }
+    Ok(())
+}
+
+/// `pb_skip_field`, for fields not in the schema.
+fn nanopb_skip(buf: &[u8], at: &mut usize, wt: u8) -> Result<(), &'static str> {
+    match wt {
+        0 => while nanopb_read(buf, at, 1)?[0] & 0x80 != 0 {},
+        1 | 5 => {
+            nanopb_read(buf, at, if wt == 1 { 8 } else { 4 })?;
+        }
+        2 => {
+            let len = nanopb_varint32(buf, at)?;
+            nanopb_read(buf, at, len as usize)?;
+        }
+        // Groups included.
+        _ => return Err("invalid wire_type"),
+    }
+    Ok(())
+}
+
+fn nanopb_read<'a>(buf: &'a [u8], at: &mut usize, n: usize) -> Result<&'a [u8], &'static str> {
+    if buf.len() - *at < n {
+        return Err("end-of-stream");
+    }
+    *at += n;
+    Ok(&buf[*at - n..*at])
+}
+
+/// `pb_decode_varint32`, for tags and lengths: anything past 32 bits may
+/// only be zero or sign extension.
+fn nanopb_varint32(buf: &[u8], at: &mut usize) -> Result<u32, &'static str> {
+    let mut result = 0u32;
+    let mut bitpos = 0;
+    loop {
+        let byte = nanopb_read(buf, at, 1)?[0];
+        if bitpos >= 32 {
+            let sign_extension = if bitpos < 63 { 0xFF } else { 0x01 };
+            let valid = byte & 0x7F == 0 || (result >> 31 != 0 && byte == sign_extension);
+            if bitpos >= 64 || !valid {
+                return Err("varint overflow");
+            }
+        } else if bitpos == 28 {
+            if byte & 0x70 != 0 && byte & 0x78 != 0x78 {
+                return Err("varint overflow");
+            }
+            result |= u32::from(byte & 0x0F) << bitpos;
+        } else {
+            result |= u32::from(byte & 0x7F) << bitpos;
+        }
+        bitpos += 7;
+        if byte & 0x80 == 0 {
+            return Ok(result);
+        }
+    }
+}
+
+/// `pb_decode_varint`: at most ten bytes, the tenth no more than 1.
+fn nanopb_varint(buf: &[u8], at: &mut usize) -> Result<u64, &'static str> {
+    let mut result = 0u64;
+    let mut bitpos = 0;
+    loop {
+        let byte = nanopb_read(buf, at, 1)?[0];
+        if bitpos >= 63 && byte & 0xFE != 0 {
+            return Err("varint overflow");
+        }
+        result |= u64::from(byte & 0x7F) << bitpos;
+        bitpos += 7;
+        if byte & 0x80 == 0 {
+            return Ok(result);
+        }
+    }
+}
+
+/// [`nanopb_decode`] plus the limits of the fixed-size nanopb buffers.
+fn decode_request(payload: &[u8]) -> Result<CalcRequest, &'static str> {
+    let req = nanopb_decode::<CalcRequest>(payload)?;
+    if let Some(TraceCtx {
+        trace_id,
+        span_id,
+        tracestate,
+        ..
+    }) = &req.trace
+    {
+        if trace_id.len() > TRACE_ID_MAX || span_id.len() > SPAN_ID_MAX {
+            return Err("bytes overflow");
+        }
+        if tracestate.len() > TRACESTATE_MAX {
+            return Err("string overflow");
+        }
+    }
+    Ok(req)
+}
+
+fn status_detail(status: Status) -> &'static str {
+    match status {
+        Status::UnsupportedOp => "unsupported op",
+        Status::DivByZero => "division by zero",
+        Status::Overflow => "overflow",
+        Status::Ok | Status::DecodeError => "",
+    }
+}
+
+fn error_frame(seq: u16, code: Status, detail: &str, trace: Option<TraceCtx>) -> Vec<u8> {
+    let mut detail = detail.to_string();
+    detail.truncate(DETAIL_MAX);
+    let err = CalcError {
+        code: code as i32,
+        detail,
+        trace,
+    };
+    encode_frame_v2(&err, seq)
+}
@@ -0,0 +1,277 @@
+//! Fragmentation of v2 frames larger than the RPMsg MTU.
+//!
+//! An RPMsg buffer is 512 bytes, 496 of them payload, and the chardev hands
+//! over one buffer per read or write. A longer frame goes out as a run of v2
+//! frames with the original type and seq, flagged [`FLAG_FRAG_FIRST`],
+//! [`FLAG_FRAG_MIDDLE`] ... [`FLAG_FRAG_LAST`]. Each fragment's payload starts
+//! with `[index(2, BE)][total(2, BE)]`, the fragment number from 0 and the
+//! length of the original payload, followed by its share of that payload; the
+//! fragment's own checksum covers both. The receiver rebuilds the original
+//! frame with the fragment bits cleared from its flags.
+//!
+//! [`split`] and [`Reassembler`] do the work and [`FragmentingTransport`]
+//! puts them under any [`Transport`]. `r5/frag_reasm.c` is the R5 side.
+
+use std::collections::HashMap;
+use std::io;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+
+use crate::metrics::count_decode_error;
+use crate::transport::Transport;
+use crate::wire::{
+    self, FLAG_FRAG_FIRST, FLAG_FRAG_LAST, FLAG_FRAG_MASK, FLAG_FRAG_MIDDLE, FRAG_HEADER_LEN,
+};
+use crate::FrameError;
+
+/// Largest frame sent unfragmented: the payload of one RPMsg buffer.
+pub const DEFAULT_MTU: usize = wire::DEFAULT_MAX_FRAME_LEN;
+
+/// The frames to send for `frame`: itself if it fits in `mtu` bytes,
+/// otherwise its fragments. Only v2 frames can be fragmented.
+pub fn split(frame: &[u8], mtu: usize) -> Result<Vec<Vec<u8>>, FrameError> {
+    if frame.len() <= mtu {
+        return Ok(vec![frame.to_vec()]);
+    }
+    let f = wire::unwrap_any(frame)?;
+    if f.header.version != wire::PROTO_VERSION_V2 {
+        return Err(FrameError::TooLong(frame.len()));
+    }
+    if f.header.flags & FLAG_FRAG_MASK != 0 {
+        return Err(FrameError::Fragment("fragment larger than the mtu"));
+    }
+    let room = mtu
+        .checked_sub(wire::v2_frame_len(f.header.flags, FRAG_HEADER_LEN))
+        .filter(|&n| n > 0)
+        .ok_or(FrameError::Fragment("mtu too small"))?;
+    // The v2 length field bounds the payload, so index and total fit a u16.
+    let total = f.payload.len() as u16;
+    let chunks: Vec<&[u8]> = f.payload.chunks(room).collect();
+    let last = chunks.len() - 1;
+    Ok(chunks
+        .iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let pos = match i {
+                0 => FLAG_FRAG_FIRST,
+                i if i == last => FLAG_FRAG_LAST,
+                _ => FLAG_FRAG_MIDDLE,
+            };
+            let mut payload = Vec::with_capacity(FRAG_HEADER_LEN + chunk.len());
+            payload.extend_from_slice(&(i as u16).to_be_bytes());
+            payload.extend_from_slice(&total.to_be_bytes());
+            payload.extend_from_slice(chunk);
+            wire::wrap_v2(f.header.typ, f.header.flags | pos, f.header.seq, &payload)
+        })
+        .collect())
+}
+
+#[derive(Debug, Clone)]
+pub struct ReassemblyConfig {
+    /// A message whose fragments stop arriving is dropped after this long.
+    pub timeout: Duration,
+    /// Largest payload a message may reassemble to.
+    pub max_len: usize,
+    /// Bytes reserved across all partial messages.
+    pub max_pending: usize,
+}
+
+impl Default for ReassemblyConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_millis(500),
+            max_len: 16 * 1024,
+            max_pending: 64 * 1024,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Partial {
+    /// Flags of the original frame.
+    flags: u8,
+    total: usize,
+    next: u16,
+    buf: Vec<u8>,
+    started: Instant,
+}
+
+/// Rebuilds fragmented frames, one partial message per (type, seq).
+#[derive(Debug, Default)]
+pub struct Reassembler {
+    config: ReassemblyConfig,
+    partial: HashMap<(u8, u16), Partial>,
+    pending: usize,
+}
+
+impl Reassembler {
+    pub fn new(config: ReassemblyConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    /// Bytes reserved by partial messages.
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    /// Feed one received frame. Anything that is not a valid fragment is
+    /// returned as is; a fragment yields the rebuilt frame once the last one
+    /// is in. A fragment that does not fit the message in progress drops
+    /// that message.
+    pub fn push(&mut self, frame: Vec<u8>) -> Result<Option<Vec<u8>>, FrameError> {
+        self.push_at(frame, Instant::now())
+    }
+
+    /// [`push`](Self::push) with an explicit clock for the timeout.
+    pub fn push_at(&mut self, frame: Vec<u8>, now: Instant) -> Result<Option<Vec<u8>>, FrameError> {
+        let (header, payload) = match wire::unwrap_any(&frame) {
+            Ok(f) if f.header.flags & FLAG_FRAG_MASK != 0 => (f.header, f.payload.to_vec()),
+            _ => return Ok(Some(frame)),
+        };
+        self.expire(now);
+        if payload.len() < FRAG_HEADER_LEN {
+            return Err(FrameError::Fragment("short fragment header"));
+        }
+        let index = u16::from_be_bytes([payload[0], payload[1]]);
+        let total = usize::from(u16::from_be_bytes([payload[2], payload[3]]));
+        let chunk = &payload[FRAG_HEADER_LEN..];
+        let key = (header.typ, header.seq);
+        let pos = header.flags & FLAG_FRAG_MASK;
+
+        match pos {
+            FLAG_FRAG_FIRST => {
+                if index != 0 {
+                    return Err(FrameError::Fragment("first fragment not at index 0"));
+                }
+                if total > self.config.max_len {
+                    return Err(FrameError::Fragment("message too large"));
+                }
+                // A restarted message replaces the one in progress.
+                self.remove(key);
+                if self.pending + total > self.config.max_pending {
+                    return Err(FrameError::Fragment("reassembly buffers full"));
+                }
+                self.pending += total;
+                self.partial.insert(
+                    key,
+                    Partial {
+                        flags: header.flags & !FLAG_FRAG_MASK,
+                        total,
+                        next: 0,
+                        buf: Vec::with_capacity(total),
+                        started: now,
+                    },
+                );
+            }
+            FLAG_FRAG_MIDDLE | FLAG_FRAG_LAST => {}
+            _ => return Err(FrameError::Fragment("conflicting fragment flags")),
+        }
+
+        let Some(p) = self.partial.get_mut(&key) else {
+            return Err(FrameError::Fragment("no first fragment"));
+        };
+        if index != p.next || total != p.total || p.buf.len() + chunk.len() > p.total {
+            self.remove(key);
+            return Err(FrameError::Fragment("fragment out of sequence"));
+        }
+        p.buf.extend_from_slice(chunk);
+        p.next = p.next.wrapping_add(1);
+        if pos != FLAG_FRAG_LAST {
+            return Ok(None);
+        }
+
+        let p = self.remove(key).expect("checked above");
+        if p.buf.len() != p.total {
+            return Err(FrameError::Fragment("message ended short"));
+        }
+        Ok(Some(wire::wrap_v2(header.typ, p.flags, header.seq, &p.buf)))
+    }
+
+    /// Drop messages started more than the timeout before `now`; returns how
+    /// many went.
+    pub fn expire(&mut self, now: Instant) -> usize {
+        let timeout = self.config.timeout;
+        let stale: Vec<_> = self
+            .partial
+            .iter()
+            .filter(|(_, p)| now.saturating_duration_since(p.started) > timeout)
+            .map(|(&key, _)| key)
+            .collect();
+        for &(typ, seq) in &stale {
+            tracing::debug!(typ, seq, "reassembly timed out");
+            self.remove((typ, seq));
+        }
+        stale.len()
+    }
+
+    fn remove(&mut self, key: (u8, u16)) -> Option<Partial> {
+        let p = self.partial.remove(&key)?;
+        self.pending -= p.total;
+        Some(p)
+    }
+}
+
+/// Splits outgoing frames above `mtu` and reassembles incoming fragments.
+/// Fragments that cannot be reassembled are logged, counted as `fragment`
+/// decode errors and dropped.
+pub struct FragmentingTransport<T> {
+    inner: T,
+    mtu: usize,
+    // Held while a fragment run is sent, so runs never interleave; the R5
+    // reassembles one message at a time.
+    send_run: tokio::sync::Mutex<()>,
+    reassembler: std::sync::Mutex<Reassembler>,
+}
+
+impl<T: Transport> FragmentingTransport<T> {
+    pub fn new(inner: T, mtu: usize) -> Self {
+        Self::with_config(inner, mtu, ReassemblyConfig::default())
+    }
+
+    pub fn with_config(inner: T, mtu: usize, config: ReassemblyConfig) -> Self {
+        Self {
+            inner,
+            mtu,
+            send_run: tokio::sync::Mutex::new(()),
+            reassembler: std::sync::Mutex::new(Reassembler::new(config)),
+        }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+#[async_trait]
+impl<T: Transport> Transport for FragmentingTransport<T> {
+    async fn send(&self, frame: &[u8]) -> io::Result<()> {
+        let frames =
+            split(frame, self.mtu).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        if let [single] = frames.as_slice() {
+            return self.inner.send(single).await;
+        }
+        let _run = self.send_run.lock().await;
+        for f in &frames {
+            self.inner.send(f).await?;
+        }
+        Ok(())
+    }
+
+    async fn recv(&self) -> io::Result<Vec<u8>> {
+        loop {
+            let frame = self.inner.recv().await?;
+            match self.reassembler.lock().unwrap().push(frame) {
+                Ok(Some(frame)) => return Ok(frame),
+                Ok(None) => {}
+                Err(e) => {
+                    tracing::debug!(error = %e, "dropping fragment");
+                    count_decode_error(&e);
+                }
+            }
+        }
+    }
+}
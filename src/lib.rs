@@ -1,11 +1,9 @@
-use prost::Message;
-
 pub mod proto {
     // Generated by prost-build in build.rs
     include!(concat!(env!("OUT_DIR"), "/rpmsg.calc.v1.rs"));
 }
 
-#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+#[derive(Debug, Clone, thiserror::Error, PartialEq, Eq)]
 pub enum FrameError {
     #[error("unknown version {0:#04x}")]
     UnknownVersion(u8),
@@ -17,103 +15,136 @@ pub enum FrameError {
     TooShort,
     #[error("decode error")]
     Decode,
+    #[error("no sync word")]
+    NoSync,
+    #[error("header crc mismatch")]
+    HeaderCrc,
+    #[error("frame too long ({0} bytes)")]
+    TooLong(usize),
+    /// A fragment that cannot be reassembled, or a fragment where a whole
+    /// frame was expected.
+    #[error("fragment: {0}")]
+    Fragment(&'static str),
+    /// Not authenticated with the link key, or a replay; see [`auth`].
+    #[error("auth: {0}")]
+    Auth(&'static str),
+    /// Over one of the [`limits::DecodeLimits`]; nothing was decoded.
+    #[error("{what} {actual} over limit {limit}")]
+    LimitExceeded {
+        what: &'static str,
+        limit: usize,
+        actual: usize,
+    },
+    /// Decoded, but failed [`validate::Validate`].
+    #[error("invalid message: {}", validate::join(.0))]
+    Invalid(Vec<validate::ValidationError>),
+    /// The R5 received the request and rejected it with a `CalcError` frame.
+    #[error("remote error {code}: {detail}")]
+    RemoteError { code: i32, detail: String },
 }
 
-pub mod wire {
-    use super::FrameError;
-
-    // Protocol constants (SYNC is audit-visible; v1 does not use it on the wire)
-    pub const SYNC: u16 = 0xA55A;
-    pub const PROTO_VERSION: u8 = 1;
-    pub const TYPE_REQ: u8 = 1;
-    pub const TYPE_RESP: u8 = 2;
-
-    #[inline]
-    pub fn crc32(data: &[u8]) -> u32 {
-        crc32fast::hash(data)
-    }
-
-    fn wrap_v1_typed(typ: u8, payload: &[u8]) -> Vec<u8> {
-        // v1 frame = [ver=1][type][payload...][crc32(payload) little-endian]
-        let mut frame = Vec::with_capacity(1 + 1 + payload.len() + 4);
-        frame.push(PROTO_VERSION);
-        frame.push(typ);
-        frame.extend_from_slice(payload);
-        frame.extend_from_slice(&crc32(payload).to_le_bytes());
-        frame
-    }
-
-    pub fn wrap_v1_req(payload: &[u8]) -> Vec<u8> {
-        wrap_v1_typed(TYPE_REQ, payload)
-    }
-    pub fn wrap_v1_resp(payload: &[u8]) -> Vec<u8> {
-        wrap_v1_typed(TYPE_RESP, payload)
-    }
-
-    fn unwrap_v1_typed(frame: &[u8], expect_typ: u8) -> Result<&[u8], FrameError> {
-        // Need at least: ver(1) + type(1) + crc(4) = 6 bytes
-        if frame.len() < 6 {
-            return Err(FrameError::TooShort);
-        }
-        let ver = frame[0];
-        if ver != PROTO_VERSION {
-            return Err(FrameError::UnknownVersion(ver));
-        }
-        let typ_actual = frame[1];
-        if typ_actual != expect_typ {
-            return Err(FrameError::UnknownType(typ_actual));
-        }
-
-        let payload_len = frame.len() - 2 - 4;
-        let (payload, crc_bytes) = frame[2..].split_at(payload_len);
-        let got = u32::from_le_bytes(crc_bytes.try_into().unwrap());
-        let want = crc32(payload);
-        if got != want {
-            return Err(FrameError::Crc);
+impl FrameError {
+    /// Short stable label for logs, JSON and metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            FrameError::UnknownVersion(_) => "unknown_version",
+            FrameError::UnknownType(_) => "unknown_type",
+            FrameError::Crc => "crc",
+            FrameError::TooShort => "too_short",
+            FrameError::Decode => "decode",
+            FrameError::NoSync => "no_sync",
+            FrameError::HeaderCrc => "header_crc",
+            FrameError::TooLong(_) => "too_long",
+            FrameError::Fragment(_) => "fragment",
+            FrameError::Auth(_) => "auth",
+            FrameError::LimitExceeded { .. } => "limit_exceeded",
+            FrameError::Invalid(_) => "invalid",
+            FrameError::RemoteError { .. } => "remote_error",
         }
-        Ok(payload)
     }
+}
 
-    pub fn unwrap_v1_req(frame: &[u8]) -> Result<&[u8], FrameError> {
-        unwrap_v1_typed(frame, TYPE_REQ)
-    }
-    pub fn unwrap_v1_resp(frame: &[u8]) -> Result<&[u8], FrameError> {
-        unwrap_v1_typed(frame, TYPE_RESP)
+impl From<proto::CalcError> for FrameError {
+    fn from(err: proto::CalcError) -> Self {
+        FrameError::RemoteError {
+            code: err.code,
+            detail: err.detail,
+        }
     }
 }
 
-pub fn encode_calc_request(a: u32, b: u32) -> Vec<u8> {
-    use crate::proto::{CalcRequest, Op};
+pub mod auth;
+pub mod calc;
+pub mod checksum;
+pub mod client;
+pub mod clocksync;
+pub mod dissect;
+pub mod emulator;
+pub mod fragment;
+pub mod limits;
+pub mod message;
+pub mod metrics;
+pub mod mux;
+pub mod remoteproc;
+pub mod rpmsg;
+pub mod server;
+pub mod telemetry;
+pub mod timing;
+pub mod trace_context;
+pub mod transport;
+pub mod validate;
+pub mod wire;
+
+pub use limits::DecodeLimits;
+pub use message::{
+    decode_any, decode_any_with_limits, decode_frame, decode_frame_with_limits, decode_reply,
+    decode_reply_with_limits, encode_frame, encode_frame_v2, AnyMessage, WireMessage,
+};
+
+/// Encode a `CalcRequest` for `op` (see [`calc::evaluate`] for semantics).
+pub fn encode_calc(op: crate::proto::Op, a: u32, b: u32) -> Vec<u8> {
+    use crate::proto::CalcRequest;
     let req = CalcRequest {
         a,
         b,
-        op: Op::Sum as i32,
+        op: op as i32,
         trace: None,
     };
-    let mut payload = Vec::new();
-    req.encode(&mut payload).expect("encode");
-    wire::wrap_v1_req(&payload)
+    encode_frame(&req)
+}
+
+pub fn encode_calc_request(a: u32, b: u32) -> Vec<u8> {
+    encode_calc(crate::proto::Op::Sum, a, b)
 }
 
 pub fn encode_calc_response(sum: u32) -> Vec<u8> {
     use crate::proto::CalcResponse;
     let resp = CalcResponse {
         result: sum,
+        ..Default::default()
+    };
+    encode_frame(&resp)
+}
+
+/// Encode a `CalcError` reply, as the R5 sends when it rejects a request.
+pub fn encode_calc_error(code: crate::proto::Status, detail: &str) -> Vec<u8> {
+    use crate::proto::CalcError;
+    let err = CalcError {
+        code: code as i32,
+        detail: detail.to_string(),
         trace: None,
     };
-    let mut payload = Vec::new();
-    resp.encode(&mut payload).expect("encode");
-    wire::wrap_v1_resp(&payload)
+    encode_frame(&err)
 }
 
 pub fn decode_calc_request(frame: &[u8]) -> Result<crate::proto::CalcRequest, FrameError> {
-    let payload = wire::unwrap_v1_req(frame)?;
-    crate::proto::CalcRequest::decode(payload).map_err(|_| FrameError::Decode)
+    decode_frame(frame)
 }
 
+/// Decode a `CalcResponse`; an R5 `CalcError` frame becomes
+/// [`FrameError::RemoteError`].
 pub fn decode_calc_response(frame: &[u8]) -> Result<crate::proto::CalcResponse, FrameError> {
-    let payload = wire::unwrap_v1_resp(frame)?;
-    crate::proto::CalcResponse::decode(payload).map_err(|_| FrameError::Decode)
+    decode_reply(frame)
 }
 
 // Legacy ABI kept for old callers
@@ -128,7 +159,7 @@ pub fn encode_calc_request_with_trace(a: u32, b: u32) -> (Vec<u8>, Vec<u8>, u64)
 }
 
 /// Header-only validator used by compat tests.
-/// Checks just [ver][type] and *does not* require CRC.
+/// Checks just [ver][type] (v1 or v2) and *does not* require CRC.
 pub fn guard_header(mut frame: &[u8]) -> Result<(u8, u8), FrameError> {
     while !frame.is_empty() && (frame[0] == 0xA5 || frame[0] == 0x5A) {
         frame = &frame[1..];
@@ -137,7 +168,7 @@ pub fn guard_header(mut frame: &[u8]) -> Result<(u8, u8), FrameError> {
         return Err(FrameError::TooShort);
     }
     let ver = frame[0];
-    if ver != crate::wire::PROTO_VERSION {
+    if ver != crate::wire::PROTO_VERSION && ver != crate::wire::PROTO_VERSION_V2 {
         return Err(FrameError::UnknownVersion(ver));
     }
 
@@ -145,32 +176,23 @@ pub fn guard_header(mut frame: &[u8]) -> Result<(u8, u8), FrameError> {
         return Err(FrameError::TooShort);
     }
     let typ = frame[1];
-    if typ != crate::wire::TYPE_REQ && typ != crate::wire::TYPE_RESP {
+    if !message::is_registered(typ) {
         return Err(FrameError::UnknownType(typ));
     }
     Ok((ver, typ))
 }
 
+/// A `TraceCtx` for a new request: the current span's ids when it is
+/// exported over OpenTelemetry, otherwise a new sampled trace with random ids.
+pub fn new_trace_ctx() -> crate::proto::TraceCtx {
+    telemetry::current_trace_ctx().unwrap_or_else(|| crate::proto::TraceCtx::random(true))
+}
+
 /// Build a CalcRequest with a TraceCtx and return (framed_request, trace_ctx).
-/// Uses a time-based ID so no extra dependencies are required.
 pub fn encode_calc_request_with_trace_ctx(a: u32, b: u32) -> (Vec<u8>, crate::proto::TraceCtx) {
-    use crate::proto::{CalcRequest, Op, TraceCtx};
-    use prost::Message;
-
-    // Derive a stable-ish ID from time (u128 -> 16B trace_id, low 8B as span_id)
-    let ts = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .expect("time")
-        .as_nanos();
-    let trace_u128 = ts;
-    let span_u64 = ts as u64;
-
-    let trace = TraceCtx {
-        trace_id: trace_u128.to_le_bytes().to_vec(), // 16 bytes
-        span_id: span_u64.to_le_bytes().to_vec(),    // 8 bytes
-        flags: 0,
-    };
+    use crate::proto::{CalcRequest, Op};
 
+    let trace = new_trace_ctx();
     let req = CalcRequest {
         a,
         b,
@@ -178,7 +200,5 @@ pub fn encode_calc_request_with_trace_ctx(a: u32, b: u32) -> (Vec<u8>, crate::pr
         trace: Some(trace.clone()),
     };
 
-    let mut payload = Vec::new();
-    req.encode(&mut payload).expect("encode");
-    (crate::wire::wrap_v1_req(&payload), trace)
+    (encode_frame(&req), trace)
 }
@@ -0,0 +1,140 @@
+//! Bounds on what decoding a frame may cost, checked before prost runs.
+//!
+//! prost allocates whatever a length prefix asks for and follows nested
+//! messages and groups down to its own recursion limit of 100, so a corrupt
+//! or hostile peer decides how much memory and CPU a frame takes.
+//! [`DecodeLimits`] walks the frame first, against the field schema its type
+//! is registered with ([`crate::message::schema`]), and refuses it with
+//! [`FrameError::LimitExceeded`] if anything is over a limit. Malformed protobuf is left for prost to report.
+//! The same struct carries how the decoded message is then validated.
+
+use crate::dissect::{read_varint, FieldDef, FieldKind};
+use crate::validate::Validation;
+use crate::{wire, FrameError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeLimits {
+    /// Largest frame, header and CRC included.
+    pub max_frame_len: usize,
+    /// Longest `bytes` or `string` field, known or not.
+    pub max_bytes_len: usize,
+    /// Deepest nesting of messages and groups; the frame's own message is 1.
+    pub max_depth: usize,
+    /// Skip fields that are not in the schema, as prost does, or refuse them.
+    pub allow_unknown_fields: bool,
+    /// What to do with a message that decodes but fails validation.
+    pub validation: Validation,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self {
+            // The largest message `ReassemblyConfig::default()` rebuilds.
+            max_frame_len: 16 * 1024 + wire::V2_HEADER_LEN + 4,
+            // W3C caps tracestate at 512 bytes; nothing else we send is longer.
+            max_bytes_len: 512,
+            max_depth: 8,
+            allow_unknown_fields: true,
+            validation: Validation::Strict,
+        }
+    }
+}
+
+impl DecodeLimits {
+    /// Whatever prost accepts, unvalidated.
+    pub fn unlimited() -> Self {
+        Self {
+            max_frame_len: usize::MAX,
+            max_bytes_len: usize::MAX,
+            max_depth: usize::MAX,
+            allow_unknown_fields: true,
+            validation: Validation::Off,
+        }
+    }
+
+    pub fn check_frame(&self, frame: &[u8]) -> Result<(), FrameError> {
+        exceeds("frame length", self.max_frame_len, frame.len())
+    }
+
+    /// Check `payload` as a message with fields `schema`.
+    pub fn check_payload(&self, payload: &[u8], schema: &[FieldDef]) -> Result<(), FrameError> {
+        let unknown = self.check_message(payload, schema, 1)?;
+        if !self.allow_unknown_fields {
+            exceeds("unknown fields", 0, unknown)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the number of unknown fields.
+    fn check_message(
+        &self,
+        buf: &[u8],
+        schema: &[FieldDef],
+        depth: usize,
+    ) -> Result<usize, FrameError> {
+        exceeds("nesting depth", self.max_depth, depth)?;
+        let mut unknown = 0;
+        // Groups opened inside this message; their fields are never known.
+        let mut groups = 0;
+        let mut at = 0;
+        while at < buf.len() {
+            let Some((key, n)) = read_varint(buf, at) else {
+                break;
+            };
+            at += n;
+            let def = match groups {
+                0 => schema.iter().find(|f| u64::from(f.tag) == key >> 3),
+                _ => None,
+            };
+            if def.is_none() && groups == 0 && key & 7 != 4 {
+                unknown += 1;
+            }
+            let skip = match key & 7 {
+                0 => read_varint(buf, at).map(|(_, n)| n),
+                1 => Some(8),
+                5 => Some(4),
+                2 => {
+                    let Some((len, n)) = read_varint(buf, at) else {
+                        break;
+                    };
+                    let len = usize::try_from(len).unwrap_or(usize::MAX);
+                    at += n;
+                    let data = at.checked_add(len).and_then(|end| buf.get(at..end));
+                    match (def.map(|f| f.kind), data) {
+                        (Some(FieldKind::Message(_, fields)), Some(data)) => {
+                            self.check_message(data, fields, depth + 1)?;
+                        }
+                        _ => exceeds("bytes field length", self.max_bytes_len, len)?,
+                    }
+                    data.map(<[u8]>::len)
+                }
+                3 => {
+                    groups += 1;
+                    exceeds("nesting depth", self.max_depth, depth + groups)?;
+                    Some(0)
+                }
+                4 if groups > 0 => {
+                    groups -= 1;
+                    Some(0)
+                }
+                _ => None,
+            };
+            match skip {
+                Some(n) => at += n,
+                None => break,
+            }
+        }
+        Ok(unknown)
+    }
+}
+
+fn exceeds(what: &'static str, limit: usize, actual: usize) -> Result<(), FrameError> {
+    if actual > limit {
+        return Err(FrameError::LimitExceeded {
+            what,
+            limit,
+            actual,
+        });
+    }
+    Ok(())
+}
@@ -1,16 +1,46 @@
 use std::env;
+use std::io;
+use std::os::unix::fs::FileTypeExt;
 use std::process;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use linux_gateway::auth::{AuthKey, AuthTransport};
+use linux_gateway::checksum::{ChecksumKind, ChecksumTransport};
+use linux_gateway::client::{ClientConfig, GatewayClient};
+use linux_gateway::clocksync::{self, SyncConfig};
+use linux_gateway::emulator::{R5Config, R5Peer};
+use linux_gateway::fragment::{self, FragmentingTransport};
+use linux_gateway::mux::{Mux, MuxConfig};
+use linux_gateway::proto::{CalcRequest, Op, TraceCtx};
+use linux_gateway::server::AppState;
+use linux_gateway::telemetry;
+use linux_gateway::timing::{LatencyBreakdown, R5Timing};
+use linux_gateway::transport::{RpmsgTransport, TcpTransport, Transport, UnixTransport};
+use linux_gateway::{wire, DecodeLimits, FrameError};
+use serde_json::{json, Value};
 
 const HELP: &str = "Usage:
-  linux_gateway --decode HEX
+  linux_gateway --decode HEX [--json]
+  linux_gateway dissect <HEX>
   linux_gateway make-resp <SUM>
   linux_gateway make-req-trace <A> <B>
-  linux_gateway rpmsg-bounce <HEX>
+  linux_gateway send <A> <B> [sum|sub|mul|div] [LINK] [--timeout MS] [--v1]
+  linux_gateway send --hex HEX [LINK] [--timeout MS]
+  linux_gateway rpmsg-bounce <HEX> [DEV] [--timeout MS]
+  linux_gateway serve [--listen ADDR] [LINK] [--timeout MS] [--sync-interval MS]
+  linux_gateway mux [--socket PATH] [LINK] [--max-in-flight N] [--per-client N]
+                    [--timeout MS]
+
+LINK is --device PATH (default /dev/rpmsg0), --unix PATH, --tcp HOST:PORT
+or --emulate (in-process R5 emulator), optionally with
+--checksum crc32|crc32c|crc16|none (v2 payload checksum, default crc32) and
+--auth-key-file PATH (authenticate v2 frames with the hex key in PATH).
   linux_gateway --version
 ";
 
 fn parse_hex(s: &str) -> Result<Vec<u8>, ()> {
-    if s.len() % 2 != 0 {
+    if !s.len().is_multiple_of(2) {
         return Err(());
     }
     let mut out = Vec::with_capacity(s.len() / 2);
@@ -25,6 +55,495 @@ fn parse_hex(s: &str) -> Result<Vec<u8>, ()> {
     Ok(out)
 }
 
+/// Hex from the command line, with an optional `0x` prefix.
+fn parse_frame_hex(s: &str) -> Result<Vec<u8>, ()> {
+    let s = s.trim();
+    parse_hex(
+        s.strip_prefix("0x")
+            .or_else(|| s.strip_prefix("0X"))
+            .unwrap_or(s),
+    )
+}
+
+/// Exit status for `--decode` failures; 2 is reserved for usage/bad hex.
+fn exit_code(err: &FrameError) -> i32 {
+    match err {
+        FrameError::UnknownVersion(_) => 10,
+        FrameError::UnknownType(_) => 11,
+        FrameError::Crc => 12,
+        FrameError::TooShort => 13,
+        FrameError::Decode => 14,
+        FrameError::NoSync => 15,
+        FrameError::HeaderCrc => 16,
+        FrameError::TooLong(_) => 17,
+        FrameError::RemoteError { .. } => 18,
+        FrameError::Fragment(_) => 19,
+        FrameError::LimitExceeded { .. } => 20,
+        FrameError::Invalid(_) => 21,
+        FrameError::Auth(_) => 22,
+    }
+}
+
+fn print_human(fields: &Value) {
+    for (key, val) in fields.as_object().unwrap() {
+        match val {
+            Value::Null => {}
+            Value::Object(inner) => {
+                for (k, v) in inner {
+                    println!("{key}.{k}: {}", plain(v));
+                }
+            }
+            v => println!("{key}: {}", plain(v)),
+        }
+    }
+}
+
+fn plain(v: &Value) -> String {
+    match v {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+enum Link {
+    Device(String),
+    Unix(String),
+    Tcp(String),
+    Emulator,
+}
+
+impl Link {
+    /// Open the link. v2 frames are sent with `checksum`, authenticated with
+    /// `auth` if set, and fragmented above one RPMsg buffer.
+    async fn open(
+        &self,
+        checksum: ChecksumKind,
+        auth: Option<AuthKey>,
+    ) -> io::Result<Box<dyn Transport>> {
+        let link: Box<dyn Transport> = match self {
+            Link::Device(path) => Box::new(RpmsgTransport::open_rpmsg(path)?),
+            Link::Unix(path) => Box::new(UnixTransport::connect_unix(path).await?),
+            Link::Tcp(addr) => Box::new(TcpTransport::connect_tcp(addr.as_str()).await?),
+            Link::Emulator => {
+                let config = R5Config {
+                    clock: true,
+                    auth: auth.clone(),
+                    ..Default::default()
+                };
+                Box::new(R5Peer::new(config).loopback().0)
+            }
+        };
+        let link = FragmentingTransport::new(link, fragment::DEFAULT_MTU);
+        Ok(match auth {
+            Some(key) => Box::new(ChecksumTransport::new(
+                AuthTransport::new(link, key),
+                checksum,
+            )),
+            None => Box::new(ChecksumTransport::new(link, checksum)),
+        })
+    }
+}
+
+/// Parse a `--checksum` value.
+fn parse_checksum(name: &str) -> Result<ChecksumKind, String> {
+    ChecksumKind::from_name(name).ok_or(format!("unknown checksum {name:?}"))
+}
+
+/// Read an `--auth-key-file`.
+fn load_key(path: &str) -> Result<AuthKey, String> {
+    AuthKey::load(path).map_err(|e| format!("auth key {path}: {e}"))
+}
+
+struct SendArgs {
+    frame: Vec<u8>,
+    /// Trace carried by the request, to check the echo against.
+    trace: Option<TraceCtx>,
+    link: Link,
+    checksum: ChecksumKind,
+    auth: Option<AuthKey>,
+    timeout: Duration,
+}
+
+/// Parse `send` / `rpmsg-bounce` arguments (everything after the command).
+fn parse_send(args: &[String], bounce: bool) -> Result<SendArgs, String> {
+    let mut link = Link::Device("/dev/rpmsg0".into());
+    let mut checksum = ChecksumKind::default();
+    let mut auth = None;
+    let mut timeout = Duration::from_millis(1000);
+    let mut v1 = false;
+    let mut hex = None;
+    let mut positional = Vec::new();
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let mut value = |name: &str| it.next().cloned().ok_or(format!("{name} needs a value"));
+        match arg.as_str() {
+            "--device" => link = Link::Device(value("--device")?),
+            "--unix" => link = Link::Unix(value("--unix")?),
+            "--tcp" => link = Link::Tcp(value("--tcp")?),
+            "--emulate" => link = Link::Emulator,
+            "--checksum" => checksum = parse_checksum(&value("--checksum")?)?,
+            "--auth-key-file" => auth = Some(load_key(&value("--auth-key-file")?)?),
+            "--hex" => hex = Some(value("--hex")?),
+            "--v1" => v1 = true,
+            "--timeout" => {
+                let ms = value("--timeout")?;
+                let ms: u64 = ms.parse().map_err(|_| format!("invalid timeout {ms:?}"))?;
+                timeout = Duration::from_millis(ms);
+            }
+            flag if flag.starts_with("--") => return Err(format!("unknown option {flag}")),
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    if bounce {
+        // rpmsg-bounce <HEX> [DEV]
+        let [frame, dev @ ..] = positional.as_slice() else {
+            return Err("missing HEX argument".into());
+        };
+        hex = Some(frame.clone());
+        if let [dev] = dev {
+            link = Link::Device(dev.clone());
+        }
+    }
+
+    let (frame, trace) = match hex {
+        Some(hex) => {
+            let frame = parse_frame_hex(&hex).map_err(|()| "invalid hex".to_string())?;
+            // The caller's own frame: read its trace as it is.
+            let as_is = DecodeLimits::unlimited();
+            let trace = linux_gateway::decode_frame_with_limits::<CalcRequest>(&frame, &as_is)
+                .ok()
+                .and_then(|req| req.trace);
+            (frame, trace)
+        }
+        None => {
+            let [a, b, rest @ ..] = positional.as_slice() else {
+                return Err("expected <A> <B> [OP] or --hex HEX".into());
+            };
+            let a: u32 = a.parse().map_err(|_| format!("invalid A {a:?}"))?;
+            let b: u32 = b.parse().map_err(|_| format!("invalid B {b:?}"))?;
+            let op = match rest {
+                [] => Op::Sum,
+                [op] => linux_gateway::calc::parse_op(op).ok_or(format!("unknown op {op:?}"))?,
+                _ => return Err("too many arguments".into()),
+            };
+            let trace = linux_gateway::new_trace_ctx();
+            let req = CalcRequest {
+                op: op as i32,
+                a,
+                b,
+                trace: Some(trace.clone()),
+            };
+            let frame = if v1 {
+                linux_gateway::encode_frame(&req)
+            } else {
+                linux_gateway::encode_frame_v2(&req, 1)
+            };
+            (frame, Some(trace))
+        }
+    };
+    // v1 frames have no flags to carry a tag; do not send them in the clear.
+    if auth.is_some() && !frame.starts_with(&wire::SYNC.to_be_bytes()) {
+        return Err("--auth-key-file needs a v2 frame (not --v1)".into());
+    }
+    Ok(SendArgs {
+        frame,
+        trace,
+        link,
+        checksum,
+        auth,
+        timeout,
+    })
+}
+
+/// Write the request, wait for the matching reply and report it.
+/// Exit codes: 3 link error, 4 timeout, 5 peer closed, 10.. as for `--decode`.
+async fn run_send(args: SendArgs) -> i32 {
+    let transport = match args.link.open(args.checksum, args.auth).await {
+        Ok(t) => t,
+        Err(e) => {
+            eprintln!("send: open link: {e}");
+            return 3;
+        }
+    };
+    // v2 replies echo the request seq; v1 has none to match.
+    let want_seq = wire::unwrap_any(&args.frame)
+        .ok()
+        .filter(|f| f.header.version == wire::PROTO_VERSION_V2)
+        .map(|f| f.header.seq);
+
+    let start = Instant::now();
+    let deadline = tokio::time::Instant::from_std(start + args.timeout);
+    if let Err(e) = transport.send(&args.frame).await {
+        eprintln!("send: write: {e}");
+        return 3;
+    }
+    let reply = loop {
+        let frame = match tokio::time::timeout_at(deadline, transport.recv()).await {
+            Err(_) => {
+                eprintln!("send: no reply within {:?}", args.timeout);
+                return 4;
+            }
+            Ok(Err(e)) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                eprintln!("send: peer closed the link");
+                return 5;
+            }
+            Ok(Err(e)) => {
+                eprintln!("send: read: {e}");
+                return 3;
+            }
+            Ok(Ok(frame)) => frame,
+        };
+        let seq = wire::unwrap_any(&frame).ok().map(|f| f.header.seq);
+        match want_seq {
+            Some(want) if seq.is_some() && seq != Some(want) => continue,
+            _ => break frame,
+        }
+    };
+    let rtt = start.elapsed();
+
+    match linux_gateway::decode_calc_response(&reply) {
+        Ok(resp) => {
+            let trace = match (&args.trace, &resp.trace) {
+                (None, _) => "not sent",
+                (Some(_), None) => "missing",
+                (Some(sent), Some(got)) if sent == got => "echoed",
+                (Some(_), Some(_)) => "MISMATCH",
+            };
+            println!("result: {}", resp.result);
+            println!("rtt_us: {}", rtt.as_micros());
+            if let Some(r5) = R5Timing::from_response(&resp) {
+                let b = LatencyBreakdown::new(rtt, &r5);
+                println!(
+                    "latency_us: queueing={} processing={} transit={}",
+                    b.queueing.as_micros(),
+                    b.processing.as_micros(),
+                    b.transit.as_micros()
+                );
+            }
+            println!("trace: {trace}");
+            0
+        }
+        Err(e) => {
+            eprintln!("send: {e} (after {} us)", rtt.as_micros());
+            exit_code(&e)
+        }
+    }
+}
+
+fn send_command(args: &[String], bounce: bool) -> ! {
+    let name = if bounce { "rpmsg-bounce" } else { "send" };
+    let args = parse_send(args, bounce).unwrap_or_else(|e| {
+        eprintln!("{name}: {e}");
+        process::exit(2);
+    });
+    let rt = tokio::runtime::Runtime::new().expect("tokio runtime");
+    process::exit(rt.block_on(run_send(args)));
+}
+
+fn serve_command(args: &[String]) -> ! {
+    let mut listen = "127.0.0.1:8080".to_string();
+    let mut link = Link::Device("/dev/rpmsg0".into());
+    let mut checksum = ChecksumKind::default();
+    let mut auth = None;
+    let mut config = ClientConfig::default();
+    let mut sync = Some(SyncConfig::default());
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        if arg == "--emulate" {
+            link = Link::Emulator;
+            continue;
+        }
+        let Some(value) = it.next().cloned() else {
+            eprintln!("serve: {arg} needs a value");
+            process::exit(2);
+        };
+        match arg.as_str() {
+            "--listen" => listen = value,
+            "--device" => link = Link::Device(value),
+            "--unix" => link = Link::Unix(value),
+            "--tcp" => link = Link::Tcp(value),
+            "--checksum" => match parse_checksum(&value) {
+                Ok(kind) => checksum = kind,
+                Err(e) => {
+                    eprintln!("serve: {e}");
+                    process::exit(2);
+                }
+            },
+            "--auth-key-file" => match load_key(&value) {
+                Ok(key) => auth = Some(key),
+                Err(e) => {
+                    eprintln!("serve: {e}");
+                    process::exit(2);
+                }
+            },
+            "--timeout" => match value.parse() {
+                Ok(ms) => config.timeout = Duration::from_millis(ms),
+                Err(_) => {
+                    eprintln!("serve: invalid timeout {value:?}");
+                    process::exit(2);
+                }
+            },
+            "--sync-interval" => match value.parse() {
+                Ok(0) => sync = None,
+                Ok(ms) => {
+                    sync = Some(SyncConfig {
+                        interval: Duration::from_millis(ms),
+                        ..Default::default()
+                    })
+                }
+                Err(_) => {
+                    eprintln!("serve: invalid sync interval {value:?}");
+                    process::exit(2);
+                }
+            },
+            _ => {
+                eprintln!("serve: unknown option {arg}");
+                process::exit(2);
+            }
+        }
+    }
+
+    let rt = tokio::runtime::Runtime::new().expect("tokio runtime");
+    let code = rt.block_on(async move {
+        let _telemetry = match telemetry::init("linux_gateway") {
+            Ok(t) => t,
+            Err(e) => {
+                eprintln!("telemetry: {e}");
+                return 3;
+            }
+        };
+        let transport = match link.open(checksum, auth).await {
+            Ok(t) => t,
+            Err(e) => {
+                eprintln!("serve: open link: {e}");
+                return 3;
+            }
+        };
+        let listener = match tokio::net::TcpListener::bind(&listen).await {
+            Ok(l) => l,
+            Err(e) => {
+                eprintln!("serve: bind {listen}: {e}");
+                return 3;
+            }
+        };
+        tracing::info!(%listen, "serving");
+        let client = Arc::new(GatewayClient::new(transport, config));
+        let _sync = sync.map(|sync| clocksync::spawn(client.clone(), sync));
+        let state = AppState::new(client);
+        let shutdown = async {
+            let _ = tokio::signal::ctrl_c().await;
+        };
+        match linux_gateway::server::serve(listener, state, shutdown).await {
+            Ok(()) => 0,
+            Err(e) => {
+                eprintln!("serve: {e}");
+                3
+            }
+        }
+    });
+    process::exit(code);
+}
+
+fn mux_command(args: &[String]) -> ! {
+    let mut socket = "/run/linux_gateway.sock".to_string();
+    let mut link = Link::Device("/dev/rpmsg0".into());
+    let mut checksum = ChecksumKind::default();
+    let mut auth = None;
+    let mut config = MuxConfig::default();
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        if arg == "--emulate" {
+            link = Link::Emulator;
+            continue;
+        }
+        let Some(value) = it.next().cloned() else {
+            eprintln!("mux: {arg} needs a value");
+            process::exit(2);
+        };
+        let number = |value: &str| -> u64 {
+            match value.parse() {
+                Ok(n) if n > 0 => n,
+                _ => {
+                    eprintln!("mux: invalid {arg} {value:?}");
+                    process::exit(2);
+                }
+            }
+        };
+        match arg.as_str() {
+            "--socket" => socket = value,
+            "--device" => link = Link::Device(value),
+            "--unix" => link = Link::Unix(value),
+            "--tcp" => link = Link::Tcp(value),
+            "--checksum" => match parse_checksum(&value) {
+                Ok(kind) => checksum = kind,
+                Err(e) => {
+                    eprintln!("mux: {e}");
+                    process::exit(2);
+                }
+            },
+            "--auth-key-file" => match load_key(&value) {
+                Ok(key) => auth = Some(key),
+                Err(e) => {
+                    eprintln!("mux: {e}");
+                    process::exit(2);
+                }
+            },
+            "--max-in-flight" => config.max_in_flight = number(&value) as usize,
+            "--per-client" => config.per_client_in_flight = number(&value) as usize,
+            "--timeout" => config.timeout = Duration::from_millis(number(&value)),
+            _ => {
+                eprintln!("mux: unknown option {arg}");
+                process::exit(2);
+            }
+        }
+    }
+
+    let rt = tokio::runtime::Runtime::new().expect("tokio runtime");
+    let code = rt.block_on(async move {
+        let _telemetry = match telemetry::init("linux_gateway") {
+            Ok(t) => t,
+            Err(e) => {
+                eprintln!("telemetry: {e}");
+                return 3;
+            }
+        };
+        let transport = match link.open(checksum, auth).await {
+            Ok(t) => t,
+            Err(e) => {
+                eprintln!("mux: open link: {e}");
+                return 3;
+            }
+        };
+        // A socket left behind by a previous run would make bind fail.
+        if std::fs::symlink_metadata(&socket).is_ok_and(|m| m.file_type().is_socket()) {
+            let _ = std::fs::remove_file(&socket);
+        }
+        let listener = match tokio::net::UnixListener::bind(&socket) {
+            Ok(l) => l,
+            Err(e) => {
+                eprintln!("mux: bind {socket}: {e}");
+                return 3;
+            }
+        };
+        tracing::info!(%socket, "multiplexing");
+        let mux = Mux::new(transport, config);
+        let code = tokio::select! {
+            res = mux.serve(listener) => match res {
+                Ok(()) => 0,
+                Err(e) => {
+                    eprintln!("mux: link: {e}");
+                    3
+                }
+            },
+            _ = tokio::signal::ctrl_c() => 0,
+        };
+        let _ = std::fs::remove_file(&socket);
+        code
+    });
+    process::exit(code);
+}
+
 fn main() {
     let argv: Vec<String> = env::args().collect();
     if argv.len() == 1 || argv[1] == "-h" || argv[1] == "--help" {
@@ -47,11 +566,36 @@ fn main() {
                 eprintln!("{}", HELP);
                 process::exit(2);
             };
-            if parse_hex(&val).is_err() {
+            let json_out = argv.iter().any(|a| a == "--json");
+            let bytes = parse_frame_hex(&val).unwrap_or_else(|()| {
                 eprintln!("decode: invalid hex");
                 process::exit(2);
+            });
+            match linux_gateway::server::frame_to_json(&bytes) {
+                Ok(fields) if json_out => println!("{fields}"),
+                Ok(fields) => print_human(&fields),
+                Err(e) => {
+                    if json_out {
+                        println!("{}", json!({"error": e.kind(), "message": e.to_string()}));
+                    } else {
+                        eprintln!("decode: {e}");
+                    }
+                    process::exit(exit_code(&e));
+                }
             }
         }
+        "dissect" => {
+            if argv.len() < 3 {
+                eprintln!("{}", HELP);
+                process::exit(2);
+            }
+            let bytes = parse_frame_hex(&argv[2]).unwrap_or_else(|()| {
+                eprintln!("dissect: invalid hex");
+                process::exit(2);
+            });
+            let d = linux_gateway::dissect::dissect(&bytes);
+            print!("{}", linux_gateway::dissect::render(&bytes, &d));
+        }
         "make-resp" | "make_resp" => {
             if argv.len() < 3 {
                 eprintln!("{}", HELP);
@@ -91,16 +635,10 @@ fn main() {
             }
             println!("{}", s);
         }
-        "rpmsg-bounce" | "rpmsg_bounce" => {
-            if argv.len() < 3 {
-                eprintln!("rpmsg-bounce: missing HEX argument");
-                process::exit(2);
-            }
-            if parse_hex(&argv[2]).is_err() {
-                eprintln!("rpmsg-bounce: invalid hex");
-                process::exit(2);
-            }
-        }
+        "send" => send_command(&argv[2..], false),
+        "serve" => serve_command(&argv[2..]),
+        "mux" => mux_command(&argv[2..]),
+        "rpmsg-bounce" | "rpmsg_bounce" => send_command(&argv[2..], true),
         _ => {
             println!("{}", HELP);
             process::exit(2);
@@ -0,0 +1,275 @@
+//! Message-type registry: ties each prost message to its wire type byte.
+//!
+//! Adding a message means adding one line to the `wire_messages!` invocation
+//! below; `encode_frame`/`decode_frame`/`decode_any` pick it up from there.
+//! `(...)` after the name is its field schema, which [`DecodeLimits`], the
+//! dissector and the emulator walk, so no registered type goes without one.
+//! `{ ... }` lists what the message carries: `trace` for a `TraceCtx` field,
+//! `timing` for the R5 timestamps of `CalcResponse`.
+//!
+//! Every decode checks the frame against [`DecodeLimits`] first and validates
+//! the message after ([`crate::validate`]); the plain functions use the
+//! defaults, the `_with_limits` ones take them.
+
+use prost::Message;
+
+use crate::dissect::{field, FieldDef, FieldKind};
+use crate::limits::DecodeLimits;
+use crate::metrics::count_decode_error;
+use crate::timing::R5Timing;
+use crate::validate::{self, Validate};
+use crate::{proto, wire, FrameError};
+
+/// A protobuf message that travels in its own frame type.
+pub trait WireMessage: Message + Default + Sized + Validate {
+    /// Type byte in the frame header.
+    const TYPE: u8;
+    /// Field schema, as [`schema`] returns it for `TYPE`.
+    const FIELDS: &'static [FieldDef];
+
+    /// Trace context carried by the message, if it has one.
+    fn trace_ctx(&self) -> Option<&proto::TraceCtx> {
+        None
+    }
+
+    /// R5 timestamps carried by the message, if it has them.
+    fn r5_timing(&self) -> Option<R5Timing> {
+        None
+    }
+}
+
+macro_rules! wire_capability {
+    (trace) => {
+        fn trace_ctx(&self) -> Option<&proto::TraceCtx> {
+            self.trace.as_ref()
+        }
+    };
+    (timing) => {
+        fn r5_timing(&self) -> Option<R5Timing> {
+            R5Timing::from_response(self)
+        }
+    };
+}
+
+macro_rules! wire_messages {
+    ($($typ:path => $name:ident($fields:expr) $({ $($cap:ident),* })?),* $(,)?) => {
+        $(
+            impl WireMessage for proto::$name {
+                const TYPE: u8 = $typ;
+                const FIELDS: &'static [FieldDef] = $fields;
+                $($(wire_capability!($cap);)*)?
+            }
+        )*
+
+        /// Any registered message, as returned by [`decode_any`].
+        #[derive(Debug, Clone, PartialEq)]
+        pub enum AnyMessage {
+            $($name(proto::$name),)*
+        }
+
+        impl AnyMessage {
+            /// Decode `payload` as whichever message is registered for `typ`.
+            pub fn from_payload(typ: u8, payload: &[u8]) -> Result<Self, FrameError> {
+                match typ {
+                    $($typ => proto::$name::decode(payload)
+                        .map(Self::$name)
+                        .map_err(|_| FrameError::Decode),)*
+                    other => Err(FrameError::UnknownType(other)),
+                }
+            }
+
+            /// Type byte this message is framed with.
+            pub fn wire_type(&self) -> u8 {
+                match self {
+                    $(Self::$name(_) => $typ,)*
+                }
+            }
+        }
+
+        impl Validate for AnyMessage {
+            fn check(&self, errors: &mut Vec<validate::ValidationError>) {
+                match self {
+                    $(Self::$name(msg) => msg.check(errors),)*
+                }
+            }
+        }
+
+        /// Whether `typ` has a registered message.
+        pub fn is_registered(typ: u8) -> bool {
+            matches!(typ, $($typ)|*)
+        }
+
+        /// Name of the message registered for `typ`.
+        pub fn type_name(typ: u8) -> Option<&'static str> {
+            match typ {
+                $($typ => Some(stringify!($name)),)*
+                _ => None,
+            }
+        }
+
+        /// Name and field schema of the message registered for `typ`.
+        pub fn schema(typ: u8) -> Option<(&'static str, &'static [FieldDef])> {
+            match typ {
+                $($typ => Some((stringify!($name), $fields)),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+pub const TRACE_CTX: &[FieldDef] = &[
+    field(1, "trace_id", FieldKind::Bytes),
+    field(2, "span_id", FieldKind::Bytes),
+    field(3, "flags", FieldKind::Uint32),
+    field(4, "tracestate", FieldKind::String),
+];
+const TRACE: FieldKind = FieldKind::Message("TraceCtx", TRACE_CTX);
+
+pub const CALC_REQUEST: &[FieldDef] = &[
+    field(1, "op", FieldKind::Op),
+    field(2, "a", FieldKind::Uint32),
+    field(3, "b", FieldKind::Uint32),
+    field(100, "trace", TRACE),
+];
+pub const CALC_RESPONSE: &[FieldDef] = &[
+    field(1, "result", FieldKind::Uint32),
+    field(2, "r5_rx_ns", FieldKind::Uint64),
+    field(3, "r5_tx_ns", FieldKind::Uint64),
+    field(4, "r5_compute_ns", FieldKind::Uint64),
+    field(100, "trace", TRACE),
+];
+pub const CALC_ERROR: &[FieldDef] = &[
+    field(1, "code", FieldKind::Status),
+    field(2, "detail", FieldKind::String),
+    field(100, "trace", TRACE),
+];
+pub const TIME_SYNC_REQUEST: &[FieldDef] = &[field(1, "t1_ns", FieldKind::Uint64)];
+pub const TIME_SYNC_RESPONSE: &[FieldDef] = &[
+    field(1, "t1_ns", FieldKind::Uint64),
+    field(2, "t2_ns", FieldKind::Uint64),
+    field(3, "t3_ns", FieldKind::Uint64),
+];
+
+wire_messages! {
+    wire::TYPE_REQ => CalcRequest(CALC_REQUEST) { trace },
+    wire::TYPE_RESP => CalcResponse(CALC_RESPONSE) { trace, timing },
+    wire::TYPE_ERR => CalcError(CALC_ERROR) { trace },
+    wire::TYPE_TIME_REQ => TimeSyncRequest(TIME_SYNC_REQUEST),
+    wire::TYPE_TIME_RESP => TimeSyncResponse(TIME_SYNC_RESPONSE),
+}
+
+fn encode_payload<M: WireMessage>(msg: &M) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(msg.encoded_len());
+    msg.encode(&mut payload).expect("encode");
+    payload
+}
+
+/// Encode `msg` as a v1 frame of its registered type.
+pub fn encode_frame<M: WireMessage>(msg: &M) -> Vec<u8> {
+    wire::wrap_v1(M::TYPE, &encode_payload(msg))
+}
+
+/// Encode `msg` as a v2 frame of its registered type.
+pub fn encode_frame_v2<M: WireMessage>(msg: &M, seq: u16) -> Vec<u8> {
+    wire::wrap_v2(M::TYPE, 0, seq, &encode_payload(msg))
+}
+
+/// [`wire::unwrap_any`], refusing fragments: their payload is only part of
+/// a message until [`Reassembler`](crate::fragment::Reassembler) rebuilds it.
+/// Authenticated frames are refused too until [`wire::open_auth`] checks
+/// and strips their tag.
+pub(crate) fn unwrap_whole(frame: &[u8]) -> Result<wire::Frame<'_>, FrameError> {
+    let f = wire::unwrap_any(frame)?;
+    if f.header.flags & wire::FLAG_FRAG_MASK != 0 {
+        return Err(FrameError::Fragment("needs reassembly"));
+    }
+    if f.header.flags & wire::FLAG_AUTH != 0 {
+        return Err(FrameError::Auth("tag not checked"));
+    }
+    Ok(f)
+}
+
+/// [`unwrap_whole`] for a frame within `limits`. The payload is checked
+/// against the schema of its type; unregistered types have nothing to
+/// decode and are refused by the caller.
+pub(crate) fn unwrap_limited<'a>(
+    frame: &'a [u8],
+    limits: &DecodeLimits,
+) -> Result<wire::Frame<'a>, FrameError> {
+    limits.check_frame(frame)?;
+    let f = unwrap_whole(frame)?;
+    if let Some((_, fields)) = schema(f.header.typ) {
+        limits.check_payload(f.payload, fields)?;
+    }
+    Ok(f)
+}
+
+/// Decode a v1 or v2 frame that must carry an `M`.
+pub fn decode_frame<M: WireMessage>(frame: &[u8]) -> Result<M, FrameError> {
+    decode_frame_with_limits(frame, &DecodeLimits::default())
+}
+
+pub fn decode_frame_with_limits<M: WireMessage>(
+    frame: &[u8],
+    limits: &DecodeLimits,
+) -> Result<M, FrameError> {
+    decode_frame_inner(frame, limits).inspect_err(count_decode_error)
+}
+
+fn decode_frame_inner<M: WireMessage>(
+    frame: &[u8],
+    limits: &DecodeLimits,
+) -> Result<M, FrameError> {
+    let f = unwrap_limited(frame, limits)?;
+    if f.header.typ != M::TYPE {
+        return Err(FrameError::UnknownType(f.header.typ));
+    }
+    let msg = M::decode(f.payload).map_err(|_| FrameError::Decode)?;
+    validate::apply(msg, M::TYPE, limits.validation)
+}
+
+/// Decode the reply to a request: an `M`, or a `CalcError` frame from the R5
+/// surfaced as [`FrameError::RemoteError`].
+pub fn decode_reply<M: WireMessage>(frame: &[u8]) -> Result<M, FrameError> {
+    decode_reply_with_limits(frame, &DecodeLimits::default())
+}
+
+pub fn decode_reply_with_limits<M: WireMessage>(
+    frame: &[u8],
+    limits: &DecodeLimits,
+) -> Result<M, FrameError> {
+    decode_reply_inner(frame, limits).inspect_err(count_decode_error)
+}
+
+fn decode_reply_inner<M: WireMessage>(
+    frame: &[u8],
+    limits: &DecodeLimits,
+) -> Result<M, FrameError> {
+    let f = unwrap_limited(frame, limits)?;
+    if f.header.typ == wire::TYPE_ERR {
+        let err = proto::CalcError::decode(f.payload).map_err(|_| FrameError::Decode)?;
+        return Err(validate::apply(err, wire::TYPE_ERR, limits.validation)?.into());
+    }
+    if f.header.typ != M::TYPE {
+        return Err(FrameError::UnknownType(f.header.typ));
+    }
+    let msg = M::decode(f.payload).map_err(|_| FrameError::Decode)?;
+    validate::apply(msg, M::TYPE, limits.validation)
+}
+
+/// Decode a v1 or v2 frame of any registered type.
+pub fn decode_any(frame: &[u8]) -> Result<AnyMessage, FrameError> {
+    decode_any_with_limits(frame, &DecodeLimits::default())
+}
+
+pub fn decode_any_with_limits(
+    frame: &[u8],
+    limits: &DecodeLimits,
+) -> Result<AnyMessage, FrameError> {
+    unwrap_limited(frame, limits)
+        .and_then(|f| {
+            let msg = AnyMessage::from_payload(f.header.typ, f.payload)?;
+            validate::apply(msg, f.header.typ, limits.validation)
+        })
+        .inspect_err(count_decode_error)
+}
@@ -0,0 +1,128 @@
+//! Prometheus metrics for the gateway, in one process-wide registry.
+//!
+//! - `linux_gateway_frames_total{direction, type}`: frames on stream links
+//!   (rpmsg chardev, UNIX, TCP); `direction` is `tx` or `rx`, `type` the
+//!   registered message name or `unknown`.
+//! - `linux_gateway_decode_errors_total{kind}`: frames or stream bytes that
+//!   failed to decode, by [`FrameError::kind`].
+//! - `linux_gateway_rtt_seconds`: request round trips seen by
+//!   [`GatewayClient`](crate::client::GatewayClient).
+//! - `linux_gateway_latency_seconds{component}`: round trips split into R5
+//!   `queueing` and `processing` and link `transit`, for replies carrying R5
+//!   timestamps ([`LatencyBreakdown`](crate::timing::LatencyBreakdown)).
+//! - `linux_gateway_in_flight{layer}`: requests awaiting a reply in the
+//!   `client` or the `mux`.
+//!
+//! `serve` exposes them on `GET /metrics`.
+
+use std::sync::OnceLock;
+
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+
+use crate::message::type_name;
+use crate::{wire, FrameError};
+
+pub struct Metrics {
+    registry: Registry,
+    pub frames: IntCounterVec,
+    pub decode_errors: IntCounterVec,
+    pub rtt: Histogram,
+    pub latency: HistogramVec,
+    pub in_flight: IntGaugeVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let frames = IntCounterVec::new(
+            Opts::new("linux_gateway_frames_total", "Frames sent and received"),
+            &["direction", "type"],
+        )
+        .unwrap();
+        let decode_errors = IntCounterVec::new(
+            Opts::new(
+                "linux_gateway_decode_errors_total",
+                "Frames that failed to decode, by error kind",
+            ),
+            &["kind"],
+        )
+        .unwrap();
+        // 50us .. ~1.6s; an rpmsg round trip is normally well under 1ms.
+        let rtt = Histogram::with_opts(
+            HistogramOpts::new("linux_gateway_rtt_seconds", "Request round-trip time")
+                .buckets(prometheus::exponential_buckets(50e-6, 2.0, 16).unwrap()),
+        )
+        .unwrap();
+        // 1us .. ~4s; R5 processing is a few microseconds.
+        let latency = HistogramVec::new(
+            HistogramOpts::new(
+                "linux_gateway_latency_seconds",
+                "Round-trip time by component",
+            )
+            .buckets(prometheus::exponential_buckets(1e-6, 2.0, 22).unwrap()),
+            &["component"],
+        )
+        .unwrap();
+        let in_flight = IntGaugeVec::new(
+            Opts::new("linux_gateway_in_flight", "Requests awaiting a reply"),
+            &["layer"],
+        )
+        .unwrap();
+
+        let registry = Registry::new();
+        registry.register(Box::new(frames.clone())).unwrap();
+        registry.register(Box::new(decode_errors.clone())).unwrap();
+        registry.register(Box::new(rtt.clone())).unwrap();
+        registry.register(Box::new(latency.clone())).unwrap();
+        registry.register(Box::new(in_flight.clone())).unwrap();
+        Self {
+            registry,
+            frames,
+            decode_errors,
+            rtt,
+            latency,
+            in_flight,
+        }
+    }
+
+    /// Everything in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut out)
+            .expect("text encoding");
+        String::from_utf8(out).expect("text exposition is UTF-8")
+    }
+}
+
+/// The process-wide metrics.
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new)
+}
+
+/// `Content-Type` of [`Metrics::render`] output.
+pub const CONTENT_TYPE: &str = prometheus::TEXT_FORMAT;
+
+pub(crate) fn count_frame(direction: &str, frame: &[u8]) {
+    // v2 frames start with SYNC and carry the type at [3]; v1 at [1].
+    let typ = if frame.starts_with(&wire::SYNC.to_be_bytes()) {
+        frame.get(3)
+    } else {
+        frame.get(1)
+    };
+    let name = typ.and_then(|&t| type_name(t)).unwrap_or("unknown");
+    metrics().frames.with_label_values(&[direction, name]).inc();
+}
+
+pub(crate) fn count_decode_error(err: &FrameError) {
+    // A CalcError frame decoded fine; the R5 just said no.
+    if !matches!(err, FrameError::RemoteError { .. }) {
+        metrics()
+            .decode_errors
+            .with_label_values(&[err.kind()])
+            .inc();
+    }
+}
@@ -0,0 +1,384 @@
+//! Multiplexing daemon: one process owns the R5 link and many local clients
+//! share it over a UNIX socket (`linux_gateway mux`).
+//!
+//! Clients speak the normal v2 framing, so anything that can talk to
+//! `/dev/rpmsgN` directly ([`GatewayClient`](crate::client::GatewayClient),
+//! `send --unix`) can talk to the mux instead. Each request gets a mux-wide
+//! seq on the way to the R5; the reply's seq is mapped back to the client's
+//! own, and its checksum to the client's choice, before it is returned. Fragmented client frames are reassembled before
+//! they are scheduled, and the upstream transport fragments them again.
+//!
+//! A single scheduler task owns all state. Queued requests are dispatched
+//! round-robin across clients, at most [`MuxConfig::max_in_flight`] at once
+//! and [`MuxConfig::per_client_in_flight`] per client, so one busy client
+//! cannot starve the others. Calc and time-sync requests are forwarded; v1
+//! frames carry no seq and are dropped, as is any other frame type and any
+//! frame a client authenticated itself (the mux's seq would break its tag).
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::net::UnixListener;
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+
+use crate::checksum::ChecksumKind;
+use crate::fragment::{self, FragmentingTransport};
+use crate::metrics::metrics;
+use crate::transport::{Transport, UnixTransport};
+use crate::wire;
+
+#[derive(Debug, Clone)]
+pub struct MuxConfig {
+    /// Requests outstanding at the R5 across all clients.
+    pub max_in_flight: usize,
+    /// Requests outstanding at the R5 for any one client.
+    pub per_client_in_flight: usize,
+    /// Requests a client may have queued or in flight before the mux stops
+    /// reading from its socket.
+    pub per_client_queue: usize,
+    /// A request with no reply after this long frees its slot; a late reply
+    /// is dropped.
+    pub timeout: Duration,
+}
+
+impl Default for MuxConfig {
+    fn default() -> Self {
+        Self {
+            max_in_flight: 8,
+            per_client_in_flight: 2,
+            per_client_queue: 32,
+            timeout: Duration::from_secs(2),
+        }
+    }
+}
+
+type ClientId = u64;
+
+enum Event {
+    Connected(ClientId, mpsc::UnboundedSender<Vec<u8>>),
+    Request(ClientId, Vec<u8>, OwnedSemaphorePermit),
+    Disconnected(ClientId),
+    Reply(Vec<u8>),
+    UpstreamClosed(io::Error),
+}
+
+struct Queued {
+    frame: Vec<u8>,
+    client_seq: u16,
+    /// The client's checksum; the link may use another.
+    checksum: ChecksumKind,
+    permit: OwnedSemaphorePermit,
+}
+
+struct InFlight {
+    client: ClientId,
+    client_seq: u16,
+    checksum: ChecksumKind,
+    sent: Instant,
+    _permit: OwnedSemaphorePermit,
+}
+
+struct ClientSlot {
+    tx: mpsc::UnboundedSender<Vec<u8>>,
+    queue: VecDeque<Queued>,
+    in_flight: usize,
+}
+
+/// Scheduler state, owned by the task running [`Mux::serve`].
+struct Sched {
+    config: MuxConfig,
+    clients: BTreeMap<ClientId, ClientSlot>,
+    in_flight: HashMap<u16, InFlight>,
+    next_seq: u16,
+    /// Last client served; the next round starts after it.
+    cursor: ClientId,
+}
+
+impl Sched {
+    fn new(config: MuxConfig) -> Self {
+        Self {
+            config,
+            clients: BTreeMap::new(),
+            in_flight: HashMap::new(),
+            next_seq: 0,
+            cursor: 0,
+        }
+    }
+
+    // Next free upstream seq; 0 is skipped since v1 frames report seq 0.
+    fn alloc_seq(&mut self) -> u16 {
+        loop {
+            self.next_seq = self.next_seq.wrapping_add(1);
+            if self.next_seq != 0 && !self.in_flight.contains_key(&self.next_seq) {
+                return self.next_seq;
+            }
+        }
+    }
+
+    /// Next client, in round-robin order after `cursor`, that has a queued
+    /// request and room for another in flight.
+    fn next_ready(&self) -> Option<ClientId> {
+        let ready = |(id, c): (&ClientId, &ClientSlot)| {
+            (!c.queue.is_empty() && c.in_flight < self.config.per_client_in_flight).then_some(*id)
+        };
+        self.clients
+            .range(self.cursor + 1..)
+            .chain(self.clients.range(..=self.cursor))
+            .find_map(ready)
+    }
+
+    /// Take the next request to send upstream, already carrying its mux seq.
+    fn dispatch(&mut self) -> Option<(u16, Vec<u8>)> {
+        if self.in_flight.len() >= self.config.max_in_flight {
+            return None;
+        }
+        let id = self.next_ready()?;
+        let seq = self.alloc_seq();
+        let slot = self.clients.get_mut(&id).expect("ready client exists");
+        let mut q = slot.queue.pop_front().expect("ready client has a request");
+        slot.in_flight += 1;
+        self.cursor = id;
+        wire::set_seq(&mut q.frame, seq).expect("queued frames have a v2 header");
+        in_flight().inc();
+        self.in_flight.insert(
+            seq,
+            InFlight {
+                client: id,
+                client_seq: q.client_seq,
+                checksum: q.checksum,
+                sent: Instant::now(),
+                _permit: q.permit,
+            },
+        );
+        Some((seq, q.frame))
+    }
+
+    fn finish(&mut self, seq: u16) -> Option<InFlight> {
+        let done = self.in_flight.remove(&seq)?;
+        in_flight().dec();
+        if let Some(slot) = self.clients.get_mut(&done.client) {
+            slot.in_flight -= 1;
+        }
+        Some(done)
+    }
+
+    fn enqueue(&mut self, id: ClientId, frame: Vec<u8>, permit: OwnedSemaphorePermit) {
+        let Some(slot) = self.clients.get_mut(&id) else {
+            return;
+        };
+        match wire::parse_v2_header(&frame) {
+            // The mux rewrites the seq, which a client's tag covers, and
+            // reassembles fragments itself: neither may come through whole.
+            Ok((h, _)) if h.flags & (wire::FLAG_AUTH | wire::FLAG_FRAG_MASK) != 0 => {
+                tracing::warn!(
+                    client = id,
+                    flags = h.flags,
+                    "dropping sealed or fragment frame"
+                )
+            }
+            Ok((h, _)) if matches!(h.typ, wire::TYPE_REQ | wire::TYPE_TIME_REQ) => {
+                slot.queue.push_back(Queued {
+                    frame,
+                    client_seq: h.seq,
+                    checksum: ChecksumKind::from_flags(h.flags),
+                    permit,
+                })
+            }
+            Ok((h, _)) => tracing::debug!(client = id, typ = h.typ, "dropping non-request frame"),
+            Err(e) => tracing::warn!(client = id, error = %e, "dropping frame without v2 header"),
+        }
+    }
+
+    fn route_reply(&mut self, mut frame: Vec<u8>) {
+        let seq = match wire::parse_v2_header(&frame) {
+            Ok((h, _)) => h.seq,
+            Err(e) => {
+                tracing::debug!(error = %e, "dropping undecodable reply");
+                return;
+            }
+        };
+        let Some(done) = self.finish(seq) else {
+            tracing::debug!(seq, "dropping reply with no waiting request");
+            return;
+        };
+        let Some(slot) = self.clients.get(&done.client) else {
+            tracing::debug!(seq, client = done.client, "client gone, dropping reply");
+            return;
+        };
+        wire::set_seq(&mut frame, done.client_seq).expect("header checked above");
+        // Answer in the checksum the client asked in, not the link's.
+        match wire::set_checksum(&frame, done.checksum) {
+            Ok(frame) => {
+                let _ = slot.tx.send(frame);
+            }
+            Err(e) => tracing::debug!(seq, error = %e, "dropping reply that does not unwrap"),
+        }
+    }
+
+    fn expire(&mut self, now: Instant) {
+        let timeout = self.config.timeout;
+        let stale: Vec<u16> = self
+            .in_flight
+            .iter()
+            .filter(|(_, f)| now.duration_since(f.sent) >= timeout)
+            .map(|(seq, _)| *seq)
+            .collect();
+        for seq in stale {
+            if let Some(f) = self.finish(seq) {
+                tracing::debug!(seq, client = f.client, "request expired");
+            }
+        }
+    }
+}
+
+impl Drop for Sched {
+    fn drop(&mut self) {
+        in_flight().sub(self.in_flight.len() as i64);
+    }
+}
+
+fn in_flight() -> prometheus::IntGauge {
+    metrics().in_flight.with_label_values(&["mux"])
+}
+
+/// The multiplexer; see the module docs.
+pub struct Mux {
+    upstream: Arc<dyn Transport>,
+    config: MuxConfig,
+}
+
+impl Mux {
+    pub fn new(upstream: impl Transport + 'static, config: MuxConfig) -> Self {
+        Self {
+            upstream: Arc::new(upstream),
+            config,
+        }
+    }
+
+    /// Accept clients on `listener` and forward their requests until the R5
+    /// link fails, which is returned as the error. A request the link
+    /// refuses with [`io::ErrorKind::InvalidInput`] is dropped on its own.
+    pub async fn serve(self, listener: UnixListener) -> io::Result<()> {
+        let (events, mut rx) = mpsc::unbounded_channel();
+
+        let upstream = self.upstream.clone();
+        let up_events = events.clone();
+        let reader = tokio::spawn(async move {
+            loop {
+                match upstream.recv().await {
+                    Ok(frame) => {
+                        if up_events.send(Event::Reply(frame)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        let _ = up_events.send(Event::UpstreamClosed(e));
+                        break;
+                    }
+                }
+            }
+        });
+        let _reader = AbortOnDrop(reader);
+
+        let mut sched = Sched::new(self.config.clone());
+        let mut next_id: ClientId = 0;
+        let mut tick =
+            tokio::time::interval((self.config.timeout / 4).max(Duration::from_millis(1)));
+        tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => {
+                    let (stream, _) = accepted?;
+                    next_id += 1;
+                    spawn_client(next_id, stream, &self.config, events.clone());
+                }
+                Some(event) = rx.recv() => match event {
+                    Event::Connected(id, tx) => {
+                        tracing::debug!(client = id, "client connected");
+                        sched.clients.insert(id, ClientSlot { tx, queue: VecDeque::new(), in_flight: 0 });
+                    }
+                    Event::Request(id, frame, permit) => sched.enqueue(id, frame, permit),
+                    Event::Disconnected(id) => {
+                        tracing::debug!(client = id, "client disconnected");
+                        // Its in-flight requests keep their slots until the R5
+                        // answers or they expire.
+                        sched.clients.remove(&id);
+                    }
+                    Event::Reply(frame) => sched.route_reply(frame),
+                    Event::UpstreamClosed(e) => return Err(e),
+                },
+                now = tick.tick() => sched.expire(now),
+            }
+
+            while let Some((seq, frame)) = sched.dispatch() {
+                match self.upstream.send(&frame).await {
+                    Ok(()) => {}
+                    // The link refused this one frame; the others still go.
+                    Err(e) if e.kind() == io::ErrorKind::InvalidInput => {
+                        let client = sched.finish(seq).map(|f| f.client);
+                        tracing::warn!(seq, client, error = %e, "dropping request the link refused");
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+}
+
+struct AbortOnDrop(tokio::task::JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Reader and writer tasks for one client connection.
+fn spawn_client(
+    id: ClientId,
+    stream: tokio::net::UnixStream,
+    config: &MuxConfig,
+    events: mpsc::UnboundedSender<Event>,
+) {
+    let transport = Arc::new(FragmentingTransport::new(
+        UnixTransport::from_unix(stream),
+        fragment::DEFAULT_MTU,
+    ));
+    let (tx, mut replies) = mpsc::unbounded_channel::<Vec<u8>>();
+    if events.send(Event::Connected(id, tx)).is_err() {
+        return;
+    }
+
+    let writer = transport.clone();
+    tokio::spawn(async move {
+        while let Some(frame) = replies.recv().await {
+            if writer.send(&frame).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    // One permit per queued or in-flight request; once they run out the
+    // client's socket is not read until a reply (or expiry) frees one.
+    let budget = Arc::new(Semaphore::new(config.per_client_queue.max(1)));
+    tokio::spawn(async move {
+        loop {
+            let Ok(permit) = budget.clone().acquire_owned().await else {
+                break;
+            };
+            match transport.recv().await {
+                Ok(frame) => {
+                    if events.send(Event::Request(id, frame, permit)).is_err() {
+                        return;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+        let _ = events.send(Event::Disconnected(id));
+    });
+}
@@ -0,0 +1,265 @@
+//! R5 lifecycle through `/sys/class/remoteproc/remoteprocN`.
+//!
+//! [`Remoteproc`] reads and writes the `state`, `firmware` and `recovery`
+//! attributes; [`Watcher`] polls `state`, reports transitions to subscribers
+//! and recreates RPMsg endpoints whenever the core comes back up.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use crate::rpmsg::{Endpoint, EndpointManager, EndpointSpec};
+
+pub const DEFAULT_SYSFS_ROOT: &str = "/sys/class/remoteproc";
+
+/// Values of the `state` attribute.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RprocState {
+    Offline,
+    Suspended,
+    Running,
+    Crashed,
+    Deleted,
+    Attached,
+    Detached,
+    Invalid,
+    /// Anything this build does not know about.
+    Other(String),
+}
+
+impl RprocState {
+    pub fn parse(s: &str) -> Self {
+        match s.trim() {
+            "offline" => Self::Offline,
+            "suspended" => Self::Suspended,
+            "running" => Self::Running,
+            "crashed" => Self::Crashed,
+            "deleted" => Self::Deleted,
+            "attached" => Self::Attached,
+            "detached" => Self::Detached,
+            "invalid" => Self::Invalid,
+            other => Self::Other(other.to_string()),
+        }
+    }
+
+    /// Running, or attached to firmware started by the bootloader.
+    pub fn is_up(&self) -> bool {
+        matches!(self, Self::Running | Self::Attached)
+    }
+}
+
+impl fmt::Display for RprocState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Offline => "offline",
+            Self::Suspended => "suspended",
+            Self::Running => "running",
+            Self::Crashed => "crashed",
+            Self::Deleted => "deleted",
+            Self::Attached => "attached",
+            Self::Detached => "detached",
+            Self::Invalid => "invalid",
+            Self::Other(s) => s,
+        })
+    }
+}
+
+/// One remote processor.
+#[derive(Debug, Clone)]
+pub struct Remoteproc {
+    dir: PathBuf,
+}
+
+impl Remoteproc {
+    /// `remoteproc<index>` under [`DEFAULT_SYSFS_ROOT`].
+    pub fn new(index: u32) -> Self {
+        Self::with_root(DEFAULT_SYSFS_ROOT, index)
+    }
+
+    pub fn with_root(root: impl AsRef<Path>, index: u32) -> Self {
+        Self {
+            dir: root.as_ref().join(format!("remoteproc{index}")),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn state(&self) -> io::Result<RprocState> {
+        Ok(RprocState::parse(&self.read("state")?))
+    }
+
+    pub fn start(&self) -> io::Result<()> {
+        self.write("state", "start")
+    }
+
+    pub fn stop(&self) -> io::Result<()> {
+        self.write("state", "stop")
+    }
+
+    /// Name of the image under `/lib/firmware` that the next start boots.
+    pub fn firmware(&self) -> io::Result<String> {
+        self.read("firmware")
+    }
+
+    /// Boot `name`: stop the core if it is up, select the image, start again.
+    pub fn load_firmware(&self, name: &str) -> io::Result<()> {
+        if self.state()? != RprocState::Offline {
+            self.stop()?;
+        }
+        self.write("firmware", name)?;
+        self.start()
+    }
+
+    /// Whether the kernel restarts the core by itself after a crash.
+    pub fn recovery(&self) -> io::Result<bool> {
+        Ok(self.read("recovery")? == "enabled")
+    }
+
+    pub fn set_recovery(&self, enabled: bool) -> io::Result<()> {
+        self.write("recovery", if enabled { "enabled" } else { "disabled" })
+    }
+
+    /// Restart a crashed core when automatic recovery is disabled.
+    pub fn recover(&self) -> io::Result<()> {
+        self.write("recovery", "recover")
+    }
+
+    fn read(&self, attr: &str) -> io::Result<String> {
+        Ok(fs::read_to_string(self.dir.join(attr))?.trim().to_string())
+    }
+
+    fn write(&self, attr: &str, value: &str) -> io::Result<()> {
+        fs::write(self.dir.join(attr), value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RprocEvent {
+    /// `state` changed between two polls.
+    State {
+        from: RprocState,
+        to: RprocState,
+    },
+    Crashed,
+    /// The core is back up and these endpoints were (re)created.
+    EndpointsReady(Vec<Endpoint>),
+    /// Recreating an endpoint failed; the watcher tries again on the next start.
+    EndpointError {
+        name: String,
+        error: String,
+    },
+}
+
+/// Polls a [`Remoteproc`] and recreates RPMsg endpoints after it restarts.
+pub struct Watcher {
+    rproc: Remoteproc,
+    interval: Duration,
+    endpoints: Option<(Arc<EndpointManager>, Vec<EndpointSpec>)>,
+}
+
+impl Watcher {
+    pub fn new(rproc: Remoteproc) -> Self {
+        Self {
+            rproc,
+            interval: Duration::from_millis(200),
+            endpoints: None,
+        }
+    }
+
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Endpoints to recreate each time the core comes up again.
+    pub fn reestablish(mut self, manager: Arc<EndpointManager>, specs: Vec<EndpointSpec>) -> Self {
+        self.endpoints = Some((manager, specs));
+        self
+    }
+
+    pub fn spawn(self) -> WatchHandle {
+        let (events, _) = broadcast::channel(16);
+        let task = tokio::spawn(self.run(events.clone()));
+        WatchHandle { events, task }
+    }
+
+    async fn run(self, events: broadcast::Sender<RprocEvent>) {
+        let mut last: Option<RprocState> = None;
+        let mut ticker = tokio::time::interval(self.interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            let state = match self.rproc.state() {
+                Ok(s) => s,
+                Err(e) => {
+                    let dir = self.rproc.dir().display();
+                    tracing::debug!(error = %e, dir = %dir, "remoteproc state unreadable");
+                    continue;
+                }
+            };
+            let Some(prev) = last.replace(state.clone()) else {
+                continue;
+            };
+            if prev == state {
+                continue;
+            }
+            tracing::info!(from = %prev, to = %state, "remoteproc state changed");
+            let _ = events.send(RprocEvent::State {
+                from: prev.clone(),
+                to: state.clone(),
+            });
+            if state == RprocState::Crashed {
+                let _ = events.send(RprocEvent::Crashed);
+            }
+            if state.is_up() && !prev.is_up() {
+                self.recreate_endpoints(&events);
+            }
+        }
+    }
+
+    fn recreate_endpoints(&self, events: &broadcast::Sender<RprocEvent>) {
+        let Some((manager, specs)) = &self.endpoints else {
+            return;
+        };
+        let mut ready = Vec::with_capacity(specs.len());
+        for spec in specs {
+            match manager.create(spec) {
+                Ok(ept) => ready.push(ept),
+                Err(e) => {
+                    tracing::warn!(name = %spec.name, error = %e, "endpoint recreate failed");
+                    let _ = events.send(RprocEvent::EndpointError {
+                        name: spec.name.clone(),
+                        error: e.to_string(),
+                    });
+                }
+            }
+        }
+        let _ = events.send(RprocEvent::EndpointsReady(ready));
+    }
+}
+
+/// A running [`Watcher`]; stops polling when dropped.
+pub struct WatchHandle {
+    events: broadcast::Sender<RprocEvent>,
+    task: JoinHandle<()>,
+}
+
+impl WatchHandle {
+    pub fn subscribe(&self) -> broadcast::Receiver<RprocEvent> {
+        self.events.subscribe()
+    }
+}
+
+impl Drop for WatchHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
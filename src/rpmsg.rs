@@ -0,0 +1,220 @@
+//! RPMsg endpoint management through `/dev/rpmsg_ctrlN`.
+//!
+//! An endpoint is created with `RPMSG_CREATE_EPT_IOCTL` on the control device,
+//! shows up as `/sys/class/rpmsg/rpmsgM` (with `name`/`src`/`dst` attributes)
+//! and `/dev/rpmsgM`, and is torn down with `RPMSG_DESTROY_EPT_IOCTL` on its own
+//! chardev. The sysfs/dev roots and the ioctl layer are injectable for tests.
+
+use std::fs;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::transport::RpmsgTransport;
+
+pub const RPMSG_NAME_SIZE: usize = 32;
+/// Let the kernel pick the local address.
+pub const RPMSG_ADDR_ANY: u32 = 0xFFFF_FFFF;
+
+/// `struct rpmsg_endpoint_info` from `<linux/rpmsg.h>`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RpmsgEndpointInfo {
+    pub name: [u8; RPMSG_NAME_SIZE],
+    pub src: u32,
+    pub dst: u32,
+}
+
+/// `_IOW(0xb5, 0x1, struct rpmsg_endpoint_info)`
+pub const RPMSG_CREATE_EPT_IOCTL: u32 = ioc(1, 0x1, std::mem::size_of::<RpmsgEndpointInfo>());
+/// `_IO(0xb5, 0x2)`
+pub const RPMSG_DESTROY_EPT_IOCTL: u32 = ioc(0, 0x2, 0);
+
+const fn ioc(dir: u32, nr: u32, size: usize) -> u32 {
+    (dir << 30) | ((size as u32) << 16) | (0xb5 << 8) | nr
+}
+
+/// What to ask the control device for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndpointSpec {
+    pub name: String,
+    pub src: u32,
+    pub dst: u32,
+}
+
+impl EndpointSpec {
+    pub fn to_info(&self) -> io::Result<RpmsgEndpointInfo> {
+        let bytes = self.name.as_bytes();
+        // Leave room for the NUL terminator.
+        if bytes.len() >= RPMSG_NAME_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("endpoint name longer than {} bytes", RPMSG_NAME_SIZE - 1),
+            ));
+        }
+        let mut name = [0u8; RPMSG_NAME_SIZE];
+        name[..bytes.len()].copy_from_slice(bytes);
+        Ok(RpmsgEndpointInfo {
+            name,
+            src: self.src,
+            dst: self.dst,
+        })
+    }
+
+    // RPMSG_ADDR_ANY on our side matches whatever address the kernel picked.
+    fn matches(&self, name: &str, src: u32, dst: u32) -> bool {
+        self.name == name && (self.src == RPMSG_ADDR_ANY || self.src == src) && self.dst == dst
+    }
+}
+
+/// The ioctl calls, split out so tests can run without a kernel.
+pub trait RpmsgIoctl: Send + Sync {
+    fn create_endpoint(&self, ctrl: &Path, spec: &EndpointSpec) -> io::Result<()>;
+    fn destroy_endpoint(&self, dev: &Path) -> io::Result<()>;
+}
+
+/// Real ioctls against the rpmsg_char driver.
+pub struct LinuxIoctl;
+
+impl RpmsgIoctl for LinuxIoctl {
+    fn create_endpoint(&self, ctrl: &Path, spec: &EndpointSpec) -> io::Result<()> {
+        let info = spec.to_info()?;
+        let f = fs::OpenOptions::new().read(true).write(true).open(ctrl)?;
+        // SAFETY: `info` is a live `struct rpmsg_endpoint_info` for the call.
+        let rc = unsafe { libc::ioctl(f.as_raw_fd(), RPMSG_CREATE_EPT_IOCTL as _, &info) };
+        if rc < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn destroy_endpoint(&self, dev: &Path) -> io::Result<()> {
+        let f = fs::OpenOptions::new().read(true).write(true).open(dev)?;
+        // SAFETY: argument-less ioctl on an fd we own.
+        let rc = unsafe { libc::ioctl(f.as_raw_fd(), RPMSG_DESTROY_EPT_IOCTL as _) };
+        if rc < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// A created endpoint and the chardev it is reachable through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    pub spec: EndpointSpec,
+    /// sysfs/dev node name, e.g. `rpmsg1`.
+    pub node: String,
+    pub dev: PathBuf,
+}
+
+pub struct EndpointManager {
+    ctrl: PathBuf,
+    sysfs_root: PathBuf,
+    dev_root: PathBuf,
+    ioctl: Arc<dyn RpmsgIoctl>,
+}
+
+impl EndpointManager {
+    /// Manage endpoints on `ctrl` (e.g. `/dev/rpmsg_ctrl0`) with the real
+    /// `/sys/class/rpmsg` and `/dev`.
+    pub fn new(ctrl: impl Into<PathBuf>) -> Self {
+        Self {
+            ctrl: ctrl.into(),
+            sysfs_root: PathBuf::from("/sys/class/rpmsg"),
+            dev_root: PathBuf::from("/dev"),
+            ioctl: Arc::new(LinuxIoctl),
+        }
+    }
+
+    pub fn with_roots(
+        mut self,
+        sysfs_root: impl Into<PathBuf>,
+        dev_root: impl Into<PathBuf>,
+    ) -> Self {
+        self.sysfs_root = sysfs_root.into();
+        self.dev_root = dev_root.into();
+        self
+    }
+
+    pub fn with_ioctl(mut self, ioctl: Arc<dyn RpmsgIoctl>) -> Self {
+        self.ioctl = ioctl;
+        self
+    }
+
+    /// Look up an existing endpoint matching `spec` in sysfs.
+    pub fn find(&self, spec: &EndpointSpec) -> io::Result<Option<Endpoint>> {
+        let entries = match fs::read_dir(&self.sysfs_root) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let node = entry.file_name().to_string_lossy().into_owned();
+            // Skip rpmsg_ctrlN and anything else that is not an endpoint.
+            let is_ept = node
+                .strip_prefix("rpmsg")
+                .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()));
+            if !is_ept {
+                continue;
+            }
+            let dir = entry.path();
+            let (Some(name), Some(src), Some(dst)) = (
+                read_attr(&dir, "name"),
+                read_attr(&dir, "src").and_then(|v| v.parse().ok()),
+                read_attr(&dir, "dst").and_then(|v| v.parse().ok()),
+            ) else {
+                continue;
+            };
+            if spec.matches(&name, src, dst) {
+                return Ok(Some(Endpoint {
+                    spec: spec.clone(),
+                    dev: self.dev_root.join(&node),
+                    node,
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Create the endpoint, or reuse one that already matches `spec`.
+    pub fn create(&self, spec: &EndpointSpec) -> io::Result<Endpoint> {
+        if let Some(ept) = self.find(spec)? {
+            return Ok(ept);
+        }
+        self.ioctl.create_endpoint(&self.ctrl, spec)?;
+        self.find(spec)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "endpoint {:?} did not appear under {}",
+                    spec.name,
+                    self.sysfs_root.display()
+                ),
+            )
+        })
+    }
+
+    pub fn destroy(&self, ept: &Endpoint) -> io::Result<()> {
+        self.ioctl.destroy_endpoint(&ept.dev)
+    }
+
+    pub fn open(&self, ept: &Endpoint) -> io::Result<RpmsgTransport> {
+        RpmsgTransport::open_rpmsg(&ept.dev)
+    }
+
+    /// Reopen after a remote reset: the chardev may have been renumbered or
+    /// removed, so look it up again (recreating it if needed) and open it.
+    pub fn reopen(&self, ept: &mut Endpoint) -> io::Result<RpmsgTransport> {
+        *ept = self.create(&ept.spec)?;
+        self.open(ept)
+    }
+}
+
+fn read_attr(dir: &Path, attr: &str) -> Option<String> {
+    fs::read_to_string(dir.join(attr))
+        .ok()
+        .map(|s| s.trim().to_string())
+}
@@ -0,0 +1,518 @@
+//! HTTP/JSON front end for the gateway (`linux_gateway serve`).
+//!
+//! - `POST /v1/calc` forwards a calculation to the R5 through a
+//!   [`GatewayClient`] and returns the result with its trace ids.
+//! - `POST /v1/frames/encode` and `POST /v1/frames/decode` convert between
+//!   JSON and hex frames without touching the R5.
+//! - `GET /v1/clock` returns the R5 clock estimate ([`crate::clocksync`]).
+//! - `GET /metrics` is the Prometheus scrape endpoint ([`crate::metrics`]).
+//!
+//! Failures are `{"error": {"kind", "message", ...}}` with `kind` taken from
+//! [`FrameError::kind`] where one applies.
+
+use std::future::Future;
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::extract::rejection::JsonRejection;
+use axum::extract::State;
+use axum::http::header;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::Instrument;
+
+use crate::calc::parse_op;
+use crate::checksum::ChecksumKind;
+use crate::client::{ClientError, GatewayClient};
+use crate::limits::DecodeLimits;
+use crate::message::{encode_frame, encode_frame_v2, unwrap_limited, WireMessage};
+use crate::proto::{CalcError, CalcRequest, CalcResponse, Op, Status, TraceCtx};
+use crate::timing::{LatencyBreakdown, R5Timing};
+use crate::validate::{self, Validate, Validation};
+use crate::{telemetry, wire, AnyMessage, FrameError};
+
+#[derive(Clone, Default)]
+pub struct AppState {
+    /// Link to the R5; without one `/v1/calc` answers 503.
+    client: Option<Arc<GatewayClient>>,
+    /// Applied to frames posted to `/v1/frames/decode`.
+    limits: DecodeLimits,
+}
+
+impl AppState {
+    pub fn new(client: impl Into<Arc<GatewayClient>>) -> Self {
+        Self {
+            client: Some(client.into()),
+            ..Default::default()
+        }
+    }
+
+    /// Frame helpers only, no R5.
+    pub fn offline() -> Self {
+        Self::default()
+    }
+
+    pub fn with_limits(mut self, limits: DecodeLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+}
+
+pub fn router(state: AppState) -> Router {
+    Router::new()
+        .route("/v1/calc", post(calc))
+        .route("/v1/frames/encode", post(encode))
+        .route("/v1/frames/decode", post(decode))
+        .route("/v1/clock", get(clock))
+        .route("/metrics", get(metrics))
+        .with_state(state)
+}
+
+/// Serve the API on `listener` until `shutdown` resolves.
+pub async fn serve(
+    listener: tokio::net::TcpListener,
+    state: AppState,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> io::Result<()> {
+    axum::serve(listener, router(state))
+        .with_graceful_shutdown(shutdown)
+        .await
+}
+
+/// A JSON error response.
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    body: Value,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, kind: &str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            body: json!({ "kind": kind, "message": message.into() }),
+        }
+    }
+
+    fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "bad_request", message)
+    }
+
+    /// A frame error; `status` depends on whose frame it was.
+    fn frame(status: StatusCode, err: FrameError) -> Self {
+        let mut e = Self::new(status, err.kind(), err.to_string());
+        if let FrameError::RemoteError { code, detail } = err {
+            // The R5 understood and refused: the caller's request is at fault.
+            e.status = StatusCode::UNPROCESSABLE_ENTITY;
+            e.body["code"] = status_name(code);
+            e.body["detail"] = json!(detail);
+        }
+        e
+    }
+}
+
+impl From<ClientError> for ApiError {
+    fn from(err: ClientError) -> Self {
+        match err {
+            ClientError::Frame(e) => Self::frame(StatusCode::BAD_GATEWAY, e),
+            ClientError::Timeout(_) => {
+                Self::new(StatusCode::GATEWAY_TIMEOUT, "timeout", err.to_string())
+            }
+            ClientError::Closed => {
+                Self::new(StatusCode::SERVICE_UNAVAILABLE, "closed", err.to_string())
+            }
+            ClientError::Busy => {
+                Self::new(StatusCode::SERVICE_UNAVAILABLE, "busy", err.to_string())
+            }
+            ClientError::Io(_) => Self::new(StatusCode::BAD_GATEWAY, "io", err.to_string()),
+        }
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rej: JsonRejection) -> Self {
+        Self::bad_request(rej.body_text())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(json!({ "error": self.body }))).into_response()
+    }
+}
+
+/// The published R5 clock estimate, or 404 before the first sync.
+async fn clock() -> Result<Json<Value>, ApiError> {
+    let est = crate::clocksync::current().ok_or_else(|| {
+        ApiError::new(
+            StatusCode::NOT_FOUND,
+            "no_clock",
+            "no R5 clock estimate yet",
+        )
+    })?;
+    Ok(Json(json!({
+        "offset_ns": est.offset_ns,
+        "drift_ppm": est.drift_ppm,
+        "delay_ns": est.delay_ns,
+        "anchor_ns": est.anchor_ns,
+        "samples": est.samples,
+    })))
+}
+
+async fn metrics() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, crate::metrics::CONTENT_TYPE)],
+        crate::metrics::metrics().render(),
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct CalcBody {
+    #[serde(default = "default_op")]
+    op: String,
+    a: u32,
+    b: u32,
+    /// Hex ids to continue an existing trace; a new one is started otherwise.
+    trace_id: Option<String>,
+    span_id: Option<String>,
+}
+
+fn default_op() -> String {
+    "sum".into()
+}
+
+async fn calc(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Result<Json<CalcBody>, JsonRejection>,
+) -> Result<(HeaderMap, Json<Value>), ApiError> {
+    let Json(body) = body?;
+    let client = state.client.as_ref().ok_or_else(|| {
+        ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "no_link",
+            "no R5 link configured",
+        )
+    })?;
+    let op = parse_op(&body.op).ok_or_else(|| ApiError::bad_request("unknown op"))?;
+    // The caller's trace comes from W3C headers or, failing those, the
+    // `trace_id`/`span_id` body fields. An invalid traceparent starts a new
+    // trace, as the spec asks.
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let parent = header("traceparent").and_then(|tp| {
+        TraceCtx::from_w3c(tp, header("tracestate"))
+            .inspect_err(|e| tracing::debug!(error = %e, "ignoring traceparent"))
+            .ok()
+    });
+    let mut trace = match &parent {
+        Some(parent) => parent.child(),
+        None => crate::new_trace_ctx(),
+    };
+    if let Some(id) = &body.trace_id {
+        trace.trace_id = parse_hex_field("trace_id", id)?;
+    }
+    if let Some(id) = &body.span_id {
+        trace.span_id = parse_hex_field("span_id", id)?;
+    }
+    // Ids from the body must be ids the R5 and the trace backend can use.
+    trace
+        .validate()
+        .map_err(|errors| ApiError::bad_request(validate::join(&errors)))?;
+    // With OTLP export on, the request gets its own span under the caller's
+    // ids and the R5 sees that span's ids instead.
+    let span = tracing::info_span!("calc", op = op.as_str_name());
+    if body.trace_id.is_some() {
+        telemetry::set_parent(&span, &trace);
+    } else if let Some(parent) = &parent {
+        telemetry::set_parent(&span, parent);
+    }
+    let trace = span.in_scope(telemetry::current_trace_ctx).unwrap_or(trace);
+    let req = CalcRequest {
+        op: op as i32,
+        a: body.a,
+        b: body.b,
+        trace: Some(trace.clone()),
+    };
+
+    let start = Instant::now();
+    let resp: CalcResponse = client
+        .call(&req, client.call_options(true))
+        .instrument(span)
+        .await?;
+    let rtt = start.elapsed();
+
+    // Hand back the context as it returned from the R5.
+    let echoed = resp.trace.as_ref().unwrap_or(&trace);
+    let mut out = HeaderMap::new();
+    out.insert("traceparent", echoed.traceparent().parse().unwrap());
+    if !echoed.tracestate.is_empty() {
+        if let Ok(state) = echoed.tracestate.parse() {
+            out.insert("tracestate", state);
+        }
+    }
+    let body = json!({
+        "op": op.as_str_name(),
+        "result": resp.result,
+        "trace_id": hex::encode(&trace.trace_id),
+        "span_id": hex::encode(&trace.span_id),
+        "traceparent": echoed.traceparent(),
+        "trace_echoed": resp.trace.as_ref() == Some(&trace),
+        "rtt_us": rtt.as_micros() as u64,
+        "latency": latency_json(&resp, rtt),
+    });
+    Ok((out, Json(body)))
+}
+
+#[derive(Debug, Deserialize)]
+struct TraceBody {
+    trace_id: String,
+    span_id: String,
+    #[serde(default)]
+    flags: u32,
+    #[serde(default)]
+    tracestate: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "message")]
+enum EncodeMessage {
+    #[serde(rename = "CalcRequest")]
+    Request {
+        #[serde(default = "default_op")]
+        op: String,
+        a: u32,
+        b: u32,
+        trace: Option<TraceBody>,
+    },
+    #[serde(rename = "CalcResponse")]
+    Response {
+        result: u32,
+        trace: Option<TraceBody>,
+    },
+    #[serde(rename = "CalcError")]
+    Error {
+        code: String,
+        #[serde(default)]
+        detail: String,
+        trace: Option<TraceBody>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct EncodeBody {
+    #[serde(flatten)]
+    message: EncodeMessage,
+    /// Wire version, 1 or 2 (default).
+    #[serde(default = "default_version")]
+    version: u8,
+    #[serde(default)]
+    seq: u16,
+}
+
+fn default_version() -> u8 {
+    wire::PROTO_VERSION_V2
+}
+
+async fn encode(body: Result<Json<EncodeBody>, JsonRejection>) -> Result<Json<Value>, ApiError> {
+    let Json(body) = body?;
+    let frame = match body.message {
+        EncodeMessage::Request { op, a, b, trace } => {
+            let op = parse_op(&op).ok_or_else(|| ApiError::bad_request("unknown op"))?;
+            let req = CalcRequest {
+                op: op as i32,
+                a,
+                b,
+                trace: trace_ctx(trace)?,
+            };
+            frame_for(&req, body.version, body.seq)?
+        }
+        EncodeMessage::Response { result, trace } => {
+            let resp = CalcResponse {
+                result,
+                trace: trace_ctx(trace)?,
+                ..Default::default()
+            };
+            frame_for(&resp, body.version, body.seq)?
+        }
+        EncodeMessage::Error {
+            code,
+            detail,
+            trace,
+        } => {
+            let code = Status::from_str_name(&code)
+                .ok_or_else(|| ApiError::bad_request("unknown code"))?;
+            let err = CalcError {
+                code: code as i32,
+                detail,
+                trace: trace_ctx(trace)?,
+            };
+            frame_for(&err, body.version, body.seq)?
+        }
+    };
+    Ok(Json(json!({
+        "frame_hex": hex::encode_upper(&frame),
+        "len": frame.len(),
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+struct DecodeBody {
+    frame_hex: String,
+}
+
+async fn decode(
+    State(state): State<AppState>,
+    body: Result<Json<DecodeBody>, JsonRejection>,
+) -> Result<Json<Value>, ApiError> {
+    let Json(body) = body?;
+    let hex = body.frame_hex.trim();
+    let hex = hex
+        .strip_prefix("0x")
+        .or_else(|| hex.strip_prefix("0X"))
+        .unwrap_or(hex);
+    let bytes = hex::decode(hex).map_err(|e| ApiError::bad_request(format!("frame_hex: {e}")))?;
+    frame_to_json_with_limits(&bytes, &state.limits)
+        .map(Json)
+        .map_err(|e| ApiError::frame(StatusCode::UNPROCESSABLE_ENTITY, e))
+}
+
+fn frame_for<M: WireMessage>(msg: &M, version: u8, seq: u16) -> Result<Vec<u8>, ApiError> {
+    match version {
+        wire::PROTO_VERSION => Ok(encode_frame(msg)),
+        // The v2 length field is a u16; a long detail or tracestate can exceed it.
+        wire::PROTO_VERSION_V2 if msg.encoded_len() > usize::from(u16::MAX) => {
+            let len = wire::v2_frame_len(0, msg.encoded_len());
+            Err(ApiError::frame(
+                StatusCode::BAD_REQUEST,
+                FrameError::TooLong(len),
+            ))
+        }
+        wire::PROTO_VERSION_V2 => Ok(encode_frame_v2(msg, seq)),
+        v => Err(ApiError::bad_request(format!("unsupported version {v}"))),
+    }
+}
+
+fn trace_ctx(trace: Option<TraceBody>) -> Result<Option<TraceCtx>, ApiError> {
+    trace
+        .map(|t| {
+            Ok(TraceCtx {
+                trace_id: parse_hex_field("trace_id", &t.trace_id)?,
+                span_id: parse_hex_field("span_id", &t.span_id)?,
+                flags: t.flags,
+                tracestate: t.tracestate,
+            })
+        })
+        .transpose()
+}
+
+fn parse_hex_field(name: &str, s: &str) -> Result<Vec<u8>, ApiError> {
+    hex::decode(s).map_err(|e| ApiError::bad_request(format!("{name}: {e}")))
+}
+
+fn op_name(op: i32) -> Value {
+    Op::try_from(op).map_or(json!(op), |op| json!(op.as_str_name()))
+}
+
+fn status_name(code: i32) -> Value {
+    Status::try_from(code).map_or(json!(code), |st| json!(st.as_str_name()))
+}
+
+/// `rtt` split by [`LatencyBreakdown`], or null if the R5 reported no timing.
+fn latency_json(resp: &CalcResponse, rtt: Duration) -> Value {
+    let Some(r5) = R5Timing::from_response(resp) else {
+        return Value::Null;
+    };
+    let b = LatencyBreakdown::new(rtt, &r5);
+    json!({
+        "queueing_us": b.queueing.as_micros() as u64,
+        "processing_us": b.processing.as_micros() as u64,
+        "transit_us": b.transit.as_micros() as u64,
+    })
+}
+
+fn trace_json(trace: &Option<TraceCtx>) -> Value {
+    match trace {
+        Some(t) => json!({
+            "trace_id": hex::encode(&t.trace_id),
+            "span_id": hex::encode(&t.span_id),
+            "flags": t.flags,
+            "tracestate": t.tracestate,
+        }),
+        None => Value::Null,
+    }
+}
+
+/// Decode one captured frame (v1, SYNC-prefixed v1, or v2) into JSON fields,
+/// as printed by `--decode --json` and returned by `/v1/frames/decode`.
+pub fn frame_to_json(bytes: &[u8]) -> Result<Value, FrameError> {
+    frame_to_json_with_limits(bytes, &DecodeLimits::default())
+}
+
+/// [`frame_to_json`] with explicit decode limits.
+pub fn frame_to_json_with_limits(bytes: &[u8], limits: &DecodeLimits) -> Result<Value, FrameError> {
+    let sync = wire::SYNC.to_be_bytes();
+    let bytes = match bytes.strip_prefix(&sync[..]) {
+        Some(rest) if rest.first() == Some(&wire::PROTO_VERSION) => rest,
+        _ => bytes,
+    };
+    let frame = unwrap_limited(bytes, limits)?;
+    let msg = AnyMessage::from_payload(frame.header.typ, frame.payload)?;
+    let mut out = json!({
+        "version": frame.header.version,
+        "type": frame.header.typ,
+    });
+    // A capture is shown as it is; what fails validation is listed with it.
+    if limits.validation != Validation::Off {
+        if let Err(errors) = msg.validate() {
+            let problems: Vec<String> = errors.iter().map(ToString::to_string).collect();
+            out["invalid"] = json!(problems);
+        }
+    }
+    if frame.header.version == wire::PROTO_VERSION_V2 {
+        out["seq"] = json!(frame.header.seq);
+        out["flags"] = json!(frame.header.flags);
+        out["checksum"] = json!(ChecksumKind::from_flags(frame.header.flags).name());
+    }
+    let fields = match msg {
+        AnyMessage::CalcRequest(req) => json!({
+            "message": "CalcRequest",
+            "op": op_name(req.op),
+            "a": req.a,
+            "b": req.b,
+            "trace": trace_json(&req.trace),
+        }),
+        AnyMessage::CalcResponse(resp) => json!({
+            "message": "CalcResponse",
+            "result": resp.result,
+            "r5_rx_ns": resp.r5_rx_ns,
+            "r5_tx_ns": resp.r5_tx_ns,
+            "r5_compute_ns": resp.r5_compute_ns,
+            "trace": trace_json(&resp.trace),
+        }),
+        AnyMessage::CalcError(err) => json!({
+            "message": "CalcError",
+            "code": status_name(err.code),
+            "detail": err.detail,
+            "trace": trace_json(&err.trace),
+        }),
+        AnyMessage::TimeSyncRequest(req) => json!({
+            "message": "TimeSyncRequest",
+            "t1_ns": req.t1_ns,
+        }),
+        AnyMessage::TimeSyncResponse(resp) => json!({
+            "message": "TimeSyncResponse",
+            "t1_ns": resp.t1_ns,
+            "t2_ns": resp.t2_ns,
+            "t3_ns": resp.t3_ns,
+        }),
+    };
+    out.as_object_mut()
+        .unwrap()
+        .extend(fields.as_object().unwrap().clone());
+    Ok(out)
+}
@@ -0,0 +1,185 @@
+//! Tracing setup and OpenTelemetry export.
+//!
+//! [`init`] installs a `tracing` subscriber that logs per `RUST_LOG` and, when
+//! `OTEL_EXPORTER_OTLP_ENDPOINT` is set, exports spans over OTLP/HTTP
+//! (e.g. to Jaeger on `http://localhost:4318`).
+//!
+//! Spans reach the R5 through [`TraceCtx`]: [`current_trace_ctx`] takes the
+//! ids of the current span when a request is encoded, and [`record_r5_span`]
+//! adds a child span for the R5 leg once the reply is back.
+
+use std::time::{Duration, SystemTime};
+
+use opentelemetry::trace::{
+    Span as _, SpanContext, SpanId, SpanKind, Status, TraceContextExt, TraceError, TraceFlags,
+    TraceId, Tracer, TracerProvider as _,
+};
+use opentelemetry::{global, Context, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{runtime, Resource};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::filter::{EnvFilter, LevelFilter};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+use crate::clocksync;
+use crate::proto::TraceCtx;
+use crate::timing::LatencyBreakdown;
+use crate::trace_context::fit_tracestate;
+use crate::FrameError;
+
+/// Instrumentation scope of the spans created here.
+const SCOPE: &str = "linux_gateway";
+
+/// Keeps the exporter alive; dropping it flushes pending spans.
+pub struct Telemetry {
+    provider: Option<TracerProvider>,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("telemetry: shutdown: {e}");
+            }
+        }
+    }
+}
+
+/// Install the global subscriber for `service`. Must be called inside a
+/// Tokio runtime, which runs the batch exporter.
+pub fn init(service: &str) -> Result<Telemetry, TraceError> {
+    let fmt = tracing_subscriber::fmt::layer().with_filter(EnvFilter::from_default_env());
+    let Ok(endpoint) = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT") else {
+        tracing_subscriber::registry().with(fmt).init();
+        return Ok(Telemetry { provider: None });
+    };
+
+    let provider = otlp_provider(service, &endpoint)?;
+    global::set_tracer_provider(provider.clone());
+    let otel = tracing_opentelemetry::layer()
+        .with_tracer(provider.tracer(SCOPE))
+        .with_filter(LevelFilter::INFO);
+    tracing_subscriber::registry().with(fmt).with(otel).init();
+    Ok(Telemetry {
+        provider: Some(provider),
+    })
+}
+
+/// A tracer provider batching spans to the OTLP/HTTP collector at
+/// `endpoint` (base URL; `/v1/traces` is appended).
+pub fn otlp_provider(service: &str, endpoint: &str) -> Result<TracerProvider, TraceError> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+        .build()?;
+    Ok(TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new(
+            "service.name",
+            service.to_string(),
+        )]))
+        .build())
+}
+
+/// Ids of the current `tracing` span, if it is exported over OpenTelemetry.
+pub fn current_trace_ctx() -> Option<TraceCtx> {
+    let cx = tracing::Span::current().context();
+    let sc = cx.span().span_context().clone();
+    sc.is_valid().then(|| TraceCtx {
+        trace_id: sc.trace_id().to_bytes().to_vec(),
+        span_id: sc.span_id().to_bytes().to_vec(),
+        flags: u32::from(sc.trace_flags().to_u8()),
+        tracestate: fit_tracestate(&sc.trace_state().header()),
+    })
+}
+
+/// `trace` as a remote span context, if its ids have W3C lengths.
+fn remote_context(trace: &TraceCtx) -> Option<SpanContext> {
+    let trace_id = TraceId::from_bytes(trace.trace_id.as_slice().try_into().ok()?);
+    let span_id = SpanId::from_bytes(trace.span_id.as_slice().try_into().ok()?);
+    let sc = SpanContext::new(
+        trace_id,
+        span_id,
+        TraceFlags::new(trace.flags as u8),
+        true,
+        trace.tracestate.parse().unwrap_or_default(),
+    );
+    sc.is_valid().then_some(sc)
+}
+
+/// Continue the trace in `trace` from `span` (e.g. ids sent by an HTTP
+/// caller). Must be called before `span` is first entered.
+pub fn set_parent(span: &tracing::Span, trace: &TraceCtx) {
+    if let Some(sc) = remote_context(trace) {
+        span.set_parent(Context::new().with_remote_span_context(sc));
+    }
+}
+
+/// Record the R5 leg of a request as a child of the span in `parent`, from
+/// `sent` to `received`, marked as failed when the reply was an error.
+///
+/// With a `breakdown`, the span gets `r5.rx`, `r5.compute` and `r5.tx`
+/// events. They are placed with the [`clocksync`] estimate when there is
+/// one (`r5.clock = "synced"`); otherwise transit is taken to be split evenly
+/// between the two directions (`"assumed"`).
+pub fn record_r5_span(
+    message: &'static str,
+    parent: &TraceCtx,
+    sent: SystemTime,
+    received: SystemTime,
+    breakdown: Option<&LatencyBreakdown>,
+    error: Option<&FrameError>,
+) {
+    let Some(sc) = remote_context(parent) else {
+        return;
+    };
+    let cx = Context::new().with_remote_span_context(sc);
+    let tracer = global::tracer(SCOPE);
+    let mut span = tracer
+        .span_builder(format!("r5 {message}"))
+        .with_kind(SpanKind::Server)
+        .with_start_time(sent)
+        .with_attributes([
+            KeyValue::new("peer.service", "r5"),
+            KeyValue::new("rpc.message", message),
+        ])
+        .start_with_context(&tracer, &cx);
+    if let Some(b) = breakdown {
+        let us = |d: Duration| d.as_micros() as i64;
+        for (component, d) in b.components() {
+            span.set_attribute(KeyValue::new(format!("r5.{component}_us"), us(d)));
+        }
+        let (clock, rx, compute, tx) = match clocksync::current() {
+            Some(est) => {
+                let at = |r5_ns| est.r5_to_system(r5_ns).max(sent).min(received);
+                let compute_ns = b.r5.tx_ns - b.processing.as_nanos() as u64;
+                ("synced", at(b.r5.rx_ns), at(compute_ns), at(b.r5.tx_ns))
+            }
+            None => {
+                let rx = sent + b.transit / 2;
+                let compute = rx + b.queueing;
+                ("assumed", rx, compute, compute + b.processing)
+            }
+        };
+        span.set_attribute(KeyValue::new("r5.clock", clock));
+        span.add_event_with_timestamp("r5.rx", rx, vec![]);
+        span.add_event_with_timestamp(
+            "r5.compute",
+            compute,
+            vec![KeyValue::new("queueing_us", us(b.queueing))],
+        );
+        span.add_event_with_timestamp(
+            "r5.tx",
+            tx,
+            vec![KeyValue::new("processing_us", us(b.processing))],
+        );
+    }
+    if let Some(e) = error {
+        span.set_attribute(KeyValue::new("error.kind", e.kind()));
+        span.set_status(Status::error(e.to_string()));
+    }
+    span.end_with_timestamp(received);
+}
@@ -0,0 +1,85 @@
+//! Where a round trip's time goes, from the R5 timestamps in `CalcResponse`.
+//!
+//! The R5 stamps when a request arrived (`r5_rx_ns`), when its reply left
+//! (`r5_tx_ns`) and how long the calculation itself took (`r5_compute_ns`),
+//! all on its own clock. Only differences between its readings are used, so
+//! the R5 and Linux clocks need not agree:
+//!
+//! - processing: `r5_compute_ns`
+//! - queueing: the rest of `r5_tx_ns - r5_rx_ns`, i.e. waiting on the R5
+//! - transit: the round trip less `r5_tx_ns - r5_rx_ns`, both directions
+//!   over the link plus the Linux side of the call
+
+use std::time::Duration;
+
+use crate::metrics::metrics;
+use crate::proto::CalcResponse;
+
+/// R5 clock readings from one reply, in ns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct R5Timing {
+    pub rx_ns: u64,
+    pub tx_ns: u64,
+    pub compute_ns: u64,
+}
+
+impl R5Timing {
+    /// The timing in `resp`, or `None` if the R5 has no clock (all zero) or
+    /// the readings are inconsistent.
+    pub fn from_response(resp: &CalcResponse) -> Option<Self> {
+        let timing = Self {
+            rx_ns: resp.r5_rx_ns,
+            tx_ns: resp.r5_tx_ns,
+            compute_ns: resp.r5_compute_ns,
+        };
+        (timing.tx_ns != 0 && timing.tx_ns >= timing.rx_ns).then_some(timing)
+    }
+
+    /// Time from request arrival to reply on the R5.
+    pub fn on_r5(&self) -> Duration {
+        Duration::from_nanos(self.tx_ns - self.rx_ns)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencyBreakdown {
+    /// The readings the breakdown was computed from.
+    pub r5: R5Timing,
+    pub queueing: Duration,
+    pub processing: Duration,
+    pub transit: Duration,
+}
+
+impl LatencyBreakdown {
+    /// Split `rtt` using the R5's own readings. Components are clamped so
+    /// they never exceed what they are carved out of.
+    pub fn new(rtt: Duration, r5: &R5Timing) -> Self {
+        let on_r5 = r5.on_r5();
+        let processing = Duration::from_nanos(r5.compute_ns).min(on_r5);
+        Self {
+            r5: *r5,
+            queueing: on_r5 - processing,
+            processing,
+            transit: rtt.saturating_sub(on_r5),
+        }
+    }
+
+    /// `(component, duration)` pairs, as labelled in metrics and spans.
+    pub fn components(&self) -> [(&'static str, Duration); 3] {
+        [
+            ("queueing", self.queueing),
+            ("processing", self.processing),
+            ("transit", self.transit),
+        ]
+    }
+
+    /// Add to `linux_gateway_latency_seconds`.
+    pub fn record(&self) {
+        for (component, d) in self.components() {
+            metrics()
+                .latency
+                .with_label_values(&[component])
+                .observe(d.as_secs_f64());
+        }
+    }
+}
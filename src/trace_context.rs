@@ -0,0 +1,131 @@
+//! W3C Trace Context for [`TraceCtx`]: random ids and conversion to and from
+//! the `traceparent` / `tracestate` HTTP headers.
+//!
+//! `traceparent` is `{version}-{trace_id}-{span_id}-{flags}` in lowercase hex,
+//! e.g. `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`. Only the
+//! sampled flag is defined for version 00; other flag bits are cleared when a
+//! header is parsed, as the spec requires of anything propagating it.
+
+use rand::RngCore;
+
+use crate::proto::TraceCtx;
+
+/// `flags` bit: the caller is recording this trace.
+pub const FLAG_SAMPLED: u32 = 0x01;
+
+/// Longest `tracestate` the R5 carries (nanopb `max_size:64`, less the NUL).
+pub const TRACESTATE_MAX: usize = 63;
+
+/// W3C cap on a single tracestate list-member.
+const MEMBER_MAX: usize = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum TraceparentError {
+    #[error("malformed traceparent")]
+    Malformed,
+    #[error("unsupported traceparent version {0:#04x}")]
+    Version(u8),
+    #[error("all-zero trace or span id")]
+    ZeroId,
+}
+
+impl TraceCtx {
+    /// A new trace with random, non-zero ids.
+    pub fn random(sampled: bool) -> Self {
+        let mut rng = rand::thread_rng();
+        Self {
+            trace_id: nonzero_bytes::<16>(&mut rng),
+            span_id: nonzero_bytes::<8>(&mut rng),
+            flags: if sampled { FLAG_SAMPLED } else { 0 },
+            tracestate: String::new(),
+        }
+    }
+
+    /// A new span in the same trace, keeping flags and tracestate.
+    pub fn child(&self) -> Self {
+        Self {
+            span_id: nonzero_bytes::<8>(&mut rand::thread_rng()),
+            ..self.clone()
+        }
+    }
+
+    pub fn is_sampled(&self) -> bool {
+        self.flags & FLAG_SAMPLED != 0
+    }
+
+    /// The `traceparent` header for this context (version 00).
+    pub fn traceparent(&self) -> String {
+        format!(
+            "00-{}-{}-{:02x}",
+            hex::encode(&self.trace_id),
+            hex::encode(&self.span_id),
+            self.flags & FLAG_SAMPLED
+        )
+    }
+
+    /// Parse `traceparent` and an optional `tracestate` header. The
+    /// tracestate is cleaned up and cut to fit the R5 ([`fit_tracestate`]).
+    pub fn from_w3c(traceparent: &str, tracestate: Option<&str>) -> Result<Self, TraceparentError> {
+        let mut parts = traceparent.trim().split('-');
+        let mut field = |len: usize| {
+            let part = parts.next().ok_or(TraceparentError::Malformed)?;
+            let lower_hex = part
+                .bytes()
+                .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+            if part.len() != len || !lower_hex {
+                return Err(TraceparentError::Malformed);
+            }
+            Ok(hex::decode(part).expect("checked hex"))
+        };
+        let version = field(2)?[0];
+        if version == 0xff {
+            return Err(TraceparentError::Version(version));
+        }
+        let trace_id = field(32)?;
+        let span_id = field(16)?;
+        let flags = field(2)?[0];
+        // Version 00 has exactly four fields; later versions may append more.
+        if version == 0 && parts.next().is_some() {
+            return Err(TraceparentError::Malformed);
+        }
+        if trace_id.iter().all(|&b| b == 0) || span_id.iter().all(|&b| b == 0) {
+            return Err(TraceparentError::ZeroId);
+        }
+        Ok(Self {
+            trace_id,
+            span_id,
+            flags: u32::from(flags) & FLAG_SAMPLED,
+            tracestate: tracestate.map(fit_tracestate).unwrap_or_default(),
+        })
+    }
+}
+
+fn nonzero_bytes<const N: usize>(rng: &mut impl RngCore) -> Vec<u8> {
+    let mut id = [0u8; N];
+    while id.iter().all(|&b| b == 0) {
+        rng.fill_bytes(&mut id);
+    }
+    id.to_vec()
+}
+
+/// Normalise a `tracestate` header and cut it to [`TRACESTATE_MAX`] bytes.
+/// Empty and oversized list-members go first, then members from the end,
+/// following the W3C truncation rules.
+pub fn fit_tracestate(header: &str) -> String {
+    let mut out = String::new();
+    let members = header
+        .split(',')
+        .map(str::trim)
+        .filter(|m| !m.is_empty() && m.len() <= MEMBER_MAX && m.contains('='));
+    for member in members {
+        let sep = usize::from(!out.is_empty());
+        if out.len() + sep + member.len() > TRACESTATE_MAX {
+            break;
+        }
+        if sep == 1 {
+            out.push(',');
+        }
+        out.push_str(member);
+    }
+    out
+}
@@ -0,0 +1,266 @@
+//! Where frames go: a `Transport` sends and receives whole frames, whatever
+//! the underlying link is.
+//!
+//! Byte-stream links (rpmsg chardev, UNIX socket, TCP) share
+//! [`StreamTransport`], which delimits frames with [`FrameDecoder`]. Tests use
+//! the in-memory [`MemoryTransport::pair`].
+
+use std::io::{self, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+
+use async_trait::async_trait;
+use tokio::io::unix::AsyncFd;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::net::{tcp, unix, TcpStream, ToSocketAddrs, UnixStream};
+use tokio::sync::{mpsc, Mutex};
+
+use crate::metrics;
+use crate::wire::{self, DecodeEvent, FrameDecoder};
+
+/// Async send/recv of complete frames. Both may be called concurrently.
+#[async_trait]
+pub trait Transport: Send + Sync {
+    /// Send one complete frame.
+    async fn send(&self, frame: &[u8]) -> io::Result<()>;
+
+    /// Receive the next complete frame. Fails with
+    /// [`io::ErrorKind::UnexpectedEof`] once the peer is gone.
+    async fn recv(&self) -> io::Result<Vec<u8>>;
+}
+
+#[async_trait]
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    async fn send(&self, frame: &[u8]) -> io::Result<()> {
+        (**self).send(frame).await
+    }
+    async fn recv(&self) -> io::Result<Vec<u8>> {
+        (**self).recv().await
+    }
+}
+
+#[async_trait]
+impl<T: Transport + ?Sized> Transport for Arc<T> {
+    async fn send(&self, frame: &[u8]) -> io::Result<()> {
+        (**self).send(frame).await
+    }
+    async fn recv(&self) -> io::Result<Vec<u8>> {
+        (**self).recv().await
+    }
+}
+
+/// Read size for stream links; one rpmsg read returns at most one 512-byte buffer.
+const READ_CHUNK: usize = 512;
+
+/// Frames over any byte stream, delimited and resynced by [`FrameDecoder`].
+/// v1 frames get a SYNC prefix on the way out, which `recv` strips again.
+pub struct StreamTransport<R, W> {
+    reader: Mutex<(R, FrameDecoder)>,
+    writer: Mutex<W>,
+}
+
+pub type UnixTransport = StreamTransport<unix::OwnedReadHalf, unix::OwnedWriteHalf>;
+pub type TcpTransport = StreamTransport<tcp::OwnedReadHalf, tcp::OwnedWriteHalf>;
+pub type RpmsgTransport =
+    StreamTransport<tokio::io::ReadHalf<RpmsgChardev>, tokio::io::WriteHalf<RpmsgChardev>>;
+
+impl<R, W> StreamTransport<R, W> {
+    pub fn from_parts(reader: R, writer: W) -> Self {
+        Self {
+            reader: Mutex::new((reader, FrameDecoder::new())),
+            writer: Mutex::new(writer),
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite> StreamTransport<tokio::io::ReadHalf<S>, tokio::io::WriteHalf<S>> {
+    pub fn new(stream: S) -> Self {
+        let (rd, wr) = tokio::io::split(stream);
+        Self::from_parts(rd, wr)
+    }
+}
+
+impl UnixTransport {
+    pub async fn connect_unix(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::from_unix(UnixStream::connect(path).await?))
+    }
+
+    pub fn from_unix(stream: UnixStream) -> Self {
+        let (rd, wr) = stream.into_split();
+        Self::from_parts(rd, wr)
+    }
+}
+
+impl TcpTransport {
+    pub async fn connect_tcp(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Ok(Self::from_tcp(TcpStream::connect(addr).await?))
+    }
+
+    pub fn from_tcp(stream: TcpStream) -> Self {
+        stream.set_nodelay(true).ok();
+        let (rd, wr) = stream.into_split();
+        Self::from_parts(rd, wr)
+    }
+}
+
+impl RpmsgTransport {
+    /// Open an rpmsg endpoint chardev such as `/dev/rpmsg0`.
+    pub fn open_rpmsg(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(RpmsgChardev::open(path)?))
+    }
+}
+
+#[async_trait]
+impl<R, W> Transport for StreamTransport<R, W>
+where
+    R: AsyncRead + Unpin + Send,
+    W: AsyncWrite + Unpin + Send,
+{
+    async fn send(&self, frame: &[u8]) -> io::Result<()> {
+        let mut w = self.writer.lock().await;
+        if frame.starts_with(&wire::SYNC.to_be_bytes()) {
+            w.write_all(frame).await?;
+        } else {
+            w.write_all(&wire::wrap_sync(frame)).await?;
+        }
+        w.flush().await?;
+        metrics::count_frame("tx", frame);
+        Ok(())
+    }
+
+    async fn recv(&self) -> io::Result<Vec<u8>> {
+        let mut guard = self.reader.lock().await;
+        let (rd, decoder) = &mut *guard;
+        let mut buf = [0u8; READ_CHUNK];
+        loop {
+            while let Some(event) = decoder.next_event() {
+                match event {
+                    DecodeEvent::Frame(frame) => {
+                        metrics::count_frame("rx", &frame);
+                        return Ok(frame);
+                    }
+                    DecodeEvent::Skipped { bytes, reason } => {
+                        tracing::debug!(bytes, %reason, "skipped bytes on stream");
+                        metrics::count_decode_error(&reason);
+                    }
+                }
+            }
+            let n = rd.read(&mut buf).await?;
+            if n == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            decoder.push(&buf[..n]);
+        }
+    }
+}
+
+/// Non-blocking handle on an rpmsg endpoint chardev (`/dev/rpmsgN`).
+///
+/// Each write is one rpmsg message and each read returns one message, so
+/// frames must fit the endpoint MTU.
+pub struct RpmsgChardev {
+    fd: AsyncFd<std::fs::File>,
+}
+
+impl RpmsgChardev {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(path)?;
+        Ok(Self {
+            fd: AsyncFd::new(file)?,
+        })
+    }
+}
+
+impl AsyncRead for RpmsgChardev {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            let mut guard = ready!(self.fd.poll_read_ready(cx))?;
+            let unfilled = buf.initialize_unfilled();
+            match guard.try_io(|f| f.get_ref().read(unfilled)) {
+                Ok(Ok(n)) => {
+                    buf.advance(n);
+                    return Poll::Ready(Ok(()));
+                }
+                Ok(Err(e)) => return Poll::Ready(Err(e)),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+impl AsyncWrite for RpmsgChardev {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            let mut guard = ready!(self.fd.poll_write_ready(cx))?;
+            match guard.try_io(|f| f.get_ref().write(buf)) {
+                Ok(res) => return Poll::Ready(res),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// One end of an in-memory frame link.
+pub struct MemoryTransport {
+    tx: mpsc::UnboundedSender<Vec<u8>>,
+    rx: Mutex<mpsc::UnboundedReceiver<Vec<u8>>>,
+}
+
+impl MemoryTransport {
+    /// Two connected ends; frames sent on one are received on the other.
+    pub fn pair() -> (Self, Self) {
+        let (a_tx, a_rx) = mpsc::unbounded_channel();
+        let (b_tx, b_rx) = mpsc::unbounded_channel();
+        (
+            Self {
+                tx: a_tx,
+                rx: Mutex::new(b_rx),
+            },
+            Self {
+                tx: b_tx,
+                rx: Mutex::new(a_rx),
+            },
+        )
+    }
+}
+
+#[async_trait]
+impl Transport for MemoryTransport {
+    async fn send(&self, frame: &[u8]) -> io::Result<()> {
+        self.tx
+            .send(frame.to_vec())
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+    }
+
+    async fn recv(&self) -> io::Result<Vec<u8>> {
+        self.rx
+            .lock()
+            .await
+            .recv()
+            .await
+            .ok_or_else(|| io::ErrorKind::UnexpectedEof.into())
+    }
+}
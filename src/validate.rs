@@ -0,0 +1,148 @@
+//! Semantic checks on decoded messages.
+//!
+//! prost takes any well-formed message: a 3-byte `trace_id`, an `op` of 7,
+//! flag bits nobody defined. [`Validate`] says what is wrong with one, and
+//! the decode functions apply it per [`Validation`] (set in
+//! [`DecodeLimits`](crate::limits::DecodeLimits)): refuse the message with
+//! [`FrameError::Invalid`], log and pass it on, or skip the checks.
+
+use crate::proto::{
+    CalcError, CalcRequest, CalcResponse, Op, Status, TimeSyncRequest, TimeSyncResponse, TraceCtx,
+};
+use crate::trace_context::FLAG_SAMPLED;
+use crate::FrameError;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ValidationError {
+    #[error("{field} is {len} bytes, expected {expected}")]
+    Length {
+        field: &'static str,
+        len: usize,
+        expected: usize,
+    },
+    #[error("{field} is all zero")]
+    ZeroId { field: &'static str },
+    #[error("{field} {value} is not in the enum")]
+    UnknownEnum { field: &'static str, value: i32 },
+    #[error("{field} has undefined bits {bits:#x}")]
+    UndefinedFlags { field: &'static str, bits: u32 },
+}
+
+/// What the decode functions do with a message that fails [`Validate`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Validation {
+    /// Refuse it with [`FrameError::Invalid`].
+    #[default]
+    Strict,
+    /// Log a warning and pass it on.
+    Permissive,
+    /// Do not check.
+    Off,
+}
+
+pub trait Validate {
+    /// Add what is wrong with `self` to `errors`.
+    fn check(&self, errors: &mut Vec<ValidationError>);
+
+    /// Everything wrong with `self`, if anything is.
+    fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+        self.check(&mut errors);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Apply `mode` to a decoded `msg` of frame type `typ`.
+pub fn apply<M: Validate>(msg: M, typ: u8, mode: Validation) -> Result<M, FrameError> {
+    if mode == Validation::Off {
+        return Ok(msg);
+    }
+    match msg.validate() {
+        Ok(()) => Ok(msg),
+        Err(errors) if mode == Validation::Permissive => {
+            let problems = join(&errors);
+            tracing::warn!(typ, %problems, "passing on invalid message");
+            Ok(msg)
+        }
+        Err(errors) => Err(FrameError::Invalid(errors)),
+    }
+}
+
+/// `errors` as one line, for logs and [`FrameError::Invalid`]'s message.
+pub fn join(errors: &[ValidationError]) -> String {
+    errors
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn check_id(field: &'static str, id: &[u8], expected: usize, errors: &mut Vec<ValidationError>) {
+    if id.len() != expected {
+        errors.push(ValidationError::Length {
+            field,
+            len: id.len(),
+            expected,
+        });
+    } else if id.iter().all(|&b| b == 0) {
+        errors.push(ValidationError::ZeroId { field });
+    }
+}
+
+fn check_enum<E: TryFrom<i32>>(field: &'static str, value: i32, errors: &mut Vec<ValidationError>) {
+    if E::try_from(value).is_err() {
+        errors.push(ValidationError::UnknownEnum { field, value });
+    }
+}
+
+fn check_trace(trace: &Option<TraceCtx>, errors: &mut Vec<ValidationError>) {
+    if let Some(trace) = trace {
+        trace.check(errors);
+    }
+}
+
+impl Validate for TraceCtx {
+    fn check(&self, errors: &mut Vec<ValidationError>) {
+        check_id("trace.trace_id", &self.trace_id, 16, errors);
+        check_id("trace.span_id", &self.span_id, 8, errors);
+        let bits = self.flags & !FLAG_SAMPLED;
+        if bits != 0 {
+            errors.push(ValidationError::UndefinedFlags {
+                field: "trace.flags",
+                bits,
+            });
+        }
+    }
+}
+
+impl Validate for CalcRequest {
+    fn check(&self, errors: &mut Vec<ValidationError>) {
+        check_enum::<Op>("op", self.op, errors);
+        check_trace(&self.trace, errors);
+    }
+}
+
+impl Validate for CalcResponse {
+    fn check(&self, errors: &mut Vec<ValidationError>) {
+        check_trace(&self.trace, errors);
+    }
+}
+
+impl Validate for CalcError {
+    fn check(&self, errors: &mut Vec<ValidationError>) {
+        check_enum::<Status>("code", self.code, errors);
+        check_trace(&self.trace, errors);
+    }
+}
+
+impl Validate for TimeSyncRequest {
+    fn check(&self, _: &mut Vec<ValidationError>) {}
+}
+
+impl Validate for TimeSyncResponse {
+    fn check(&self, _: &mut Vec<ValidationError>) {}
+}
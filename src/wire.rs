@@ -0,0 +1,571 @@
+use std::collections::VecDeque;
+
+use crate::auth::AuthKey;
+use crate::checksum::ChecksumKind;
+use crate::FrameError;
+
+// Protocol constants (SYNC is audit-visible; v1 does not use it on the wire)
+pub const SYNC: u16 = 0xA55A;
+pub const PROTO_VERSION: u8 = 1;
+pub const PROTO_VERSION_V2: u8 = 2;
+pub const TYPE_REQ: u8 = 1;
+pub const TYPE_RESP: u8 = 2;
+pub const TYPE_ERR: u8 = 3;
+pub const TYPE_TIME_REQ: u8 = 4;
+pub const TYPE_TIME_RESP: u8 = 5;
+
+/// v2 header: [SYNC(2, BE)][ver=2][type][flags][len(2, BE)][seq(2, BE)][crc8(header[..9])]
+pub const V2_HEADER_LEN: usize = 10;
+
+/// v2 `flags` bits marking a fragment of a larger frame ([`crate::fragment`]).
+pub const FLAG_FRAG_FIRST: u8 = 0x01;
+pub const FLAG_FRAG_MIDDLE: u8 = 0x02;
+pub const FLAG_FRAG_LAST: u8 = 0x04;
+pub const FLAG_FRAG_MASK: u8 = 0x07;
+
+/// Fragment sub-header at the start of the payload: [index(2, BE)][total(2, BE)].
+pub const FRAG_HEADER_LEN: usize = 4;
+
+/// v2 `flags` bits choosing the payload checksum ([`crate::checksum`]).
+pub const FLAG_CSUM_MASK: u8 = 0x30;
+
+/// v2 `flags` bit marking an authenticated frame ([`seal_auth`]).
+pub const FLAG_AUTH: u8 = 0x80;
+
+/// Authentication block at the end of the payload of a [`FLAG_AUTH`] frame:
+/// [counter(8, BE)][HMAC-SHA256 truncated to 16 bytes].
+pub const AUTH_COUNTER_LEN: usize = 8;
+pub const AUTH_TAG_LEN: usize = 16;
+pub const AUTH_LEN: usize = AUTH_COUNTER_LEN + AUTH_TAG_LEN;
+
+/// Largest frame (without a v1 SYNC prefix) the stream decoder will wait for.
+/// Matches the usable payload of a 512-byte RPMsg buffer.
+pub const DEFAULT_MAX_FRAME_LEN: usize = 496;
+
+#[inline]
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32fast::hash(data)
+}
+
+/// CRC-8 (poly 0x07, init 0) protecting the v2 header.
+pub fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |mut crc, &b| {
+        crc ^= b;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+fn is_known_type(typ: u8) -> bool {
+    crate::message::is_registered(typ)
+}
+
+pub fn wrap_v1(typ: u8, payload: &[u8]) -> Vec<u8> {
+    // v1 frame = [ver=1][type][payload...][crc32(payload) little-endian]
+    let mut frame = Vec::with_capacity(1 + 1 + payload.len() + 4);
+    frame.push(PROTO_VERSION);
+    frame.push(typ);
+    frame.extend_from_slice(payload);
+    frame.extend_from_slice(&crc32(payload).to_le_bytes());
+    frame
+}
+
+pub fn wrap_v1_req(payload: &[u8]) -> Vec<u8> {
+    wrap_v1(TYPE_REQ, payload)
+}
+pub fn wrap_v1_resp(payload: &[u8]) -> Vec<u8> {
+    wrap_v1(TYPE_RESP, payload)
+}
+
+/// Prefix a v1 frame with the SYNC word so it can be sent over a byte stream
+/// and picked up again by [`FrameDecoder`].
+pub fn wrap_sync(frame: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(2 + frame.len());
+    out.extend_from_slice(&SYNC.to_be_bytes());
+    out.extend_from_slice(frame);
+    out
+}
+
+fn unwrap_v1_typed(frame: &[u8], expect_typ: u8) -> Result<&[u8], FrameError> {
+    // Need at least: ver(1) + type(1) + crc(4) = 6 bytes
+    if frame.len() < 6 {
+        return Err(FrameError::TooShort);
+    }
+    let ver = frame[0];
+    if ver != PROTO_VERSION {
+        return Err(FrameError::UnknownVersion(ver));
+    }
+    let typ_actual = frame[1];
+    if typ_actual != expect_typ {
+        return Err(FrameError::UnknownType(typ_actual));
+    }
+
+    let payload_len = frame.len() - 2 - 4;
+    let (payload, crc_bytes) = frame[2..].split_at(payload_len);
+    let got = u32::from_le_bytes(crc_bytes.try_into().unwrap());
+    let want = crc32(payload);
+    if got != want {
+        return Err(FrameError::Crc);
+    }
+    Ok(payload)
+}
+
+pub fn unwrap_v1_req(frame: &[u8]) -> Result<&[u8], FrameError> {
+    unwrap_v1_typed(frame, TYPE_REQ)
+}
+pub fn unwrap_v1_resp(frame: &[u8]) -> Result<&[u8], FrameError> {
+    unwrap_v1_typed(frame, TYPE_RESP)
+}
+
+/// Header fields common to v1 and v2 frames. v1 frames report `flags = 0`
+/// and `seq = 0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub version: u8,
+    pub typ: u8,
+    pub flags: u8,
+    pub seq: u16,
+}
+
+/// A validated frame: its header and the CRC-checked payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame<'a> {
+    pub header: Header,
+    pub payload: &'a [u8],
+}
+
+/// Build a v2 frame: header, payload, then the checksum `flags` selects
+/// (crc32 unless [`FLAG_CSUM_MASK`] bits are set), little-endian.
+pub fn wrap_v2(typ: u8, flags: u8, seq: u16, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(v2_frame_len(flags, payload.len()));
+    frame.extend_from_slice(&v2_header(typ, flags, seq, payload.len()));
+    frame.extend_from_slice(payload);
+    frame.extend(ChecksumKind::from_flags(flags).checksum().trailer(payload));
+    frame
+}
+
+fn v2_header(typ: u8, flags: u8, seq: u16, len: usize) -> [u8; V2_HEADER_LEN] {
+    let len = u16::try_from(len).expect("v2 payload exceeds u16 length");
+    let mut header = [0; V2_HEADER_LEN];
+    header[..2].copy_from_slice(&SYNC.to_be_bytes());
+    header[2] = PROTO_VERSION_V2;
+    header[3] = typ;
+    header[4] = flags;
+    header[5..7].copy_from_slice(&len.to_be_bytes());
+    header[7..9].copy_from_slice(&seq.to_be_bytes());
+    header[9] = crc8(&header[..9]);
+    header
+}
+
+/// Length of a v2 frame with `flags` and a `len`-byte payload.
+pub fn v2_frame_len(flags: u8, len: usize) -> usize {
+    V2_HEADER_LEN + len + ChecksumKind::from_flags(flags).checksum().width()
+}
+
+pub fn wrap_v2_req(seq: u16, payload: &[u8]) -> Vec<u8> {
+    wrap_v2(TYPE_REQ, 0, seq, payload)
+}
+pub fn wrap_v2_resp(seq: u16, payload: &[u8]) -> Vec<u8> {
+    wrap_v2(TYPE_RESP, 0, seq, payload)
+}
+
+/// Parse and check a v2 header. Does not look at the payload.
+pub fn parse_v2_header(frame: &[u8]) -> Result<(Header, usize), FrameError> {
+    if frame.len() < V2_HEADER_LEN {
+        return Err(FrameError::TooShort);
+    }
+    if frame[..2] != SYNC.to_be_bytes() {
+        return Err(FrameError::NoSync);
+    }
+    if frame[2] != PROTO_VERSION_V2 {
+        return Err(FrameError::UnknownVersion(frame[2]));
+    }
+    if crc8(&frame[..9]) != frame[9] {
+        return Err(FrameError::HeaderCrc);
+    }
+    let header = Header {
+        version: frame[2],
+        typ: frame[3],
+        flags: frame[4],
+        seq: u16::from_be_bytes([frame[7], frame[8]]),
+    };
+    if !is_known_type(header.typ) {
+        return Err(FrameError::UnknownType(header.typ));
+    }
+    let len = u16::from_be_bytes([frame[5], frame[6]]) as usize;
+    Ok((header, len))
+}
+
+/// Replace the seq of a v2 frame in place and fix up the header CRC; the
+/// payload CRC does not cover the header, so it stays valid. Returns the old
+/// seq.
+pub fn set_seq(frame: &mut [u8], seq: u16) -> Result<u16, FrameError> {
+    let (header, _) = parse_v2_header(frame)?;
+    frame[7..9].copy_from_slice(&seq.to_be_bytes());
+    frame[9] = crc8(&frame[..9]);
+    Ok(header.seq)
+}
+
+fn unwrap_v2_frame(frame: &[u8]) -> Result<Frame<'_>, FrameError> {
+    let (header, len) = parse_v2_header(frame)?;
+    let end = V2_HEADER_LEN + len;
+    let total = v2_frame_len(header.flags, len);
+    // Bytes after the trailing checksum are ignored, as on the R5 side.
+    if frame.len() < total {
+        return Err(FrameError::TooShort);
+    }
+    let payload = &frame[V2_HEADER_LEN..end];
+    let checksum = ChecksumKind::from_flags(header.flags).checksum();
+    if !checksum.verify(payload, &frame[end..total]) {
+        return Err(FrameError::Crc);
+    }
+    Ok(Frame { header, payload })
+}
+
+/// `frame` with its payload checksum switched to `kind`. v1 frames, which
+/// always carry crc32, are returned as they are.
+pub fn set_checksum(frame: &[u8], kind: ChecksumKind) -> Result<Vec<u8>, FrameError> {
+    if !frame.starts_with(&SYNC.to_be_bytes()) {
+        return Ok(frame.to_vec());
+    }
+    let f = unwrap_v2_frame(frame)?;
+    if ChecksumKind::from_flags(f.header.flags) == kind {
+        return Ok(frame.to_vec());
+    }
+    let flags = f.header.flags & !FLAG_CSUM_MASK | kind.flag_bits();
+    Ok(wrap_v2(f.header.typ, flags, f.header.seq, f.payload))
+}
+
+/// Authenticate a v2 frame with `key`: set [`FLAG_AUTH`] and append
+/// `counter` and a tag over the header, payload and counter to the payload.
+/// The caller picks a `counter` above any it used before with this key.
+pub fn seal_auth(frame: &[u8], key: &AuthKey, counter: u64) -> Result<Vec<u8>, FrameError> {
+    if !frame.starts_with(&SYNC.to_be_bytes()) {
+        return Err(FrameError::Auth("v1 frames cannot be authenticated"));
+    }
+    let f = unwrap_v2_frame(frame)?;
+    if f.header.flags & FLAG_AUTH != 0 {
+        return Err(FrameError::Auth("already authenticated"));
+    }
+    let flags = f.header.flags | FLAG_AUTH;
+    let len = f.payload.len() + AUTH_LEN;
+    let mut body = Vec::with_capacity(len);
+    body.extend_from_slice(f.payload);
+    body.extend_from_slice(&counter.to_be_bytes());
+    let header = v2_header(f.header.typ, flags, f.header.seq, len);
+    body.extend_from_slice(&key.tag(&[&header, &body]));
+    Ok(wrap_v2(f.header.typ, flags, f.header.seq, &body))
+}
+
+/// Check the tag of a frame made by [`seal_auth`]. Returns the frame as it
+/// was before sealing, and its counter for the caller's
+/// [`ReplayWindow`](crate::auth::ReplayWindow).
+pub fn open_auth(frame: &[u8], key: &AuthKey) -> Result<(Vec<u8>, u64), FrameError> {
+    if !frame.starts_with(&SYNC.to_be_bytes()) {
+        return Err(FrameError::Auth("not authenticated"));
+    }
+    let f = unwrap_v2_frame(frame)?;
+    if f.header.flags & FLAG_AUTH == 0 {
+        return Err(FrameError::Auth("not authenticated"));
+    }
+    let Some(at) = f.payload.len().checked_sub(AUTH_LEN) else {
+        return Err(FrameError::Auth("short authentication block"));
+    };
+    let (body, tag) = f.payload.split_at(at + AUTH_COUNTER_LEN);
+    if !key.verify(&[&frame[..V2_HEADER_LEN], body], tag) {
+        return Err(FrameError::Auth("bad tag"));
+    }
+    let counter = u64::from_be_bytes(body[at..].try_into().unwrap());
+    let flags = f.header.flags & !FLAG_AUTH;
+    Ok((
+        wrap_v2(f.header.typ, flags, f.header.seq, &body[..at]),
+        counter,
+    ))
+}
+
+/// Decode a v1 or v2 frame of any known type, whichever is on the wire.
+pub fn unwrap_any(frame: &[u8]) -> Result<Frame<'_>, FrameError> {
+    if frame.starts_with(&SYNC.to_be_bytes()) {
+        return unwrap_v2_frame(frame);
+    }
+    let typ = *frame.get(1).ok_or(FrameError::TooShort)?;
+    if frame[0] == PROTO_VERSION && !is_known_type(typ) {
+        return Err(FrameError::UnknownType(typ));
+    }
+    let payload = unwrap_v1_typed(frame, typ)?;
+    Ok(Frame {
+        header: Header {
+            version: PROTO_VERSION,
+            typ,
+            flags: 0,
+            seq: 0,
+        },
+        payload,
+    })
+}
+
+fn unwrap_v2_typed(frame: &[u8], expect_typ: u8) -> Result<Frame<'_>, FrameError> {
+    let f = unwrap_any(frame)?;
+    if f.header.typ != expect_typ {
+        return Err(FrameError::UnknownType(f.header.typ));
+    }
+    Ok(f)
+}
+
+/// Unwrap a request frame in either v1 or v2 layout.
+pub fn unwrap_v2_req(frame: &[u8]) -> Result<Frame<'_>, FrameError> {
+    unwrap_v2_typed(frame, TYPE_REQ)
+}
+/// Unwrap a response frame in either v1 or v2 layout.
+pub fn unwrap_v2_resp(frame: &[u8]) -> Result<Frame<'_>, FrameError> {
+    unwrap_v2_typed(frame, TYPE_RESP)
+}
+
+/// One result of feeding bytes into a [`FrameDecoder`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeEvent {
+    /// A complete, CRC-checked frame ready for [`unwrap_any`]: v1 frames have
+    /// their SYNC prefix stripped, v2 frames are returned whole.
+    Frame(Vec<u8>),
+    /// `bytes` were dropped from the stream while resynchronizing.
+    Skipped { bytes: usize, reason: FrameError },
+}
+
+/// Stateful decoder for SYNC-prefixed frames arriving over a byte stream.
+///
+/// Stream layout is either `[0xA5][0x5A][v1 frame]` or a v2 frame, which starts
+/// with SYNC itself. v2 frames are delimited by their header length. Since v1
+/// carries no length, the end of a v1 frame is the first position where the
+/// trailing CRC matches the bytes before it. Chunks may split or merge frames
+/// arbitrarily; anything that does not form a valid frame is reported as
+/// [`DecodeEvent::Skipped`].
+#[derive(Debug)]
+pub struct FrameDecoder {
+    buf: Vec<u8>,
+    max_frame_len: usize,
+    // The candidate at the head of `buf`, and those at later SYNC words up to
+    // `synced_to`. Each keeps its progress, so bytes are scanned once per
+    // candidate however the stream is chunked.
+    head: Candidate,
+    later: VecDeque<Candidate>,
+    synced_to: usize,
+}
+
+/// A frame that may start at SYNC offset `at` of the buffer.
+#[derive(Debug, Clone)]
+struct Candidate {
+    at: usize,
+    // v1: next candidate end (relative to `at`) and the CRC over the payload
+    // before it, buf[at + 4..at + end - 4].
+    end: usize,
+    crc: crc32fast::Hasher,
+}
+
+impl Candidate {
+    fn new(at: usize) -> Self {
+        Self {
+            at,
+            end: 8,
+            crc: crc32fast::Hasher::new(),
+        }
+    }
+
+    // Check the candidate against `buf`, which starts at its SYNC.
+    // Ok(Some(end)) = complete frame ending at buf[end];
+    // Ok(None) = need more bytes.
+    fn advance(&mut self, buf: &[u8], max_frame_len: usize) -> Result<Option<usize>, FrameError> {
+        check_header(buf)?;
+        if buf.len() < 4 {
+            return Ok(None);
+        }
+        if buf[2] == PROTO_VERSION_V2 {
+            if buf.len() < V2_HEADER_LEN {
+                return Ok(None);
+            }
+            let (header, len) = parse_v2_header(buf)?;
+            let total = v2_frame_len(header.flags, len);
+            if total > max_frame_len {
+                return Err(FrameError::TooLong(total));
+            }
+            if buf.len() < total {
+                return Ok(None);
+            }
+            unwrap_v2_frame(&buf[..total])?;
+            return Ok(Some(total));
+        }
+
+        let limit = buf.len().min(2 + max_frame_len);
+        while self.end <= limit {
+            let end = self.end;
+            let want = u32::from_le_bytes(buf[end - 4..end].try_into().unwrap());
+            if self.crc.clone().finalize() == want {
+                return Ok(Some(end));
+            }
+            self.crc.update(&buf[end - 4..end - 3]);
+            self.end += 1;
+        }
+        if self.end > 2 + max_frame_len {
+            return Err(FrameError::Crc);
+        }
+        Ok(None)
+    }
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Self::with_max_frame_len(DEFAULT_MAX_FRAME_LEN)
+    }
+
+    /// `max_frame_len` bounds a single frame (excluding a v1 SYNC prefix). A v1
+    /// candidate that grows past it without a CRC match is dropped as
+    /// [`FrameError::Crc`]; a v2 header announcing more is [`FrameError::TooLong`].
+    pub fn with_max_frame_len(max_frame_len: usize) -> Self {
+        Self {
+            buf: Vec::new(),
+            max_frame_len: max_frame_len.max(6),
+            head: Candidate::new(0),
+            later: VecDeque::new(),
+            synced_to: 1,
+        }
+    }
+
+    /// Append a chunk read from the stream.
+    pub fn push(&mut self, chunk: &[u8]) {
+        self.buf.extend_from_slice(chunk);
+    }
+
+    /// Bytes buffered but not yet returned as a frame or skipped.
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Drop all buffered state, e.g. after the underlying device was reopened.
+    pub fn reset(&mut self) {
+        self.buf.clear();
+        self.head = Candidate::new(0);
+        self.later.clear();
+        self.synced_to = 1;
+    }
+
+    /// Push `chunk` and drain every event it completes.
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<DecodeEvent> {
+        self.push(chunk);
+        std::iter::from_fn(|| self.next_event()).collect()
+    }
+
+    /// Next frame or skip, or `None` when more input is needed.
+    pub fn next_event(&mut self) -> Option<DecodeEvent> {
+        // Hunt for SYNC; keep a trailing 0xA5 since it may be half a SYNC word.
+        match find_sync(&self.buf) {
+            Some(0) => {}
+            Some(at) => return Some(self.skip(at, FrameError::NoSync)),
+            None => {
+                let keep = usize::from(self.buf.last() == Some(&SYNC.to_be_bytes()[0]));
+                let drop = self.buf.len() - keep;
+                return (drop > 0).then(|| self.skip(drop, FrameError::NoSync));
+            }
+        }
+
+        match self.head.advance(&self.buf, self.max_frame_len) {
+            Ok(Some(end)) => {
+                // v1 frames are returned without their SYNC prefix.
+                let start = if self.buf[2] == PROTO_VERSION_V2 {
+                    0
+                } else {
+                    2
+                };
+                let frame = self.buf[start..end].to_vec();
+                self.consume(end);
+                Some(DecodeEvent::Frame(frame))
+            }
+            // A v2 head whose header CRC passed is waiting for its payload;
+            // a SYNC inside that payload is not a reason to give up on it.
+            Ok(None) if self.buf.len() >= V2_HEADER_LEN && self.buf[2] == PROTO_VERSION_V2 => None,
+            // Still waiting on an unverified head. If a later SYNC already
+            // starts a complete frame, the head was a false SYNC.
+            Ok(None) => self
+                .complete_later()
+                .map(|at| self.skip(at, FrameError::Crc)),
+            Err(reason) => {
+                let next = find_sync(&self.buf[1..]).map_or(self.buf.len(), |i| i + 1);
+                Some(self.skip(next, reason))
+            }
+        }
+    }
+
+    // Offset of the first later candidate that is a complete frame. Candidates
+    // that cannot become one are dropped on the way.
+    fn complete_later(&mut self) -> Option<usize> {
+        let last = self.buf.len().saturating_sub(1);
+        for at in self.synced_to..last {
+            if self.buf[at..].starts_with(&SYNC.to_be_bytes()) {
+                self.later.push_back(Candidate::new(at));
+            }
+        }
+        self.synced_to = self.synced_to.max(last);
+
+        let mut i = 0;
+        while let Some(c) = self.later.get_mut(i) {
+            match c.advance(&self.buf[c.at..], self.max_frame_len) {
+                Ok(Some(_)) => return Some(c.at),
+                Ok(None) => i += 1,
+                Err(_) => {
+                    self.later.remove(i);
+                }
+            }
+        }
+        None
+    }
+
+    fn skip(&mut self, bytes: usize, reason: FrameError) -> DecodeEvent {
+        self.consume(bytes);
+        DecodeEvent::Skipped { bytes, reason }
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.buf.drain(..n);
+        while self.later.front().is_some_and(|c| c.at < n) {
+            self.later.pop_front();
+        }
+        self.head = match self.later.front() {
+            Some(c) if c.at == n => self.later.pop_front().unwrap(),
+            _ => Candidate::new(n),
+        };
+        for c in std::iter::once(&mut self.head).chain(&mut self.later) {
+            c.at -= n;
+        }
+        self.synced_to = self.synced_to.saturating_sub(n).max(1);
+    }
+}
+
+fn find_sync(buf: &[u8]) -> Option<usize> {
+    buf.windows(2).position(|w| w == SYNC.to_be_bytes())
+}
+
+// Version/type checks on a SYNC-prefixed candidate, as far as bytes allow.
+// Both layouts keep the version at [2] and the type at [3].
+fn check_header(buf: &[u8]) -> Result<(), FrameError> {
+    if let Some(&ver) = buf.get(2) {
+        if ver != PROTO_VERSION && ver != PROTO_VERSION_V2 {
+            return Err(FrameError::UnknownVersion(ver));
+        }
+    }
+    if let Some(&typ) = buf.get(3) {
+        if !is_known_type(typ) {
+            return Err(FrameError::UnknownType(typ));
+        }
+    }
+    Ok(())
+}
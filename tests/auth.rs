@@ -0,0 +1,219 @@
+use linux_gateway::auth::{AuthKey, AuthTransport, ReplayWindow};
+use linux_gateway::checksum::{ChecksumKind, ChecksumTransport};
+use linux_gateway::client::{ClientConfig, GatewayClient};
+use linux_gateway::dissect::dissect;
+use linux_gateway::emulator::{R5Config, R5Peer};
+use linux_gateway::fragment::{self, FragmentingTransport};
+use linux_gateway::proto::{CalcRequest, CalcResponse, Op};
+use linux_gateway::transport::{MemoryTransport, Transport};
+use linux_gateway::{decode_calc_request, decode_calc_response, wire, FrameError};
+
+fn key() -> AuthKey {
+    AuthKey::new(&(0..32).collect::<Vec<u8>>()).unwrap()
+}
+
+/// `OP_MUL 6 7` at seq 1.
+fn mul_request() -> Vec<u8> {
+    wire::wrap_v2(wire::TYPE_REQ, 0, 1, &hex::decode("080210061807").unwrap())
+}
+
+/// Frames sealed with `key()` and what the `r5/` C build, through
+/// `frame_auth.c`, answered when fed them in this order. `None`: no reply.
+/// `tests/r5_c.rs` checks the emulator against that build directly.
+const C_EXCHANGE: [(&str, Option<&str>); 6] = [
+    // Counter 1000.
+    (
+        "A55A020180001E00017808021006180700000000000003E8D0576EE6E09C31CF8D0FA300A82D18641FB10ECF",
+        Some("A55A020280001A0001A8082A00000000000003E8B34402CA90D6C9CDAD1B276A684F0CA4194EF212"),
+    ),
+    // Counter 1001, crc16.
+    (
+        "A55A0201A0001E00021508021006180700000000000003E9ACA919128C6534E9C291074D9F55AF484D0A",
+        Some("A55A0202A0001A0002C5082A00000000000003E9FF32C4BA505ED4E9FDD07DACD9F0A3AA098F"),
+    ),
+    // Counter 1000 again.
+    (
+        "A55A020180001E00037608021006180700000000000003E85E97A52F4379DEC225741B9023705F86257C7951",
+        None,
+    ),
+    // Not authenticated.
+    ("A55A02010000060004070802100618077956BA6F", None),
+    // Counter 999: late, but inside the window.
+    (
+        "A55A020180001E00066D08021006180700000000000003E77C0FFF5771D30A34BC73F2A2150316BFCEEE9E6D",
+        Some("A55A020280001A0006BD082A00000000000003E724EE1CA720112861FF5EC4A391903ACBF3CA6BAC"),
+    ),
+    // Counter 900: below the window.
+    (
+        "A55A020180001E00076A0802100618070000000000000384DA73ADC2ADEC5F67FB345C7F3178FE6492202F2F",
+        None,
+    ),
+];
+
+#[test]
+fn sealing_tags_and_replay_window() {
+    // RFC 4231 test case 1, truncated.
+    let rfc = AuthKey::new(&[0x0B; 20]).unwrap();
+    assert_eq!(
+        hex::encode(rfc.tag(&[b"Hi ", b"There"])),
+        "b0344c61d8db38535ca8afceaf0bf12b"
+    );
+    assert!(AuthKey::new(&[1; 15]).is_none());
+    assert_eq!(format!("{rfc:?}"), "AuthKey(..)");
+
+    let frame = mul_request();
+    let sealed = wire::seal_auth(&frame, &key(), 7).unwrap();
+    assert_eq!(sealed[4], wire::FLAG_AUTH);
+    assert_eq!(sealed.len(), frame.len() + wire::AUTH_LEN);
+    assert_eq!(
+        wire::open_auth(&sealed, &key()).unwrap(),
+        (frame.clone(), 7)
+    );
+
+    // Decoders refuse what has not been opened.
+    let err = decode_calc_request(&sealed).unwrap_err();
+    assert_eq!(err, FrameError::Auth("tag not checked"));
+    assert_eq!(err.kind(), "auth");
+    let other = AuthKey::new(&[9; 16]).unwrap();
+    assert_eq!(
+        wire::open_auth(&sealed, &other),
+        Err(FrameError::Auth("bad tag"))
+    );
+    assert_eq!(
+        wire::open_auth(&frame, &key()),
+        Err(FrameError::Auth("not authenticated"))
+    );
+    // A changed header or message fails the tag even with a good checksum.
+    let mut forged = sealed.clone();
+    wire::set_seq(&mut forged, 2).unwrap();
+    assert_eq!(
+        wire::open_auth(&forged, &key()),
+        Err(FrameError::Auth("bad tag"))
+    );
+
+    let d = dissect(&sealed);
+    assert!(!d.has_problem());
+    let auth = d.nodes.iter().find(|n| n.label == "auth").unwrap();
+    assert_eq!(auth.children[0].value, "7");
+
+    let mut w = ReplayWindow::new();
+    for counter in [5, 7, 6, 70] {
+        assert_eq!(w.check(counter), Ok(()), "{counter}");
+    }
+    assert_eq!(w.check(7), Err(FrameError::Auth("replayed counter")));
+    assert_eq!(w.check(8), Ok(()));
+    assert_eq!(
+        w.check(6),
+        Err(FrameError::Auth("counter below replay window"))
+    );
+    assert_eq!(w.highest(), 70);
+    assert!(ReplayWindow::new().check(0).is_err());
+}
+
+#[test]
+fn emulator_authenticates_and_answers() {
+    let r5 = R5Peer::new(R5Config {
+        auth: Some(key()),
+        ..Default::default()
+    });
+    for (i, (frame, want)) in C_EXCHANGE.iter().enumerate() {
+        let reply = r5.handle_frame(&hex::decode(frame).unwrap());
+        assert_eq!(reply.map(hex::encode_upper).as_deref(), *want, "frame {i}");
+    }
+    let reply = hex::decode(C_EXCHANGE[1].1.unwrap()).unwrap();
+    let (plain, counter) = wire::open_auth(&reply, &key()).unwrap();
+    assert_eq!(counter, 1001);
+    assert_eq!(ChecksumKind::from_flags(plain[4]), ChecksumKind::Crc16Ccitt);
+    assert_eq!(decode_calc_response(&plain).unwrap().result, 42);
+
+    // Without a key the R5 has never heard of the flag.
+    let open = R5Peer::new(R5Config::default());
+    assert!(open.handle_frame(&mul_request()).is_some());
+}
+
+#[tokio::test]
+async fn auth_transport_guards_a_link() {
+    let config = R5Config {
+        auth: Some(key()),
+        ..Default::default()
+    };
+    let (link, _r5) = R5Peer::new(config).loopback();
+    let link = FragmentingTransport::new(link, fragment::DEFAULT_MTU);
+    let link = AuthTransport::new(link, key());
+    let link = ChecksumTransport::new(link, ChecksumKind::Crc32c);
+    let client = GatewayClient::new(link, ClientConfig::default());
+    for (a, b) in [(6, 7), (40, 2)] {
+        let req = CalcRequest {
+            op: Op::Mul as i32,
+            a,
+            b,
+            trace: None,
+        };
+        let resp: CalcResponse = client.call(&req, client.call_options(false)).await.unwrap();
+        assert_eq!(resp.result, a * b);
+    }
+
+    // Forged and replayed frames never reach the reader.
+    let (ours, theirs) = MemoryTransport::pair();
+    let ours = AuthTransport::with_counter(ours, key(), 1);
+    let reply = wire::wrap_v2(wire::TYPE_RESP, 0, 1, &[8, 42]);
+    let sealed = wire::seal_auth(&reply, &key(), 3).unwrap();
+    theirs.send(&reply).await.unwrap();
+    theirs.send(&sealed).await.unwrap();
+    theirs.send(&sealed).await.unwrap();
+    theirs
+        .send(&wire::seal_auth(&reply, &key(), 4).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(ours.recv().await.unwrap(), reply);
+    assert_eq!(ours.recv().await.unwrap(), reply);
+    ours.send(&reply).await.unwrap();
+    let sent = theirs.recv().await.unwrap();
+    assert_eq!(wire::open_auth(&sent, &key()).unwrap(), (reply, 1));
+}
+
+#[tokio::test]
+async fn auth_transport_drops_bad_tags_and_old_counters() {
+    let (ours, theirs) = MemoryTransport::pair();
+    let ours = AuthTransport::with_counter(ours, key(), 1);
+    // Each reply carries its counter, to tell which got through.
+    let reply = |counter: u64| wire::wrap_v2(wire::TYPE_RESP, 0, 1, &[8, counter as u8]);
+    let seal = |counter| wire::seal_auth(&reply(counter), &key(), counter).unwrap();
+
+    // A flipped tag bit under a good checksum.
+    let sealed = seal(50);
+    let mut payload = wire::unwrap_any(&sealed).unwrap().payload.to_vec();
+    *payload.last_mut().unwrap() ^= 1;
+    let bad_tag = wire::wrap_v2(wire::TYPE_RESP, wire::FLAG_AUTH, 1, &payload);
+    assert_eq!(
+        wire::open_auth(&bad_tag, &key()),
+        Err(FrameError::Auth("bad tag"))
+    );
+
+    // 36 is below the window once 100 is in; 0 is never accepted.
+    for frame in [seal(0), bad_tag, seal(100), seal(36), seal(0), seal(37)] {
+        theirs.send(&frame).await.unwrap();
+    }
+    assert_eq!(ours.recv().await.unwrap(), reply(100));
+    assert_eq!(ours.recv().await.unwrap(), reply(37));
+}
+
+#[tokio::test]
+async fn auth_transport_refuses_frames_it_cannot_seal() {
+    let (ours, theirs) = MemoryTransport::pair();
+    let ours = AuthTransport::with_counter(ours, key(), 1);
+    let v1 = wire::wrap_v1_req(&hex::decode("080210061807").unwrap());
+    let sealed = wire::seal_auth(&mul_request(), &key(), 9).unwrap();
+    let mut bad_crc = mul_request();
+    *bad_crc.last_mut().unwrap() ^= 1;
+    for frame in [v1, sealed, bad_crc] {
+        let err = ours.send(&frame).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    // Nothing went out in the clear.
+    ours.send(&mul_request()).await.unwrap();
+    let sent = theirs.recv().await.unwrap();
+    let (plain, _) = wire::open_auth(&sent, &key()).unwrap();
+    assert_eq!(plain, mul_request());
+}
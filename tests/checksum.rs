@@ -0,0 +1,155 @@
+use linux_gateway::checksum::{Checksum, ChecksumKind, ChecksumTransport, Crc16Ccitt, Crc32c};
+use linux_gateway::dissect::{dissect, CrcStatus};
+use linux_gateway::emulator::{Firmware, R5Config, R5Peer};
+use linux_gateway::proto::{CalcRequest, CalcResponse, Op};
+use linux_gateway::transport::{MemoryTransport, Transport};
+use linux_gateway::wire::{self, DecodeEvent, FrameDecoder};
+use linux_gateway::{decode_calc_response, encode_frame_v2, FrameError};
+
+/// `OP_MUL 6 7` at seq 1, with `kind`'s checksum.
+fn mul_request(kind: ChecksumKind) -> Vec<u8> {
+    let payload = hex::decode("080210061807").unwrap();
+    wire::wrap_v2(wire::TYPE_REQ, kind.flag_bits(), 1, &payload)
+}
+
+/// Replies from the `r5/` C build to `mul_request`, per checksum
+/// (`tests/r5_c.rs` checks the emulator against that build directly).
+const C_REPLIES: [(ChecksumKind, &str); 4] = [
+    (ChecksumKind::Crc32, "A55A02020000020001CC082A2151BB52"),
+    (ChecksumKind::Crc32c, "A55A02021000020001FE082A8C122A27"),
+    (ChecksumKind::Crc16Ccitt, "A55A02022000020001A8082A8E11"),
+    (ChecksumKind::None, "A55A020230000200019A082A"),
+];
+
+#[test]
+fn kinds_round_trip_and_are_checked() {
+    assert_eq!(Crc32c.compute(b"123456789"), 0xE306_9283);
+    assert_eq!(Crc16Ccitt.compute(b"123456789"), 0x29B1);
+    assert_eq!(Crc16Ccitt.trailer(b"123456789"), [0xB1, 0x29]);
+
+    let payload = [8, 2, 16, 6];
+    for kind in ChecksumKind::ALL {
+        assert_eq!(ChecksumKind::from_name(kind.name()), Some(kind));
+        let frame = wire::wrap_v2(wire::TYPE_REQ, kind.flag_bits(), 5, &payload);
+        let width = kind.checksum().width();
+        assert_eq!(frame.len(), wire::V2_HEADER_LEN + payload.len() + width);
+        let f = wire::unwrap_any(&frame).unwrap();
+        assert_eq!(ChecksumKind::from_flags(f.header.flags), kind);
+        assert_eq!(f.payload, payload);
+
+        let d = dissect(&frame);
+        assert!(!d.has_problem(), "{kind:?}");
+        if kind == ChecksumKind::None {
+            assert_eq!(d.crc, CrcStatus::Absent);
+        } else {
+            assert!(d.nodes.iter().any(|n| n.label == kind.name()));
+            let mut bad = frame.clone();
+            *bad.last_mut().unwrap() ^= 1;
+            assert_eq!(wire::unwrap_any(&bad).unwrap_err(), FrameError::Crc);
+            assert!(matches!(dissect(&bad).crc, CrcStatus::Mismatch { .. }));
+        }
+
+        // Switching keeps everything but the checksum bits.
+        let crc32 = wire::wrap_v2(wire::TYPE_REQ, wire::FLAG_FRAG_FIRST, 5, &payload);
+        let switched = wire::set_checksum(&crc32, kind).unwrap();
+        let f = wire::unwrap_any(&switched).unwrap();
+        assert_eq!(f.header.flags, wire::FLAG_FRAG_FIRST | kind.flag_bits());
+        assert_eq!((f.header.seq, f.payload), (5, &payload[..]));
+    }
+    // v1 frames always carry crc32.
+    let v1 = wire::wrap_v1_req(&payload);
+    assert_eq!(wire::set_checksum(&v1, ChecksumKind::None).unwrap(), v1);
+
+    // The stream decoder sizes v2 frames by their checksum.
+    let frames: Vec<_> = ChecksumKind::ALL.into_iter().map(mul_request).collect();
+    let events = FrameDecoder::new().feed(&frames.concat());
+    let want: Vec<_> = frames.into_iter().map(DecodeEvent::Frame).collect();
+    assert_eq!(events, want);
+}
+
+#[test]
+fn emulator_answers_in_kind() {
+    let r5 = R5Peer::new(R5Config::default());
+    for (kind, want) in C_REPLIES {
+        let reply = r5.handle_frame(&mul_request(kind)).expect("reply");
+        assert_eq!(hex::encode_upper(&reply), want, "{kind:?}");
+        assert_eq!(decode_calc_response(&reply).unwrap().result, 42);
+    }
+
+    // Older images only check crc32: a crc32c trailer reads as a bad one.
+    let old = R5Peer::new(R5Config {
+        firmware: Firmware::SumOnly,
+        ..Default::default()
+    });
+    let reply = old
+        .handle_frame(&mul_request(ChecksumKind::Crc32c))
+        .unwrap();
+    assert_eq!(ChecksumKind::from_flags(reply[4]), ChecksumKind::Crc32);
+    let err = decode_calc_response(&reply).unwrap_err();
+    assert!(matches!(err, FrameError::RemoteError { ref detail, .. } if detail == "payload crc"));
+}
+
+#[tokio::test]
+async fn transport_holds_a_link_to_one_checksum() {
+    let req = CalcRequest {
+        op: Op::Sum as i32,
+        a: 40,
+        b: 2,
+        trace: None,
+    };
+    for kind in ChecksumKind::ALL {
+        let (link, _r5) = R5Peer::new(R5Config::default()).loopback();
+        let link = ChecksumTransport::new(link, kind);
+        link.send(&encode_frame_v2(&req, 3)).await.unwrap();
+        let reply = link.recv().await.unwrap();
+        assert_eq!(ChecksumKind::from_flags(reply[4]), kind);
+        let resp: CalcResponse = decode_calc_response(&reply).unwrap();
+        assert_eq!(resp.result, 42);
+    }
+
+    // A frame with another checksum is dropped, not passed on.
+    let (a, b) = MemoryTransport::pair();
+    let a = ChecksumTransport::new(a, ChecksumKind::Crc16Ccitt);
+    let weak = wire::wrap_v2(wire::TYPE_RESP, ChecksumKind::None.flag_bits(), 1, &[8, 42]);
+    b.send(&weak).await.unwrap();
+    let wanted = wire::set_checksum(&weak, ChecksumKind::Crc16Ccitt).unwrap();
+    b.send(&wanted).await.unwrap();
+    assert_eq!(a.recv().await.unwrap(), wanted);
+}
+
+#[tokio::test]
+async fn transport_drops_every_other_checksum() {
+    let payload = [8, 42];
+    for kind in ChecksumKind::ALL {
+        let (a, b) = MemoryTransport::pair();
+        let a = ChecksumTransport::new(a, kind);
+        for other in ChecksumKind::ALL.into_iter().filter(|&k| k != kind) {
+            let frame = wire::wrap_v2(wire::TYPE_RESP, other.flag_bits(), 1, &payload);
+            b.send(&frame).await.unwrap();
+        }
+        // v1 frames have no choice of checksum and pass through.
+        let v1 = wire::wrap_v1_req(&payload);
+        b.send(&v1).await.unwrap();
+        let wanted = wire::wrap_v2(wire::TYPE_RESP, kind.flag_bits(), 2, &payload);
+        b.send(&wanted).await.unwrap();
+        assert_eq!(a.recv().await.unwrap(), v1, "{kind:?}");
+        assert_eq!(a.recv().await.unwrap(), wanted, "{kind:?}");
+    }
+}
+
+#[tokio::test]
+async fn transport_refuses_frames_it_cannot_rechecksum() {
+    let (a, b) = MemoryTransport::pair();
+    let a = ChecksumTransport::new(a, ChecksumKind::Crc32c);
+    let mut bad = mul_request(ChecksumKind::Crc32);
+    *bad.last_mut().unwrap() ^= 1;
+    let truncated = &mul_request(ChecksumKind::Crc32)[..12];
+    for frame in [&bad[..], truncated] {
+        let err = a.send(frame).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    // Nothing reached the link; the next good frame goes out re-checksummed.
+    a.send(&mul_request(ChecksumKind::Crc32)).await.unwrap();
+    assert_eq!(b.recv().await.unwrap(), mul_request(ChecksumKind::Crc32c));
+}
@@ -0,0 +1,82 @@
+use assert_cmd::prelude::*;
+use std::process::Command;
+
+use linux_gateway::proto::{CalcError, Op, Status};
+use linux_gateway::{encode_calc, encode_calc_request_with_trace_ctx, encode_frame_v2, wire};
+
+const BIN: &str = env!("CARGO_PKG_NAME");
+
+fn decode(args: &[&str]) -> (i32, String) {
+    let out = Command::cargo_bin(BIN)
+        .unwrap()
+        .arg("--decode")
+        .args(args)
+        .output()
+        .expect("run --decode");
+    let mut text = String::from_utf8_lossy(&out.stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&out.stderr));
+    (out.status.code().unwrap(), text)
+}
+
+#[test]
+fn prints_request_fields_as_text() {
+    let frame = hex::encode_upper(encode_calc(Op::Div, 100, 4));
+    let (code, text) = decode(&[&format!("0x{frame}")]);
+    assert_eq!(code, 0, "{text}");
+    for line in [
+        "version: 1",
+        "message: CalcRequest",
+        "op: OP_DIV",
+        "a: 100",
+        "b: 4",
+    ] {
+        assert!(text.contains(line), "missing {line:?} in:\n{text}");
+    }
+}
+
+#[test]
+fn prints_json_with_trace_and_seq() {
+    let (frame, trace) = encode_calc_request_with_trace_ctx(7, 35);
+    let (code, text) = decode(&[&hex::encode(wire::wrap_sync(&frame)), "--json"]);
+    assert_eq!(code, 0, "{text}");
+    let v: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(v["a"], 7);
+    assert_eq!(v["op"], "OP_SUM");
+    assert_eq!(v["trace"]["trace_id"], hex::encode(&trace.trace_id));
+    assert_eq!(v["trace"]["span_id"], hex::encode(&trace.span_id));
+
+    let err = CalcError {
+        code: Status::Overflow as i32,
+        detail: "overflow".into(),
+        trace: None,
+    };
+    let (code, text) = decode(&[&hex::encode(encode_frame_v2(&err, 513)), "--json"]);
+    assert_eq!(code, 0, "{text}");
+    let v: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(v["version"], 2);
+    assert_eq!(v["seq"], 513);
+    assert_eq!(v["code"], "STATUS_OVERFLOW");
+}
+
+#[test]
+fn exit_code_identifies_the_failure() {
+    let mut bad_crc = encode_calc(Op::Sum, 1, 2);
+    *bad_crc.last_mut().unwrap() ^= 1;
+    let mut bad_hdr = wire::wrap_v2_req(1, b"");
+    bad_hdr[9] ^= 1;
+
+    let cases = [
+        ("GG".to_string(), 2),
+        ("0901000000000000".to_string(), 10),
+        ("0109000000000000".to_string(), 11),
+        (hex::encode(bad_crc), 12),
+        ("01".to_string(), 13),
+        (hex::encode(bad_hdr), 16),
+    ];
+    for (frame, want) in cases {
+        let (code, text) = decode(&[&frame]);
+        assert_eq!(code, want, "{frame}: {text}");
+    }
+    let (_, text) = decode(&["01", "--json"]);
+    assert!(text.contains(r#""error":"too_short""#), "{text}");
+}
@@ -0,0 +1,153 @@
+use assert_cmd::prelude::*;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use linux_gateway::emulator::{Firmware, R5Config, R5Peer};
+use linux_gateway::proto::{CalcRequest, Op};
+use linux_gateway::transport::UnixTransport;
+use tokio::net::UnixListener;
+
+const BIN: &str = env!("CARGO_PKG_NAME");
+
+/// Serve `firmware` on a fresh UNIX socket; returns the socket path.
+fn emulated_r5(dir: &tempfile::TempDir, firmware: Firmware) -> PathBuf {
+    let path = dir.path().join("r5.sock");
+    let listener = UnixListener::bind(&path).unwrap();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let peer = R5Peer::new(R5Config {
+                firmware,
+                ..Default::default()
+            });
+            peer.spawn(UnixTransport::from_unix(stream));
+        }
+    });
+    path
+}
+
+async fn run(args: Vec<String>) -> Output {
+    tokio::task::spawn_blocking(move || Command::cargo_bin(BIN).unwrap().args(args).output())
+        .await
+        .unwrap()
+        .expect("run binary")
+}
+
+fn args(list: &[&str]) -> Vec<String> {
+    list.iter().map(|s| s.to_string()).collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn send_prints_result_latency_and_trace_echo() {
+    let dir = tempfile::tempdir().unwrap();
+    let sock = emulated_r5(&dir, Firmware::Current);
+    let sock = sock.to_str().unwrap();
+
+    let out = run(args(&["send", "6", "7", "mul", "--unix", sock])).await;
+    let text = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{text}");
+    assert!(text.contains("result: 42"), "{text}");
+    assert!(text.contains("rtt_us: "), "{text}");
+    assert!(text.contains("trace: echoed"), "{text}");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn send_hex_reports_remote_error_and_v1_legacy() {
+    let dir = tempfile::tempdir().unwrap();
+    let sock = emulated_r5(&dir, Firmware::Current);
+    let req = CalcRequest {
+        op: Op::Div as i32,
+        a: 1,
+        b: 0,
+        trace: None,
+    };
+    let frame = hex::encode(linux_gateway::encode_frame_v2(&req, 77));
+    let out = run(args(&[
+        "send",
+        "--hex",
+        &frame,
+        "--unix",
+        sock.to_str().unwrap(),
+    ]))
+    .await;
+    assert_eq!(out.status.code(), Some(18));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("division by zero"));
+
+    let dir = tempfile::tempdir().unwrap();
+    let sock = emulated_r5(&dir, Firmware::Legacy);
+    let sock = sock.to_str().unwrap();
+    let out = run(args(&["send", "40", "2", "--v1", "--unix", sock])).await;
+    let text = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        text.contains("result: 42") && text.contains("trace: echoed"),
+        "{text}"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn send_times_out_and_rejects_bad_arguments() {
+    // Legacy firmware ignores v2 requests.
+    let dir = tempfile::tempdir().unwrap();
+    let sock = emulated_r5(&dir, Firmware::Legacy);
+    let sock = sock.to_str().unwrap();
+    let out = run(args(&[
+        "send",
+        "1",
+        "2",
+        "--unix",
+        sock,
+        "--timeout",
+        "100",
+    ]))
+    .await;
+    assert_eq!(out.status.code(), Some(4));
+
+    for bad in [
+        &["send", "1"][..],
+        &["send", "1", "2", "pow"],
+        &["rpmsg-bounce", "XYZ"],
+    ] {
+        let out = run(args(bad)).await;
+        assert_eq!(out.status.code(), Some(2), "{bad:?}");
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn send_refuses_v1_frames_with_an_auth_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = dir.path().join("key");
+    std::fs::write(&key, hex::encode([7u8; 32])).unwrap();
+    let key = key.to_str().unwrap();
+
+    let out = run(args(&[
+        "send",
+        "6",
+        "7",
+        "mul",
+        "--emulate",
+        "--auth-key-file",
+        key,
+    ]))
+    .await;
+    let text = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{text}");
+    assert!(text.contains("result: 42"), "{text}");
+
+    let req = CalcRequest {
+        op: Op::Mul as i32,
+        a: 6,
+        b: 7,
+        trace: None,
+    };
+    let v1 = hex::encode(linux_gateway::encode_frame(&req));
+    for bad in [
+        &["send", "6", "7", "mul", "--v1"][..],
+        &["send", "--hex", &v1],
+    ] {
+        let mut argv = args(bad);
+        argv.extend(args(&["--emulate", "--auth-key-file", key]));
+        let out = run(argv).await;
+        assert_eq!(out.status.code(), Some(2), "{bad:?}");
+        let err = String::from_utf8_lossy(&out.stderr);
+        assert!(err.contains("--auth-key-file needs a v2 frame"), "{err}");
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use linux_gateway::calc::evaluate_request;
+use linux_gateway::client::{ClientConfig, ClientError, GatewayClient};
+use linux_gateway::proto::{CalcError, CalcRequest, CalcResponse, Op, Status};
+use linux_gateway::transport::{MemoryTransport, StreamTransport, Transport};
+use linux_gateway::wire::{self, DecodeEvent, FrameDecoder};
+use linux_gateway::{encode_frame_v2, FrameError};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+// Minimal R5 stand-in: answers each request with the evaluated result.
+// `drop_first` requests are swallowed; replies to a batch of `reorder`
+// requests are sent back in reverse order.
+async fn fake_r5(mut io: DuplexStream, drop_first: usize, reorder: usize) {
+    let mut dec = FrameDecoder::new();
+    let mut buf = [0u8; 512];
+    let mut seen = 0;
+    let mut batch = Vec::new();
+    loop {
+        let n = match io.read(&mut buf).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => n,
+        };
+        for ev in dec.feed(&buf[..n]) {
+            let DecodeEvent::Frame(frame) = ev else {
+                continue;
+            };
+            seen += 1;
+            if seen <= drop_first {
+                continue;
+            }
+            let f = wire::unwrap_v2_req(&frame).unwrap();
+            let req: CalcRequest = prost::Message::decode(f.payload).unwrap();
+            let reply = match evaluate_request(&req) {
+                Ok(result) => encode_frame_v2(
+                    &CalcResponse {
+                        result,
+                        trace: req.trace,
+                        ..Default::default()
+                    },
+                    f.header.seq,
+                ),
+                Err(code) => encode_frame_v2(
+                    &CalcError {
+                        code: code as i32,
+                        detail: "rejected".into(),
+                        trace: None,
+                    },
+                    f.header.seq,
+                ),
+            };
+            batch.push(reply);
+            if batch.len() >= reorder.max(1) {
+                for reply in batch.drain(..).rev() {
+                    io.write_all(&reply).await.unwrap();
+                }
+            }
+        }
+    }
+}
+
+fn config() -> ClientConfig {
+    ClientConfig {
+        timeout: Duration::from_millis(200),
+        retries: 2,
+        backoff: Duration::from_millis(10),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn concurrent_calls_are_matched_by_seq() {
+    let (ours, theirs) = tokio::io::duplex(4096);
+    tokio::spawn(fake_r5(theirs, 0, 3));
+    let client = GatewayClient::new(StreamTransport::new(ours), config());
+
+    let (a, b, c) = tokio::join!(
+        client.calc(Op::Sum, 7, 35),
+        client.calc(Op::Mul, 6, 7),
+        client.calc(Op::Div, 100, 4),
+    );
+    assert_eq!(a.unwrap().result, 42);
+    assert_eq!(b.unwrap().result, 42);
+    assert_eq!(c.unwrap().result, 25);
+    assert_eq!(client.in_flight(), 0);
+}
+
+#[tokio::test]
+async fn retries_after_timeout_and_surfaces_remote_errors() {
+    let (ours, theirs) = tokio::io::duplex(4096);
+    tokio::spawn(fake_r5(theirs, 1, 1));
+    let client = GatewayClient::new(StreamTransport::new(ours), config());
+
+    assert_eq!(client.calc(Op::Sub, 50, 8).await.unwrap().result, 42);
+    match client.calc(Op::Div, 1, 0).await {
+        Err(ClientError::Frame(FrameError::RemoteError { code, .. })) => {
+            assert_eq!(code, Status::DivByZero as i32)
+        }
+        other => panic!("expected remote error, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn times_out_and_reports_closed_peer() {
+    let (ours, theirs) = tokio::io::duplex(4096);
+    tokio::spawn(fake_r5(theirs, usize::MAX, 1));
+    let client = GatewayClient::new(StreamTransport::new(ours), config());
+    let mut opts = client.call_options(false);
+    opts.timeout = Duration::from_millis(50);
+    let req = CalcRequest {
+        op: Op::Sum as i32,
+        a: 1,
+        b: 1,
+        trace: None,
+    };
+    let err = client
+        .call::<_, CalcResponse>(&req, opts)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, ClientError::Timeout(d) if d == Duration::from_millis(50)));
+
+    let (ours, theirs) = tokio::io::duplex(64);
+    drop(theirs);
+    let client = GatewayClient::new(StreamTransport::new(ours), config());
+    let err = client.calc(Op::Sum, 1, 1).await.unwrap_err();
+    assert!(
+        matches!(err, ClientError::Closed | ClientError::Io(_)),
+        "{err:?}"
+    );
+}
+
+#[tokio::test]
+async fn reports_busy_when_every_seq_is_in_flight() {
+    let (ours, theirs) = MemoryTransport::pair();
+    let client = Arc::new(GatewayClient::new(ours, config()));
+    let mut opts = client.call_options(false);
+    opts.timeout = Duration::from_secs(60);
+    let req = CalcRequest {
+        op: Op::Sum as i32,
+        a: 1,
+        b: 1,
+        trace: None,
+    };
+    let mut calls = Vec::new();
+    for _ in 0..u16::MAX {
+        let (client, req, opts) = (client.clone(), req.clone(), opts.clone());
+        calls.push(tokio::spawn(async move {
+            client.call::<_, CalcResponse>(&req, opts).await
+        }));
+    }
+    // Each request is registered before it is sent.
+    let mut seqs = HashSet::new();
+    for _ in 0..u16::MAX {
+        let frame = theirs.recv().await.unwrap();
+        seqs.insert(wire::unwrap_any(&frame).unwrap().header.seq);
+    }
+    assert_eq!(seqs.len(), usize::from(u16::MAX));
+    assert!(!seqs.contains(&0));
+    assert_eq!(client.in_flight(), usize::from(u16::MAX));
+    let err = client.call::<_, CalcResponse>(&req, opts.clone()).await;
+    assert!(matches!(err, Err(ClientError::Busy)), "{err:?}");
+
+    // An answer frees its seq for the next request.
+    let reply = CalcResponse {
+        result: 2,
+        ..Default::default()
+    };
+    theirs.send(&encode_frame_v2(&reply, 77)).await.unwrap();
+    while client.in_flight() == usize::from(u16::MAX) {
+        tokio::task::yield_now().await;
+    }
+    let next = tokio::spawn({
+        let client = client.clone();
+        async move { client.call::<_, CalcResponse>(&req, opts).await }
+    });
+    let frame = theirs.recv().await.unwrap();
+    assert_eq!(wire::unwrap_any(&frame).unwrap().header.seq, 77);
+    theirs.send(&encode_frame_v2(&reply, 77)).await.unwrap();
+    assert_eq!(next.await.unwrap().unwrap().result, 2);
+    for call in calls {
+        call.abort();
+    }
+}
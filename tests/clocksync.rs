@@ -0,0 +1,166 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use http_body_util::BodyExt;
+use tower::ServiceExt;
+
+use linux_gateway::client::{ClientConfig, GatewayClient};
+use linux_gateway::clocksync::{self, monotonic_ns, ClockSync, Sample, SyncConfig};
+use linux_gateway::emulator::{Firmware, R5Config, R5Peer};
+use linux_gateway::proto::TimeSyncRequest;
+use linux_gateway::server::{router, AppState};
+use linux_gateway::{encode_frame_v2, wire};
+
+/// An exchange at Linux time `t1` with an R5 clock running `offset` ahead
+/// and `ppm` fast, `out`/`back` ns on the link and 2us on the R5.
+fn sample(t1: u64, offset: i64, ppm: f64, out: u64, back: u64) -> Sample {
+    let r5 = |linux: u64| (linux as f64 * (1.0 + ppm * 1e-6)) as i64 + offset;
+    let t2 = r5(t1 + out) as u64;
+    Sample {
+        t1,
+        t2,
+        t3: t2 + 2_000,
+        t4: t1 + out + 2_000 + back,
+    }
+}
+
+#[test]
+fn estimates_offset_and_drift() {
+    const SEC: u64 = 1_000_000_000;
+    let mut sync = ClockSync::new(8);
+    assert!(sync.estimate().is_none());
+    // No R5 clock: t2 = t3 = 0.
+    assert!(!sync.add(Sample {
+        t1: 1,
+        t2: 0,
+        t3: 0,
+        t4: 2
+    }));
+
+    let offset = -7 * SEC as i64;
+    for i in 1..=10 {
+        // Every third exchange is held up on the way back.
+        let back = if i % 3 == 0 { 400_000 } else { 20_000 };
+        assert!(sync.add(sample(100 * SEC + i * SEC, offset, 50.0, 20_000, back)));
+    }
+    let est = sync.estimate().unwrap();
+    assert_eq!(est.samples, 8);
+    assert_eq!(est.delay_ns, 40_000);
+    assert!((est.drift_ppm - 50.0).abs() < 0.5, "{est:?}");
+    // Offset of the true R5 clock at the anchor, to within the half-delay.
+    let truth = (est.anchor_ns as f64 * 50e-6) as i64 + offset;
+    assert!(
+        (est.offset_ns - truth).abs() <= 20_000,
+        "{est:?} vs {truth}"
+    );
+
+    let linux = 120 * SEC;
+    let r5 = (linux as f64 * (1.0 + 50e-6)) as i64 + offset;
+    let back = est.r5_to_monotonic(r5 as u64);
+    assert!(back.abs_diff(linux) < 50_000, "{back} vs {linux}");
+}
+
+#[test]
+fn out_of_order_samples_are_rejected() {
+    let good = sample(1_000_000, 5_000, 0.0, 20_000, 20_000);
+    let mut sync = ClockSync::new(8);
+    assert!(sync.add(good));
+    let bad = [
+        // Reply received before the request was sent.
+        Sample {
+            t4: good.t1 - 1,
+            ..good
+        },
+        // Reply sent before the request arrived.
+        Sample {
+            t3: good.t2 - 1,
+            ..good
+        },
+        // Held on the R5 longer than the whole round trip.
+        Sample {
+            t3: good.t2 + (good.t4 - good.t1) + 1,
+            ..good
+        },
+        Sample {
+            t1: u64::MAX,
+            t2: u64::MAX,
+            t3: 1,
+            t4: 0,
+        },
+    ];
+    for s in bad {
+        assert_eq!(s.delay_ns(), None, "{s:?}");
+        assert!(!sync.add(s), "{s:?}");
+    }
+    // None of them displaced the good sample as the anchor.
+    let est = sync.estimate().unwrap();
+    assert_eq!(est.samples, 1);
+    assert_eq!(Some(est.delay_ns), good.delay_ns());
+    assert_eq!(est.offset_ns, good.offset_ns());
+}
+
+#[test]
+fn emulator_time_sync_matches_r5_c_build() {
+    // Captured from r5/frame_decode.c with the weak (zero) calc_now_ns.
+    let cases = [
+        ("08E807", "A55A020500000300017808E807ED6491F9"),
+        // t1 sent as a length-delimited field
+        (
+            "0AFF",
+            "A55A020300001300012C0801120F77726F6E67207769726520747970659573812D",
+        ),
+    ];
+    let r5 = R5Peer::new(R5Config::default());
+    for (payload, want) in cases {
+        let req = wire::wrap_v2(wire::TYPE_TIME_REQ, 0, 1, &hex::decode(payload).unwrap());
+        let got = r5.handle_frame(&req).expect("reply");
+        assert_eq!(hex::encode_upper(got), want, "payload {payload}");
+    }
+
+    let req = encode_frame_v2(&TimeSyncRequest { t1_ns: 1000 }, 1);
+    let sum_only = R5Peer::new(R5Config {
+        firmware: Firmware::SumOnly,
+        ..Default::default()
+    });
+    assert!(sum_only.handle_frame(&req).is_none());
+}
+
+#[tokio::test]
+async fn background_sync_publishes_emulator_offset() {
+    let created = monotonic_ns();
+    let (link, _r5) = R5Peer::new(R5Config {
+        clock: true,
+        ..Default::default()
+    })
+    .loopback();
+    let client = Arc::new(GatewayClient::new(link, ClientConfig::default()));
+
+    let sample = clocksync::exchange(&client).await.unwrap();
+    assert!(sample.r5_has_clock());
+    assert!(sample.t3 >= sample.t2 && sample.t4 >= sample.t1);
+
+    let task = clocksync::spawn(
+        client.clone(),
+        SyncConfig {
+            interval: Duration::from_millis(10),
+            window: 4,
+        },
+    );
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    task.abort();
+    let est = clocksync::current().expect("published estimate");
+    assert_eq!(est.samples, 4);
+    // The emulator clock counts from its creation.
+    let want = -(created as i64);
+    assert!((est.offset_ns - want).abs() < 5_000_000, "{est:?}");
+
+    let req = Request::get("/v1/clock").body(Body::empty()).unwrap();
+    let resp = router(AppState::offline()).oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = resp.into_body().collect().await.unwrap().to_bytes();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["offset_ns"], est.offset_ns);
+    assert_eq!(json["samples"], 4);
+}
@@ -0,0 +1,80 @@
+use assert_cmd::prelude::*;
+use std::process::Command;
+
+use linux_gateway::dissect::{dissect, render, CrcStatus, Node};
+use linux_gateway::proto::{CalcRequest, Op, TraceCtx};
+use linux_gateway::{encode_calc_response, encode_frame_v2, wire};
+
+fn find<'a>(nodes: &'a [Node], label: &str) -> &'a Node {
+    nodes
+        .iter()
+        .find(|n| n.label == label)
+        .unwrap_or_else(|| panic!("no {label:?} in {nodes:#?}"))
+}
+
+#[test]
+fn labels_v2_header_and_nested_fields() {
+    let req = CalcRequest {
+        op: Op::Mul as i32,
+        a: 6,
+        b: 7,
+        trace: Some(TraceCtx {
+            trace_id: vec![0xAB; 16],
+            span_id: vec![0xCD; 8],
+            flags: 1,
+            ..Default::default()
+        }),
+    };
+    let frame = encode_frame_v2(&req, 0x1234);
+    let d = dissect(&frame);
+    assert_eq!(d.version, Some(2));
+    assert!(matches!(d.crc, CrcStatus::Ok(_)));
+    assert!(!d.has_problem());
+
+    assert_eq!(find(&d.nodes, "seq").value, "4660");
+    assert_eq!(find(&d.nodes, "seq").range, 7..9);
+    let payload = find(&d.nodes, "payload");
+    assert_eq!(payload.range, wire::V2_HEADER_LEN..frame.len() - 4);
+    assert_eq!(find(&payload.children, "op (1)").value, "2 (OP_MUL)");
+    assert_eq!(find(&payload.children, "b (3)").value, "7");
+    let trace = find(&find(&payload.children, "trace (100)").children, "value");
+    let span = find(&trace.children, "span_id (2)");
+    assert_eq!(span.value, "cdcdcdcdcdcdcdcd");
+    assert_eq!(find(&span.children, "tag").value, "field 2, len");
+}
+
+#[test]
+fn flags_unknown_fields_and_bad_crc() {
+    // v1 response: result=5, then an unknown field 9 (varint 1).
+    let mut frame = wire::wrap_v1_resp(&[0x08, 0x05, 0x48, 0x01]);
+    *frame.last_mut().unwrap() ^= 0xFF;
+    let d = dissect(&frame);
+    assert_eq!(d.version, Some(1));
+    let payload = find(&d.nodes, "payload");
+    assert_eq!(find(&payload.children, "result (1)").value, "5");
+    assert_eq!(find(&payload.children, "unknown (9)").range, 4..6);
+    assert!(matches!(d.crc, CrcStatus::Mismatch { .. }));
+    assert!(find(&d.nodes, "crc32").problem.is_some());
+
+    // Truncated varint inside the payload.
+    let d = dissect(&wire::wrap_v1_req(&[0x10, 0x80]));
+    let payload = find(&d.nodes, "payload");
+    assert!(find(&payload.children, "garbage").problem.is_some());
+    assert!(d.has_problem());
+    assert!(render(&frame, &dissect(&frame)).contains("crc MISMATCH"));
+}
+
+#[test]
+fn cli_renders_annotated_hexdump() {
+    let frame = wire::wrap_sync(&encode_calc_response(42));
+    let out = Command::cargo_bin(env!("CARGO_PKG_NAME"))
+        .unwrap()
+        .args(["dissect", &format!("0x{}", hex::encode(&frame))])
+        .output()
+        .expect("run dissect");
+    assert!(out.status.success());
+    let text = String::from_utf8_lossy(&out.stdout);
+    assert!(text.contains("0000  A5 5A"), "{text}");
+    assert!(text.contains("result (1)"), "{text}");
+    assert!(text.contains("crc ok"), "{text}");
+}
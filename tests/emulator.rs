@@ -0,0 +1,201 @@
+use std::time::{Duration, Instant};
+
+use linux_gateway::client::{ClientConfig, ClientError, GatewayClient};
+use linux_gateway::emulator::{Faults, Firmware, R5Config, R5Peer};
+use linux_gateway::proto::{Op, Status};
+use linux_gateway::{wire, FrameError};
+
+fn peer(firmware: Firmware) -> R5Peer {
+    R5Peer::new(R5Config {
+        firmware,
+        ..Default::default()
+    })
+}
+
+#[test]
+fn replies_match_r5_c_build() {
+    // (request payload, seq, reply) pairs captured from r5/frame_decode.c.
+    let cases = [
+        // DIV 1 / 0
+        (
+            "08031001",
+            1,
+            "A55A020300001400013A080312106469766973696F6E206279207A65726FD242A001",
+        ),
+        // MUL 0 * 5 = 0, an empty CalcResponse
+        ("08021805", 1, "A55A020200000000011A00000000"),
+        // truncated varint
+        (
+            "0807100110",
+            1,
+            "A55A02030000110001FA0801120D656E642D6F662D73747265616D1C6A8728",
+        ),
+        // MUL 6 * 7 with a W3C trace context, echoed with its tracestate
+        (
+            "080210061807A206310A104B4B4B4B4B4B4B4B4B4B4B4B4B4B4B4B1208F0F0F0F0F0F0F0F018012211636F6E676F3D7436317263576B674D7A45",
+            1,
+            "A55A0202000036000186082AA206310A104B4B4B4B4B4B4B4B4B4B4B4B4B4B4B4B1208F0F0F0F0F0F0F0F018012211636F6E676F3D7436317263576B674D7A4548D5F033",
+        ),
+        // tracestate one byte over the nanopb buffer
+        (
+            "1001A206422240613D7878787878787878787878787878787878787878787878787878787878787878787878787878787878787878787878787878787878787878787878787878",
+            1,
+            "A55A020300001300012C0801120F737472696E67206F766572666C6F77EE4C7967",
+        ),
+    ];
+    let r5 = peer(Firmware::Current);
+    for (payload, seq, want) in cases {
+        let req = wire::wrap_v2_req(seq, &hex::decode(payload).unwrap());
+        let got = r5.handle_frame(&req).expect("reply");
+        assert_eq!(hex::encode_upper(got), want, "payload {payload}");
+    }
+
+    let mut bad_crc = wire::wrap_v2_req(0x0203, &hex::decode("08011003").unwrap());
+    *bad_crc.last_mut().unwrap() ^= 0xff;
+    assert_eq!(
+        hex::encode_upper(r5.handle_frame(&bad_crc).unwrap()),
+        "A55A020300000F0203500801120B7061796C6F616420637263E1A42ACA"
+    );
+    // v1 and non-request frames get no reply from current firmware.
+    assert_eq!(
+        r5.handle_frame(&linux_gateway::encode_calc(Op::Sum, 1, 2)),
+        None
+    );
+    assert_eq!(r5.handle_frame(&wire::wrap_v2_resp(1, b"")), None);
+}
+
+#[test]
+fn decode_errors_match_r5_c_build() {
+    // Request payloads and the `CalcError` detail `r5/frame_decode.c` (nanopb)
+    // answered with; `None` for a `CalcResponse`.
+    let cases = [
+        ("0807100110", Some("end-of-stream")),
+        ("80", Some("end-of-stream")),
+        ("2D0100", Some("end-of-stream")),
+        ("29010203", Some("end-of-stream")),
+        ("2A0501", Some("end-of-stream")),
+        ("A206030A0501", Some("end-of-stream")),
+        ("A2060310", Some("parent stream too short")),
+        ("08FFFFFFFFFFFFFFFFFF7F", Some("varint overflow")),
+        ("10FFFFFFFFFFFFFFFFFF02", Some("varint overflow")),
+        ("F8FFFFFFFF0100", Some("varint overflow")),
+        ("08FFFFFFFF1F", Some("integer too large")),
+        ("10FFFFFFFF1F", Some("integer too large")),
+        ("0E01", Some("wrong wire type")),
+        ("0D01000000", Some("wrong wire type")),
+        ("0B", Some("wrong wire type")),
+        ("A206020801", Some("wrong wire type")),
+        ("2B2C", Some("invalid wire_type")),
+        ("0001", Some("zero tag")),
+        ("0201", Some("zero tag")),
+        // Unknown fields are skipped, however long their varint.
+        ("F8FFFFFF7F00", None),
+        ("28FFFFFFFFFFFFFFFFFFFFFF01", None),
+        ("08021006", None),
+    ];
+    let r5 = peer(Firmware::Current);
+    for (payload, want) in cases {
+        let req = wire::wrap_v2_req(1, &hex::decode(payload).unwrap());
+        let reply = r5.handle_frame(&req).expect("reply");
+        let got = match linux_gateway::decode_calc_response(&reply) {
+            Ok(_) => None,
+            Err(FrameError::RemoteError { code, detail }) => {
+                assert_eq!(code, Status::DecodeError as i32, "payload {payload}");
+                Some(detail)
+            }
+            Err(e) => panic!("payload {payload}: {e}"),
+        };
+        assert_eq!(got.as_deref(), want, "payload {payload}");
+    }
+}
+
+#[tokio::test]
+async fn client_end_to_end_across_firmware_versions() {
+    let config = ClientConfig {
+        timeout: Duration::from_millis(200),
+        ..Default::default()
+    };
+
+    let (link, _r5) = peer(Firmware::Current).loopback();
+    let client = GatewayClient::new(link, config.clone());
+    assert_eq!(client.calc(Op::Mul, 6, 7).await.unwrap().result, 42);
+
+    let (link, _r5) = peer(Firmware::SumOnly).loopback();
+    let client = GatewayClient::new(link, config.clone());
+    assert_eq!(client.calc(Op::Sum, 40, 2).await.unwrap().result, 42);
+    match client.calc(Op::Sub, 50, 8).await {
+        Err(ClientError::Frame(FrameError::RemoteError { code, detail })) => {
+            assert_eq!(code, Status::UnsupportedOp as i32);
+            assert_eq!(detail, "unsupported op");
+        }
+        other => panic!("expected unsupported op, got {other:?}"),
+    }
+
+    // Legacy firmware only speaks v1, so v2 requests go unanswered.
+    let (link, _r5) = peer(Firmware::Legacy).loopback();
+    let client = GatewayClient::new(
+        link,
+        ClientConfig {
+            retries: 0,
+            ..config
+        },
+    );
+    assert!(matches!(
+        client.calc(Op::Sum, 1, 2).await,
+        Err(ClientError::Timeout(_))
+    ));
+}
+
+#[tokio::test]
+async fn latency_and_fault_injection() {
+    let (link, _r5) = R5Peer::new(R5Config {
+        latency: Duration::from_millis(30),
+        faults: Faults {
+            drop: 0.5,
+            ..Default::default()
+        },
+        seed: 7,
+        ..Default::default()
+    })
+    .loopback();
+    let client = GatewayClient::new(
+        link,
+        ClientConfig {
+            timeout: Duration::from_millis(100),
+            retries: 10,
+            backoff: Duration::from_millis(1),
+            ..Default::default()
+        },
+    );
+    for i in 0..5 {
+        let start = Instant::now();
+        assert_eq!(client.calc(Op::Sum, i, 1).await.unwrap().result, i + 1);
+        assert!(start.elapsed() >= Duration::from_millis(30));
+    }
+
+    let forced = R5Peer::new(R5Config {
+        faults: Faults {
+            force_status: Some(Status::Overflow),
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    let reply = forced
+        .handle_frame(&linux_gateway::encode_frame_v2(
+            &linux_gateway::proto::CalcRequest {
+                op: Op::Sum as i32,
+                a: 1,
+                b: 1,
+                trace: None,
+            },
+            9,
+        ))
+        .unwrap();
+    assert_eq!(
+        linux_gateway::decode_calc_response(&reply),
+        Err(FrameError::RemoteError {
+            code: Status::Overflow as i32,
+            detail: "overflow".into()
+        })
+    );
+}
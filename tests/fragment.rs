@@ -0,0 +1,225 @@
+use std::time::{Duration, Instant};
+
+use linux_gateway::dissect::dissect;
+use linux_gateway::emulator::{R5Config, R5Peer};
+use linux_gateway::fragment::{self, FragmentingTransport, Reassembler, ReassemblyConfig};
+use linux_gateway::proto::CalcRequest;
+use linux_gateway::transport::{MemoryTransport, Transport};
+use linux_gateway::{wire, FrameError};
+
+/// `OP_MUL 6 7` padded to 1009 bytes with an unknown field (15), which both
+/// prost and nanopb skip.
+fn big_request(seq: u16) -> Vec<u8> {
+    let mut payload = hex::decode("0802100618077AE807").unwrap();
+    for _ in 0..3 {
+        payload.extend(0..=255u8);
+    }
+    payload.resize(1009, 0);
+    wire::wrap_v2(wire::TYPE_REQ, 0, seq, &payload)
+}
+
+/// Reply from the `r5/` C build, through `frag_reasm.c`, to the fragments
+/// of `big_request(9)`.
+const C_REASSEMBLED_REPLY: &str = "A55A02020000020009F4082A2151BB52";
+
+fn reassemble(r: &mut Reassembler, frames: &[Vec<u8>]) -> Result<Option<Vec<u8>>, FrameError> {
+    let mut last = Ok(None);
+    for f in frames {
+        last = r.push(f.clone());
+    }
+    last
+}
+
+#[test]
+fn split_and_reassemble() {
+    let frame = big_request(9);
+    let small = wire::wrap_v2(wire::TYPE_REQ, 0, 1, &[8, 2]);
+    let whole = fragment::split(&small, fragment::DEFAULT_MTU).unwrap();
+    assert_eq!(whole, [&small[..]]);
+    let v1 = wire::wrap_v1_req(&[0; 600]);
+    assert!(matches!(
+        fragment::split(&v1, 496),
+        Err(FrameError::TooLong(_))
+    ));
+
+    let frags = fragment::split(&frame, fragment::DEFAULT_MTU).unwrap();
+    assert_eq!(frags.len(), 3);
+    assert!(frags.iter().all(|f| f.len() <= fragment::DEFAULT_MTU));
+    let flags: Vec<u8> = frags.iter().map(|f| f[4]).collect();
+    assert_eq!(
+        flags,
+        [
+            wire::FLAG_FRAG_FIRST,
+            wire::FLAG_FRAG_MIDDLE,
+            wire::FLAG_FRAG_LAST
+        ]
+    );
+    // Fragments are dissected by their sub-header and refused by decoders.
+    let d = dissect(&frags[1]);
+    assert!(!d.has_problem());
+    let payload = d.nodes.iter().find(|n| n.label == "payload").unwrap();
+    assert_eq!(payload.value, "fragment");
+    assert_eq!(payload.children[0].value, "1");
+    assert_eq!(payload.children[1].value, "1009");
+    assert!(matches!(
+        linux_gateway::decode_frame::<CalcRequest>(&frags[0]),
+        Err(FrameError::Fragment(_))
+    ));
+
+    let mut r = Reassembler::default();
+    assert_eq!(r.push(small.clone()).unwrap(), Some(small));
+    assert_eq!(reassemble(&mut r, &frags).unwrap(), Some(frame.clone()));
+    assert_eq!(r.pending(), 0);
+
+    // A gap drops the message.
+    let err = reassemble(&mut r, &[frags[0].clone(), frags[2].clone()]).unwrap_err();
+    assert_eq!(err.to_string(), "fragment: fragment out of sequence");
+    assert_eq!(r.pending(), 0);
+    let err = r.push(frags[1].clone()).unwrap_err();
+    assert_eq!(err.to_string(), "fragment: no first fragment");
+
+    // Stale messages time out.
+    let t0 = Instant::now();
+    let mut r = Reassembler::new(ReassemblyConfig {
+        timeout: Duration::from_millis(50),
+        ..Default::default()
+    });
+    assert_eq!(r.push_at(frags[0].clone(), t0).unwrap(), None);
+    assert_eq!(r.pending(), 1009);
+    let late = t0 + Duration::from_millis(60);
+    assert!(r.push_at(frags[1].clone(), late).is_err());
+    assert_eq!(r.pending(), 0);
+
+    // Memory caps.
+    let mut r = Reassembler::new(ReassemblyConfig {
+        max_len: 1000,
+        ..Default::default()
+    });
+    let err = r.push(frags[0].clone()).unwrap_err();
+    assert_eq!(err.to_string(), "fragment: message too large");
+    let mut r = Reassembler::new(ReassemblyConfig {
+        max_pending: 1500,
+        ..Default::default()
+    });
+    let other = fragment::split(&big_request(10), 496).unwrap();
+    assert_eq!(r.push(frags[0].clone()).unwrap(), None);
+    let err = r.push(other[0].clone()).unwrap_err();
+    assert_eq!(err.to_string(), "fragment: reassembly buffers full");
+}
+
+#[test]
+fn emulator_reassembles_fragments() {
+    let r5 = R5Peer::new(R5Config::default());
+    let frags = fragment::split(&big_request(9), fragment::DEFAULT_MTU).unwrap();
+    assert!(r5.handle_frame(&frags[0]).is_none());
+    assert!(r5.handle_frame(&frags[1]).is_none());
+    let got = r5.handle_frame(&frags[2]).expect("reply");
+    assert_eq!(hex::encode_upper(got), C_REASSEMBLED_REPLY);
+
+    // Missing middle: dropped without a reply, and the R5 recovers.
+    assert!(r5.handle_frame(&frags[0]).is_none());
+    assert!(r5.handle_frame(&frags[2]).is_none());
+    assert!(r5.handle_frame(&frags[0]).is_none());
+    assert!(r5.handle_frame(&frags[1]).is_none());
+    assert!(r5.handle_frame(&frags[2]).is_some());
+
+    // A fragment with a bad payload CRC is answered like any other frame.
+    let mut bad = frags[0].clone();
+    *bad.last_mut().unwrap() ^= 1;
+    let reply = r5.handle_frame(&bad).expect("reply");
+    assert_eq!(reply[3], wire::TYPE_ERR);
+}
+
+#[test]
+fn reassembler_drops_what_does_not_fit() {
+    let frags = fragment::split(&big_request(9), fragment::DEFAULT_MTU).unwrap();
+    let mut r = Reassembler::default();
+
+    // A duplicate drops the message; the rest of it has nowhere to go.
+    assert_eq!(r.push(frags[0].clone()).unwrap(), None);
+    assert_eq!(r.push(frags[1].clone()).unwrap(), None);
+    let err = r.push(frags[1].clone()).unwrap_err();
+    assert_eq!(err, FrameError::Fragment("fragment out of sequence"));
+    assert_eq!(r.pending(), 0);
+    let err = r.push(frags[2].clone()).unwrap_err();
+    assert_eq!(err, FrameError::Fragment("no first fragment"));
+
+    // A restarted message is reserved once, not twice.
+    assert_eq!(r.push(frags[0].clone()).unwrap(), None);
+    assert_eq!(r.push(frags[0].clone()).unwrap(), None);
+    assert_eq!(r.pending(), 1009);
+    assert_eq!(
+        reassemble(&mut r, &frags[1..]).unwrap(),
+        Some(big_request(9))
+    );
+    assert_eq!(r.pending(), 0);
+
+    // Mixed-up position bits and short sub-headers.
+    let payload = &frags[0][wire::V2_HEADER_LEN..frags[0].len() - 4];
+    let both = wire::FLAG_FRAG_FIRST | wire::FLAG_FRAG_LAST;
+    let both = wire::wrap_v2(wire::TYPE_REQ, both, 9, payload);
+    let err = r.push(both).unwrap_err();
+    assert_eq!(err, FrameError::Fragment("conflicting fragment flags"));
+    let short = wire::wrap_v2(wire::TYPE_REQ, wire::FLAG_FRAG_FIRST, 3, &[0, 0, 1]);
+    let err = r.push(short).unwrap_err();
+    assert_eq!(err, FrameError::Fragment("short fragment header"));
+}
+
+#[test]
+fn reassembler_evicts_stale_messages_to_make_room() {
+    let t0 = Instant::now();
+    let mut r = Reassembler::new(ReassemblyConfig {
+        timeout: Duration::from_millis(50),
+        max_pending: 1500,
+        ..Default::default()
+    });
+    let first = fragment::split(&big_request(9), 496).unwrap();
+    let second = fragment::split(&big_request(10), 496).unwrap();
+    assert_eq!(r.push_at(first[0].clone(), t0).unwrap(), None);
+
+    // Full while the first message is live...
+    let soon = t0 + Duration::from_millis(10);
+    let err = r.push_at(second[0].clone(), soon).unwrap_err();
+    assert_eq!(err, FrameError::Fragment("reassembly buffers full"));
+    assert_eq!(r.pending(), 1009);
+
+    // ...and room once it has timed out.
+    let late = t0 + Duration::from_millis(60);
+    assert_eq!(r.push_at(second[0].clone(), late).unwrap(), None);
+    assert_eq!(r.pending(), 1009);
+    let err = r.push_at(first[1].clone(), late).unwrap_err();
+    assert_eq!(err, FrameError::Fragment("no first fragment"));
+    let rest = &second[1..];
+    let mut last = None;
+    for f in rest {
+        last = r.push_at(f.clone(), late).unwrap();
+    }
+    assert_eq!(last, Some(big_request(10)));
+    assert_eq!(r.pending(), 0);
+    assert_eq!(r.expire(late + Duration::from_secs(1)), 0);
+}
+
+#[tokio::test]
+async fn fragmenting_transport_round_trip() {
+    let (link, _r5) = R5Peer::new(R5Config::default()).loopback();
+    let link = FragmentingTransport::new(link, fragment::DEFAULT_MTU);
+    link.send(&big_request(9)).await.unwrap();
+    let reply = link.recv().await.unwrap();
+    assert_eq!(hex::encode_upper(reply), C_REASSEMBLED_REPLY);
+
+    // Both ends fragmenting: large frames arrive whole, in order.
+    let (a, b) = MemoryTransport::pair();
+    let a = FragmentingTransport::new(a, 200);
+    let b = FragmentingTransport::new(b, 200);
+    let frames = [
+        big_request(1),
+        wire::wrap_v2(wire::TYPE_REQ, 0, 2, &[8, 2]),
+        big_request(3),
+    ];
+    for f in &frames {
+        a.send(f).await.unwrap();
+    }
+    for f in &frames {
+        assert_eq!(&b.recv().await.unwrap(), f);
+    }
+}
@@ -0,0 +1,136 @@
+use linux_gateway::wire::{self, DecodeEvent, FrameDecoder};
+use linux_gateway::{decode_calc_request, encode_calc_request, encode_calc_response, FrameError};
+
+#[test]
+fn reassembles_frame_fed_byte_by_byte() {
+    let frame = encode_calc_request(7, 35);
+    let stream = wire::wrap_sync(&frame);
+
+    let mut dec = FrameDecoder::new();
+    let mut events = Vec::new();
+    for b in &stream {
+        events.extend(dec.feed(std::slice::from_ref(b)));
+    }
+    assert_eq!(events, vec![DecodeEvent::Frame(frame.clone())]);
+    let req = decode_calc_request(&frame).expect("decode");
+    assert_eq!((req.a, req.b), (7, 35));
+    assert_eq!(dec.buffered(), 0);
+}
+
+#[test]
+fn splits_merged_frames_and_reports_garbage() {
+    let f1 = encode_calc_request(1, 2);
+    let f2 = encode_calc_response(3);
+    let mut stream = vec![0x00, 0x13, 0x37];
+    stream.extend(wire::wrap_sync(&f1));
+    stream.extend(wire::wrap_sync(&f2));
+
+    let events = FrameDecoder::new().feed(&stream);
+    assert_eq!(
+        events,
+        vec![
+            DecodeEvent::Skipped {
+                bytes: 3,
+                reason: FrameError::NoSync
+            },
+            DecodeEvent::Frame(f1),
+            DecodeEvent::Frame(f2),
+        ]
+    );
+}
+
+#[test]
+fn resyncs_after_bad_header_and_false_sync() {
+    let good = encode_calc_response(42);
+    let mut stream = vec![0xA5, 0x5A, 0x09, 0x01]; // bad version
+    stream.extend([0xA5, 0x5A, 0x01, 0x02, 0xFF]); // plausible header, never completes
+    stream.extend(wire::wrap_sync(&good));
+
+    let events = FrameDecoder::new().feed(&stream);
+    assert_eq!(
+        events,
+        vec![
+            DecodeEvent::Skipped {
+                bytes: 4,
+                reason: FrameError::UnknownVersion(0x09)
+            },
+            DecodeEvent::Skipped {
+                bytes: 5,
+                reason: FrameError::Crc
+            },
+            DecodeEvent::Frame(good),
+        ]
+    );
+}
+
+#[test]
+fn gives_up_on_candidate_longer_than_max() {
+    let mut dec = FrameDecoder::with_max_frame_len(16);
+    let mut stream = vec![0xA5, 0x5A, 0x01, 0x01];
+    stream.extend([0xEEu8; 32]);
+    let events = dec.feed(&stream);
+    assert!(matches!(
+        events.first(),
+        Some(DecodeEvent::Skipped {
+            reason: FrameError::Crc,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn false_syncs_are_scanned_once_however_chunked() {
+    // Plausible v1 headers that never complete, then a real frame.
+    let good = encode_calc_response(42);
+    let mut stream: Vec<u8> = [0xA5, 0x5A, 0x01, 0x02].repeat(2000);
+    stream.extend(wire::wrap_sync(&good));
+
+    let whole = FrameDecoder::new().feed(&stream);
+    let mut dec = FrameDecoder::new();
+    let mut bytewise = Vec::new();
+    for b in &stream {
+        bytewise.extend(dec.feed(std::slice::from_ref(b)));
+    }
+    assert_eq!(whole, bytewise);
+    assert_eq!(whole.last(), Some(&DecodeEvent::Frame(good)));
+    let skipped: usize = whole
+        .iter()
+        .map(|e| match e {
+            DecodeEvent::Skipped { bytes, .. } => *bytes,
+            DecodeEvent::Frame(_) => 0,
+        })
+        .sum();
+    assert_eq!(skipped, 8000);
+    assert_eq!(dec.buffered(), 0);
+}
+
+#[test]
+fn verified_v2_head_waits_out_a_frame_in_its_payload() {
+    // A whole frame carried as the payload of another, delivered in two
+    // parts that split the outer one just after the inner one.
+    let inner = wire::wrap_v2(wire::TYPE_REQ, 0, 7, &[8, 2, 16, 6, 24, 7]);
+    let mut payload = inner.clone();
+    payload.extend([0x5A; 20]);
+    let outer = wire::wrap_v2(wire::TYPE_REQ, 0, 8, &payload);
+    let cut = wire::V2_HEADER_LEN + inner.len() + 3;
+
+    let mut dec = FrameDecoder::new();
+    assert_eq!(dec.feed(&outer[..cut]), []);
+    assert_eq!(dec.buffered(), cut);
+    assert_eq!(dec.feed(&outer[cut..]), [DecodeEvent::Frame(outer.clone())]);
+    assert_eq!(dec.buffered(), 0);
+
+    // With a bad header CRC the head is not trusted, and the inner frame is
+    // found as soon as it is complete.
+    let mut bad = outer.clone();
+    bad[wire::V2_HEADER_LEN - 1] ^= 1;
+    let events = FrameDecoder::new().feed(&bad[..cut]);
+    assert_eq!(
+        events[0],
+        DecodeEvent::Skipped {
+            bytes: wire::V2_HEADER_LEN,
+            reason: FrameError::HeaderCrc
+        }
+    );
+    assert_eq!(events[1], DecodeEvent::Frame(inner));
+}
@@ -0,0 +1,153 @@
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use http_body_util::BodyExt;
+use prost::Message;
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+use linux_gateway::client::{ClientConfig, ClientError, GatewayClient};
+use linux_gateway::emulator::{R5Config, R5Peer};
+use linux_gateway::proto::{CalcRequest, CalcResponse, TraceCtx};
+use linux_gateway::server::{router, AppState};
+use linux_gateway::{
+    decode_any_with_limits, decode_frame, decode_frame_with_limits, encode_frame_v2, wire,
+    DecodeLimits, FrameError,
+};
+
+fn limit(what: &'static str, limit: usize, actual: usize) -> FrameError {
+    FrameError::LimitExceeded {
+        what,
+        limit,
+        actual,
+    }
+}
+
+fn request_with_trace_id(len: usize) -> Vec<u8> {
+    let req = CalcRequest {
+        a: 1,
+        b: 2,
+        trace: Some(TraceCtx {
+            trace_id: vec![0xAB; len],
+            span_id: vec![0xCD; 8],
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    encode_frame_v2(&req, 1)
+}
+
+#[test]
+fn bytes_fields_frame_length_and_unknown_fields() {
+    let ok = request_with_trace_id(16);
+    assert!(decode_frame::<CalcRequest>(&ok).is_ok());
+
+    let big = request_with_trace_id(4096);
+    assert_eq!(
+        decode_frame::<CalcRequest>(&big),
+        Err(limit("bytes field length", 512, 4096))
+    );
+    let unlimited = DecodeLimits::unlimited();
+    let req: CalcRequest = decode_frame_with_limits(&big, &unlimited).unwrap();
+    assert_eq!(req.trace.unwrap().trace_id.len(), 4096);
+
+    let small = DecodeLimits {
+        max_frame_len: 64,
+        ..Default::default()
+    };
+    assert_eq!(
+        decode_frame_with_limits::<CalcRequest>(&big, &small),
+        Err(limit("frame length", 64, big.len()))
+    );
+
+    // Two unknown fields: 15 (varint) and 16 (bytes).
+    let mut payload = CalcRequest::default().encode_to_vec();
+    payload.extend_from_slice(&[0x78, 0x01, 0x82, 0x01, 0x02, 0xCA, 0xFE]);
+    let frame = wire::wrap_v2(wire::TYPE_REQ, 0, 1, &payload);
+    assert!(decode_frame::<CalcRequest>(&frame).is_ok());
+    let strict = DecodeLimits {
+        allow_unknown_fields: false,
+        ..Default::default()
+    };
+    assert_eq!(
+        decode_frame_with_limits::<CalcRequest>(&frame, &strict),
+        Err(limit("unknown fields", 0, 2))
+    );
+    assert!(decode_frame_with_limits::<CalcRequest>(&ok, &strict).is_ok());
+    assert_eq!(
+        limit("frame length", 64, 100).to_string(),
+        "frame length 100 over limit 64"
+    );
+}
+
+#[test]
+fn nested_groups_are_bounded() {
+    // An unknown field 15 holding 20 nested groups, which prost would skip
+    // by recursing into each.
+    let mut payload = vec![0x08, 0x02];
+    payload.extend(std::iter::repeat_n(0x7B, 20));
+    payload.extend(std::iter::repeat_n(0x7C, 20));
+    let frame = wire::wrap_v2(wire::TYPE_REQ, 0, 1, &payload);
+    assert_eq!(
+        decode_any_with_limits(&frame, &DecodeLimits::default()),
+        Err(limit("nesting depth", 8, 9))
+    );
+    let deep = DecodeLimits {
+        max_depth: 21,
+        ..Default::default()
+    };
+    assert!(decode_any_with_limits(&frame, &deep).is_ok());
+
+    // TraceCtx is the only nested message: depth 2.
+    let shallow = DecodeLimits {
+        max_depth: 1,
+        ..Default::default()
+    };
+    let ok = request_with_trace_id(16);
+    assert_eq!(
+        decode_frame_with_limits::<CalcRequest>(&ok, &shallow),
+        Err(limit("nesting depth", 1, 2))
+    );
+}
+
+#[tokio::test]
+async fn client_and_server_apply_their_limits() {
+    let (link, _r5) = R5Peer::new(R5Config::default()).loopback();
+    let tight = DecodeLimits {
+        max_bytes_len: 8,
+        ..Default::default()
+    };
+    let client = GatewayClient::new(
+        link,
+        ClientConfig {
+            limits: tight,
+            ..Default::default()
+        },
+    );
+    // The R5 echoes the 16-byte trace id.
+    let req = CalcRequest {
+        a: 1,
+        b: 2,
+        trace: Some(TraceCtx::random(true)),
+        ..Default::default()
+    };
+    let err = client
+        .call::<_, CalcResponse>(&req, client.call_options(false))
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(err, ClientError::Frame(ref e) if *e == limit("bytes field length", 8, 16)),
+        "{err:?}"
+    );
+
+    let body = json!({ "frame_hex": hex::encode(request_with_trace_id(16)) });
+    let req = Request::post("/v1/frames/decode")
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let state = AppState::offline().with_limits(tight);
+    let resp = router(state).oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let bytes = resp.into_body().collect().await.unwrap().to_bytes();
+    let json: Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(json["error"]["kind"], "limit_exceeded");
+}
@@ -0,0 +1,104 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use http_body_util::BodyExt;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tower::ServiceExt;
+
+use linux_gateway::client::{ClientConfig, GatewayClient};
+use linux_gateway::emulator::{R5Config, R5Peer};
+use linux_gateway::metrics::metrics;
+use linux_gateway::proto::{CalcError, Op, Status};
+use linux_gateway::server::{router, AppState};
+use linux_gateway::transport::{StreamTransport, Transport};
+use linux_gateway::{encode_calc_response, encode_frame_v2};
+
+fn frames(direction: &str, typ: &str) -> u64 {
+    metrics().frames.with_label_values(&[direction, typ]).get()
+}
+
+fn decode_errors(kind: &str) -> u64 {
+    metrics().decode_errors.with_label_values(&[kind]).get()
+}
+
+#[tokio::test]
+async fn stream_links_count_frames_and_skipped_bytes() {
+    let (tx_before, rx_before) = (frames("tx", "CalcError"), frames("rx", "CalcError"));
+    let no_sync_before = decode_errors("no_sync");
+    let err = CalcError {
+        code: Status::Overflow as i32,
+        detail: "overflow".into(),
+        trace: None,
+    };
+    let frame = encode_frame_v2(&err, 3);
+
+    let (near, mut far) = tokio::io::duplex(1024);
+    let link = StreamTransport::new(near);
+    link.send(&frame).await.unwrap();
+    let mut echoed = vec![0; frame.len()];
+    far.read_exact(&mut echoed).await.unwrap();
+    far.write_all(b"\x00\x11\x22").await.unwrap();
+    far.write_all(&echoed).await.unwrap();
+    assert_eq!(link.recv().await.unwrap(), frame);
+
+    assert_eq!(frames("tx", "CalcError") - tx_before, 1);
+    assert_eq!(frames("rx", "CalcError") - rx_before, 1);
+    assert!(decode_errors("no_sync") > no_sync_before);
+}
+
+#[tokio::test]
+async fn client_records_rtt_and_in_flight() {
+    let rtt_before = metrics().rtt.get_sample_count();
+    let (near, far) = tokio::io::duplex(1024);
+    let _r5 = R5Peer::new(R5Config {
+        latency: Duration::from_millis(100),
+        ..Default::default()
+    })
+    .spawn(StreamTransport::new(far));
+    let client = Arc::new(GatewayClient::new(
+        StreamTransport::new(near),
+        ClientConfig::default(),
+    ));
+
+    let in_flight = metrics().in_flight.with_label_values(&["client"]);
+    let call = tokio::spawn({
+        let client = client.clone();
+        async move { client.calc(Op::Sum, 40, 2).await }
+    });
+    tokio::time::sleep(Duration::from_millis(30)).await;
+    assert_eq!(in_flight.get(), 1);
+    assert_eq!(call.await.unwrap().unwrap().result, 42);
+    assert_eq!(in_flight.get(), 0);
+
+    assert_eq!(metrics().rtt.get_sample_count() - rtt_before, 1);
+    assert!(metrics().rtt.get_sample_sum() >= 0.1);
+}
+
+#[tokio::test]
+async fn metrics_endpoint_exposes_decode_errors() {
+    let mut bad = encode_calc_response(42);
+    *bad.last_mut().unwrap() ^= 1;
+    let before = decode_errors("crc");
+    assert!(linux_gateway::decode_calc_response(&bad).is_err());
+    assert_eq!(decode_errors("crc") - before, 1);
+
+    let req = Request::get("/metrics").body(Body::empty()).unwrap();
+    let resp = router(AppState::offline()).oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain"));
+    let body = resp.into_body().collect().await.unwrap().to_bytes();
+    let text = String::from_utf8(body.to_vec()).unwrap();
+    assert!(
+        text.contains("linux_gateway_decode_errors_total{kind=\"crc\"}"),
+        "{text}"
+    );
+    assert!(
+        text.contains("# TYPE linux_gateway_rtt_seconds histogram"),
+        "{text}"
+    );
+}
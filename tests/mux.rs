@@ -0,0 +1,232 @@
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use linux_gateway::auth::{AuthKey, AuthTransport};
+use linux_gateway::checksum::{ChecksumKind, ChecksumTransport};
+use linux_gateway::client::{ClientConfig, GatewayClient};
+use linux_gateway::emulator::{R5Config, R5Peer};
+use linux_gateway::mux::{Mux, MuxConfig};
+use linux_gateway::proto::{CalcRequest, Op};
+use linux_gateway::transport::{MemoryTransport, Transport, UnixTransport};
+use linux_gateway::{encode_frame_v2, wire};
+use tokio::net::UnixListener;
+
+fn start_mux(
+    dir: &tempfile::TempDir,
+    upstream: impl Transport + 'static,
+    config: MuxConfig,
+) -> PathBuf {
+    let path = dir.path().join("mux.sock");
+    let listener = UnixListener::bind(&path).unwrap();
+    tokio::spawn(Mux::new(upstream, config).serve(listener));
+    path
+}
+
+fn request(a: u32, seq: u16) -> Vec<u8> {
+    let req = CalcRequest {
+        op: Op::Sum as i32,
+        a,
+        b: 1,
+        trace: None,
+    };
+    encode_frame_v2(&req, seq)
+}
+
+/// `a` of the request in `frame`, to tell clients apart upstream.
+fn request_a(frame: &[u8]) -> u32 {
+    linux_gateway::decode_calc_request(frame).unwrap().a
+}
+
+async fn recv_within(t: &dyn Transport, ms: u64) -> Option<Vec<u8>> {
+    tokio::time::timeout(Duration::from_millis(ms), t.recv())
+        .await
+        .ok()
+        .map(Result::unwrap)
+}
+
+#[tokio::test]
+async fn clients_share_the_link_with_their_own_seqs() {
+    let dir = tempfile::tempdir().unwrap();
+    let (upstream, _r5) = R5Peer::new(R5Config::default()).loopback();
+    let path = start_mux(&dir, upstream, MuxConfig::default());
+
+    let mut calls = tokio::task::JoinSet::new();
+    for i in 0..3u32 {
+        let t = UnixTransport::connect_unix(&path).await.unwrap();
+        let client = Arc::new(GatewayClient::new(t, ClientConfig::default()));
+        // Every client numbers its requests from 1, so seqs collide at the mux.
+        for j in 0..4 {
+            let client = client.clone();
+            let a = i * 100 + j;
+            calls.spawn(async move { (a, client.calc(Op::Mul, a, 2).await) });
+        }
+    }
+    while let Some(done) = calls.join_next().await {
+        let (a, resp) = done.unwrap();
+        assert_eq!(resp.unwrap().result, a * 2);
+    }
+}
+
+#[tokio::test]
+async fn limits_requests_in_flight_and_restores_client_seq() {
+    let dir = tempfile::tempdir().unwrap();
+    let (upstream, r5) = MemoryTransport::pair();
+    let config = MuxConfig {
+        max_in_flight: 2,
+        per_client_in_flight: 4,
+        ..Default::default()
+    };
+    let path = start_mux(&dir, upstream, config);
+    let client = UnixTransport::connect_unix(&path).await.unwrap();
+    for seq in 10..14 {
+        client.send(&request(seq as u32, seq)).await.unwrap();
+    }
+
+    let first = recv_within(&r5, 500).await.expect("first request");
+    let second = recv_within(&r5, 500).await.expect("second request");
+    assert_eq!((request_a(&first), request_a(&second)), (10, 11));
+    assert!(recv_within(&r5, 100).await.is_none(), "third sent early");
+
+    let peer = R5Peer::new(R5Config::default());
+    r5.send(&peer.handle_frame(&first).unwrap()).await.unwrap();
+    let third = recv_within(&r5, 500).await.expect("third request");
+    assert_eq!(request_a(&third), 12);
+
+    let reply = client.recv().await.unwrap();
+    let f = wire::unwrap_v2_resp(&reply).unwrap();
+    assert_eq!(f.header.seq, 10);
+    assert_eq!(
+        linux_gateway::decode_calc_response(&reply).unwrap().result,
+        11
+    );
+}
+
+#[tokio::test]
+async fn busy_client_does_not_starve_others() {
+    let dir = tempfile::tempdir().unwrap();
+    let (upstream, r5) = MemoryTransport::pair();
+    let config = MuxConfig {
+        max_in_flight: 1,
+        per_client_in_flight: 1,
+        ..Default::default()
+    };
+    let path = start_mux(&dir, upstream, config);
+    let busy = UnixTransport::connect_unix(&path).await.unwrap();
+    let quiet = UnixTransport::connect_unix(&path).await.unwrap();
+
+    for seq in 1..=3 {
+        busy.send(&request(seq as u32, seq)).await.unwrap();
+    }
+    let first = recv_within(&r5, 500).await.expect("busy request");
+    quiet.send(&request(100, 1)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let peer = R5Peer::new(R5Config::default());
+    r5.send(&peer.handle_frame(&first).unwrap()).await.unwrap();
+    let next = recv_within(&r5, 500).await.expect("next request");
+    assert_eq!(request_a(&next), 100, "quiet client should go next");
+    r5.send(&peer.handle_frame(&next).unwrap()).await.unwrap();
+    assert_eq!(request_a(&recv_within(&r5, 500).await.unwrap()), 2);
+
+    let reply = quiet.recv().await.unwrap();
+    assert_eq!(wire::unwrap_v2_resp(&reply).unwrap().header.seq, 1);
+    assert_eq!(
+        linux_gateway::decode_calc_response(&reply).unwrap().result,
+        101
+    );
+}
+
+/// Refuses requests whose `a` is 666, as a transport refuses a frame it
+/// cannot re-checksum or seal.
+struct Refusing<T>(T);
+
+#[async_trait]
+impl<T: Transport> Transport for Refusing<T> {
+    async fn send(&self, frame: &[u8]) -> io::Result<()> {
+        if request_a(frame) == 666 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "refused"));
+        }
+        self.0.send(frame).await
+    }
+
+    async fn recv(&self) -> io::Result<Vec<u8>> {
+        self.0.recv().await
+    }
+}
+
+#[tokio::test]
+async fn refused_and_sealed_frames_do_not_stop_the_mux() {
+    let dir = tempfile::tempdir().unwrap();
+    let key = AuthKey::new(&[7; 32]).unwrap();
+    let (link, r5) = MemoryTransport::pair();
+    let config = R5Config {
+        auth: Some(key.clone()),
+        ..Default::default()
+    };
+    R5Peer::new(config).spawn(r5);
+    let upstream = Refusing(AuthTransport::new(link, key.clone()));
+    let path = start_mux(&dir, upstream, MuxConfig::default());
+
+    let bad = UnixTransport::connect_unix(&path).await.unwrap();
+    // Sealed by the client: the mux would break its tag, so it drops it.
+    let sealed = wire::seal_auth(&request(1, 1), &key, 5).unwrap();
+    bad.send(&sealed).await.unwrap();
+    // Refused by the link.
+    bad.send(&request(666, 2)).await.unwrap();
+    // A lone fragment.
+    let frag = wire::wrap_v2(
+        wire::TYPE_REQ,
+        wire::FLAG_FRAG_FIRST,
+        3,
+        &[0, 0, 0, 9, 8, 2],
+    );
+    bad.send(&frag).await.unwrap();
+
+    let t = UnixTransport::connect_unix(&path).await.unwrap();
+    let other = GatewayClient::new(t, ClientConfig::default());
+    assert_eq!(other.calc(Op::Mul, 6, 7).await.unwrap().result, 42);
+
+    // The same client is still served too, and got nothing for the others.
+    bad.send(&request(40, 4)).await.unwrap();
+    let reply = recv_within(&bad, 1000).await.expect("reply");
+    assert_eq!(wire::unwrap_v2_resp(&reply).unwrap().header.seq, 4);
+    assert_eq!(
+        linux_gateway::decode_calc_response(&reply).unwrap().result,
+        41
+    );
+    assert!(recv_within(&bad, 100).await.is_none());
+}
+
+#[tokio::test]
+async fn replies_use_each_clients_checksum() {
+    let dir = tempfile::tempdir().unwrap();
+    let (link, _r5) = R5Peer::new(R5Config::default()).loopback();
+    let upstream = ChecksumTransport::new(link, ChecksumKind::Crc32);
+    let path = start_mux(&dir, upstream, MuxConfig::default());
+
+    // Each client's transport drops replies in any other checksum.
+    let mut calls = tokio::task::JoinSet::new();
+    for (i, kind) in ChecksumKind::ALL.into_iter().enumerate() {
+        let t = UnixTransport::connect_unix(&path).await.unwrap();
+        let client = GatewayClient::new(ChecksumTransport::new(t, kind), ClientConfig::default());
+        calls.spawn(async move { (i as u32, client.calc(Op::Mul, i as u32, 3).await) });
+    }
+    while let Some(done) = calls.join_next().await {
+        let (a, resp) = done.unwrap();
+        assert_eq!(resp.unwrap().result, a * 3);
+    }
+
+    let raw = UnixTransport::connect_unix(&path).await.unwrap();
+    for (seq, kind) in (1..).zip(ChecksumKind::ALL) {
+        let frame = wire::set_checksum(&request(seq as u32, seq), kind).unwrap();
+        raw.send(&frame).await.unwrap();
+        let reply = recv_within(&raw, 1000).await.expect("reply");
+        let f = wire::unwrap_any(&reply).unwrap();
+        assert_eq!(ChecksumKind::from_flags(f.header.flags), kind);
+        assert_eq!(f.header.seq, seq);
+    }
+}
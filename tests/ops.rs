@@ -0,0 +1,32 @@
+use linux_gateway::calc::{evaluate, evaluate_request};
+use linux_gateway::proto::{Op, Status};
+use linux_gateway::{decode_calc_request, encode_calc};
+
+#[test]
+fn evaluator_is_checked() {
+    assert_eq!(evaluate(Op::Sum, 7, 35), Ok(42));
+    assert_eq!(evaluate(Op::Sum, u32::MAX, 1), Err(Status::Overflow));
+    assert_eq!(evaluate(Op::Sub, 35, 7), Ok(28));
+    assert_eq!(evaluate(Op::Sub, 7, 35), Err(Status::Overflow));
+    assert_eq!(evaluate(Op::Mul, 65535, 65537), Ok(u32::MAX));
+    assert_eq!(evaluate(Op::Mul, 65536, 65536), Err(Status::Overflow));
+    assert_eq!(evaluate(Op::Div, 43, 6), Ok(7));
+    assert_eq!(evaluate(Op::Div, 1, 0), Err(Status::DivByZero));
+}
+
+#[test]
+fn encode_calc_carries_op() {
+    for op in [Op::Sum, Op::Sub, Op::Mul, Op::Div] {
+        let req = decode_calc_request(&encode_calc(op, 100, 4)).expect("decode");
+        assert_eq!(req.op(), op);
+        assert_eq!((req.a, req.b), (100, 4));
+    }
+}
+
+#[test]
+fn unknown_op_is_unsupported() {
+    let mut req = decode_calc_request(&encode_calc(Op::Div, 8, 2)).unwrap();
+    assert_eq!(evaluate_request(&req), Ok(4));
+    req.op = 42;
+    assert_eq!(evaluate_request(&req), Err(Status::UnsupportedOp));
+}
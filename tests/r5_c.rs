@@ -0,0 +1,473 @@
+//! The `r5/` C sources built for the host and fed the same frames as the
+//! Rust side. `make -C r5 host-frames` builds the same driver.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use linux_gateway::auth::{AuthKey, ReplayWindow};
+use linux_gateway::checksum::{Checksum, ChecksumKind, Crc16Ccitt, Crc32, Crc32c};
+use linux_gateway::emulator::{R5Config, R5Peer};
+use linux_gateway::fragment::{self, Reassembler, ReassemblyConfig};
+use linux_gateway::{wire, FrameError};
+
+/// What `r5/Makefile` builds `host-frames` from, relative to `r5/`.
+const SOURCES: &[&str] = &[
+    "host_frames.c",
+    "frag_reasm.c",
+    "frame_auth.c",
+    "frame_decode.c",
+    "calc_service.c",
+    "gen/calc.pb.c",
+    "vendor/nanopb/pb_common.c",
+    "vendor/nanopb/pb_decode.c",
+    "vendor/nanopb/pb_encode.c",
+];
+
+/// `r5/host_frames.c`, built once per test run with `$CC` (default `cc`).
+fn host_frames() -> &'static Path {
+    static BIN: OnceLock<PathBuf> = OnceLock::new();
+    BIN.get_or_init(|| {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let r5 = root.join("r5");
+        let bin = Path::new(env!("CARGO_TARGET_TMPDIR")).join("host-frames");
+        let cc = std::env::var("CC").unwrap_or_else(|_| "cc".into());
+        let status = Command::new(&cc)
+            .args(["-O1", "-Wall"])
+            .args(
+                [root, &r5, &r5.join("gen"), &r5.join("vendor/nanopb")].map(|dir| {
+                    let mut flag = std::ffi::OsString::from("-I");
+                    flag.push(dir);
+                    flag
+                }),
+            )
+            .args(SOURCES.iter().map(|src| r5.join(src)))
+            .arg("-o")
+            .arg(&bin)
+            .status()
+            .unwrap_or_else(|e| panic!("running {cc}: {e}"));
+        assert!(status.success(), "building r5/host_frames.c failed");
+        bin
+    })
+}
+
+/// One line of `host-frames` output: the reassembler's verdict and the reply.
+#[derive(Debug, PartialEq)]
+struct Answer {
+    verdict: String,
+    reply: Option<Vec<u8>>,
+}
+
+/// Feed `lines` to a fresh `host-frames <args>` and return what it prints.
+fn c_output(args: &[&str], lines: &[String]) -> String {
+    let mut child = Command::new(host_frames())
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    for line in lines {
+        writeln!(stdin, "{line}").unwrap();
+    }
+    drop(stdin);
+    let out = child.wait_with_output().unwrap();
+    assert!(out.status.success());
+    String::from_utf8(out.stdout).unwrap()
+}
+
+/// Feed `lines` to a fresh `host-frames` and return its answers.
+fn run_c(lines: &[String]) -> Vec<Answer> {
+    c_output(&[], lines)
+        .lines()
+        .map(|line| {
+            let (verdict, reply) = line.split_once(' ').unwrap();
+            Answer {
+                verdict: verdict.to_string(),
+                reply: (reply != "-").then(|| hex::decode(reply).unwrap()),
+            }
+        })
+        .collect()
+}
+
+/// [`run_c`] for frames on a stopped clock.
+fn run_c_frames(frames: &[Vec<u8>]) -> Vec<Answer> {
+    run_c(&frames.iter().map(hex::encode_upper).collect::<Vec<_>>())
+}
+
+/// `OP_MUL 6 7` padded to `len` bytes with an unknown field (15).
+fn big_request(seq: u16, len: usize) -> Vec<u8> {
+    big_request_with(ChecksumKind::Crc32, seq, len)
+}
+
+/// [`big_request`] with `kind`'s checksum.
+fn big_request_with(kind: ChecksumKind, seq: u16, len: usize) -> Vec<u8> {
+    let mut payload = hex::decode("0802100618077AE807").unwrap();
+    payload.extend((0..len).map(|i| i as u8));
+    payload.truncate(len);
+    wire::wrap_v2(wire::TYPE_REQ, kind.flag_bits(), seq, &payload)
+}
+
+fn frags(seq: u16) -> Vec<Vec<u8>> {
+    fragment::split(&big_request(seq, 1009), fragment::DEFAULT_MTU).unwrap()
+}
+
+#[test]
+fn checksums_match_rust() {
+    // Lengths around the 4- and 8-byte strides a CRC unit would take.
+    let mut inputs = vec![Vec::new(), b"123456789".to_vec(), (0..=255).collect()];
+    inputs.extend((1..=17).map(|n| (0..n).map(|i| (i * 37 + n) as u8).collect()));
+    inputs.push(vec![0xFF; 1024]);
+    let lines: Vec<_> = inputs.iter().map(hex::encode_upper).collect();
+    let got = c_output(&["crc"], &lines);
+    let got: Vec<_> = got.lines().collect();
+    assert_eq!(got.len(), inputs.len());
+    for (input, got) in inputs.iter().zip(got) {
+        let want = format!(
+            "{:08X} {:08X} {:04X}",
+            Crc32.compute(input),
+            Crc32c.compute(input),
+            Crc16Ccitt.compute(input)
+        );
+        assert_eq!(got, want, "{}", hex::encode(input));
+    }
+}
+
+#[test]
+fn every_checksum_matches_emulator() {
+    for kind in ChecksumKind::ALL {
+        let mul = hex::decode("080210061807").unwrap();
+        let plain = wire::wrap_v2(wire::TYPE_REQ, kind.flag_bits(), 3, &mul);
+        let frags =
+            fragment::split(&big_request_with(kind, 4, 1009), fragment::DEFAULT_MTU).unwrap();
+        assert!(frags.iter().all(|f| ChecksumKind::from_flags(f[4]) == kind));
+        let mut runs: Vec<(&str, Vec<Vec<u8>>)> = vec![
+            ("plain", vec![plain.clone()]),
+            ("fragmented", frags.clone()),
+            ("short trailer", vec![plain[..plain.len() - 1].to_vec()]),
+        ];
+        if kind != ChecksumKind::None {
+            let mut bad = plain.clone();
+            *bad.last_mut().unwrap() ^= 0x80;
+            let mut bad_frag = frags[1].clone();
+            *bad_frag.last_mut().unwrap() ^= 0x80;
+            runs.push(("bad trailer", vec![bad]));
+            runs.push((
+                "bad fragment",
+                vec![frags[0].clone(), bad_frag, frags[2].clone()],
+            ));
+        }
+        for (name, frames) in runs {
+            let r5 = R5Peer::new(R5Config::default());
+            let want: Vec<_> = frames.iter().map(|f| r5.handle_frame(f)).collect();
+            let got: Vec<_> = run_c_frames(&frames).into_iter().map(|a| a.reply).collect();
+            assert_eq!(got, want, "{kind:?}, {name}");
+            if ["plain", "fragmented"].contains(&name) {
+                let reply = got.last().unwrap().as_ref().expect(name);
+                assert_eq!(ChecksumKind::from_flags(reply[4]), kind, "{name}");
+                assert_eq!(
+                    linux_gateway::decode_calc_response(reply).unwrap().result,
+                    42
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn reassembly_matches_emulator() {
+    let (a, b) = (frags(9), frags(10));
+    let mut bad_crc = a[1].clone();
+    *bad_crc.last_mut().unwrap() ^= 1;
+    let too_big = fragment::split(&big_request(11, 1025), fragment::DEFAULT_MTU).unwrap();
+    let plain = wire::wrap_v2(wire::TYPE_REQ, 0, 12, &hex::decode("08021806").unwrap());
+    let runs: Vec<(&str, Vec<Vec<u8>>)> = vec![
+        ("in order", a.clone()),
+        ("gap", vec![a[0].clone(), a[2].clone(), a[1].clone()]),
+        ("gap, then again", [&a[..2], &a[2..], &a[..]].concat()),
+        (
+            "duplicate",
+            vec![a[0].clone(), a[1].clone(), a[1].clone(), a[2].clone()],
+        ),
+        ("no first", a[1..].to_vec()),
+        ("bad crc", vec![a[0].clone(), bad_crc, a[2].clone()]),
+        (
+            "replaced",
+            vec![
+                a[0].clone(),
+                b[0].clone(),
+                a[1].clone(),
+                b[1].clone(),
+                b[2].clone(),
+            ],
+        ),
+        (
+            "interleaved plain frame",
+            vec![a[0].clone(), plain.clone(), a[1].clone(), a[2].clone()],
+        ),
+        ("too big", too_big),
+    ];
+    for (name, frames) in runs {
+        let r5 = R5Peer::new(R5Config::default());
+        let want: Vec<_> = frames.iter().map(|f| r5.handle_frame(f)).collect();
+        let got: Vec<_> = run_c_frames(&frames).into_iter().map(|a| a.reply).collect();
+        assert_eq!(got, want, "{name}");
+    }
+
+    let verdicts: Vec<_> = run_c_frames(&a).into_iter().map(|a| a.verdict).collect();
+    assert_eq!(verdicts, ["MORE", "MORE", "DONE"]);
+}
+
+#[test]
+fn reassembly_times_out_like_reassembler() {
+    let a = frags(9);
+    // When each fragment arrives, in ms on the R5 clock.
+    let runs = [
+        ("in time", [1, 60, 101]),
+        ("last late", [1, 60, 102]),
+        ("middle late", [1, 102, 103]),
+    ];
+    for (name, at) in runs {
+        let lines: Vec<_> = at
+            .iter()
+            .zip(&a)
+            .map(|(ms, f)| format!("@{} {}", ms * 1_000_000, hex::encode_upper(f)))
+            .collect();
+        let got: Vec<_> = run_c(&lines).into_iter().map(|a| a.verdict).collect();
+
+        let t0 = Instant::now();
+        let mut r = Reassembler::new(ReassemblyConfig {
+            timeout: Duration::from_millis(100),
+            max_len: 1024,
+            max_pending: 1024,
+        });
+        let want: Vec<_> = at
+            .iter()
+            .zip(&a)
+            .map(
+                |(ms, f)| match r.push_at(f.clone(), t0 + Duration::from_millis(*ms)) {
+                    Ok(None) => "MORE",
+                    Ok(Some(_)) => "DONE",
+                    Err(_) => "DROP",
+                },
+            )
+            .collect();
+        assert_eq!(got, want, "{name}");
+    }
+}
+
+/// RFC 4231 test cases 1-7: key, data, HMAC-SHA256 (case 5 truncated).
+const RFC_4231: [(&str, &str, &str); 7] = [
+    (
+        "0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b",
+        "4869205468657265",
+        "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+    ),
+    (
+        "4a656665",
+        "7768617420646f2079612077616e7420666f72206e6f7468696e673f",
+        "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+    ),
+    (
+        "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+        "dddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddd",
+        "773ea91e36800e46854db8ebd09181a72959098b3ef8c122d9635514ced565fe",
+    ),
+    (
+        "0102030405060708090a0b0c0d0e0f10111213141516171819",
+        "cdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcd",
+        "82558a389a443c0ea4cc819899f2083a85f0faa3e578f8077a2e3ff46729665b",
+    ),
+    (
+        "0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c",
+        "546573742057697468205472756e636174696f6e",
+        "a3b6167473100ee06e0c796c2955552b",
+    ),
+    (
+        "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+        "54657374205573696e67204c6172676572205468616e20426c6f636b2d53697a65204b6579202d2048617368204b6579204669727374",
+        "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+    ),
+    (
+        "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+        "5468697320697320612074657374207573696e672061206c6172676572207468616e20626c6f636b2d73697a65206b657920616e642061206c6172676572207468616e20626c6f636b2d73697a6520646174612e20546865206b6579206e6565647320746f20626520686173686564206265666f7265206265696e6720757365642062792074686520484d414320616c676f726974686d2e",
+        "9b09ffa71b942fcb27635fbcd5b0e944bfdc63644f0713938a7f51535c3a35e2",
+    ),
+];
+
+fn key_bytes() -> Vec<u8> {
+    (0..32).collect()
+}
+
+fn key() -> AuthKey {
+    AuthKey::new(&key_bytes()).unwrap()
+}
+
+/// `host-frames open` over `frames`, one replay window for all of them:
+/// each frame without its block and its counter, or `None`.
+fn c_open(frames: &[Vec<u8>]) -> Vec<Option<(Vec<u8>, u64)>> {
+    let lines: Vec<_> = frames.iter().map(hex::encode_upper).collect();
+    c_output(&["open", &hex::encode(key_bytes())], &lines)
+        .lines()
+        .map(|line| {
+            let (counter, frame) = line.split_once(' ')?;
+            Some((hex::decode(frame).unwrap(), counter.parse().unwrap()))
+        })
+        .collect()
+}
+
+#[test]
+fn hmac_matches_rfc_4231_and_rust() {
+    let mut cases: Vec<(Vec<u8>, Vec<u8>)> = RFC_4231
+        .iter()
+        .map(|(key, data, _)| (hex::decode(key).unwrap(), hex::decode(data).unwrap()))
+        .collect();
+    // Messages either side of the SHA-256 padding and block boundaries,
+    // and keys of one block and just over.
+    cases.extend((0..=130).map(|n| (key_bytes(), (0..n).map(|i| i as u8 ^ 0x5C).collect())));
+    cases.extend([64, 65].map(|n| (vec![0x36; n], b"key length".to_vec())));
+    let lines: Vec<_> = cases
+        .iter()
+        .map(|(key, data)| format!("{} {}", hex::encode(key), hex::encode(data)))
+        .collect();
+    let got = c_output(&["hmac"], &lines).to_lowercase();
+    let got: Vec<_> = got.lines().collect();
+    assert_eq!(got.len(), cases.len());
+
+    for ((_, _, want), got) in RFC_4231.iter().zip(&got) {
+        assert!(got.starts_with(want), "{got} != {want}");
+    }
+    for ((key, data), got) in cases.iter().zip(&got) {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+        mac.update(data);
+        let want = hex::encode(mac.finalize().into_bytes());
+        assert_eq!(*got, want, "key {} data {}", hex::encode(key), data.len());
+    }
+}
+
+#[test]
+fn auth_blocks_match_rust_both_ways() {
+    let frames: Vec<_> = ChecksumKind::ALL
+        .into_iter()
+        .zip([1, 2, 1 << 40, u64::MAX])
+        .map(|(kind, counter)| {
+            let frame = big_request_with(kind, counter as u16, 40);
+            (frame, counter)
+        })
+        .collect();
+
+    // C seals what Rust seals.
+    let lines: Vec<_> = frames
+        .iter()
+        .map(|(frame, counter)| format!("{counter} {}", hex::encode_upper(frame)))
+        .collect();
+    let got = c_output(&["seal", &hex::encode(key_bytes())], &lines);
+    let sealed: Vec<_> = frames
+        .iter()
+        .map(|(frame, counter)| wire::seal_auth(frame, &key(), *counter).unwrap())
+        .collect();
+    let want: Vec<_> = sealed.iter().map(hex::encode_upper).collect();
+    assert_eq!(got.lines().collect::<Vec<_>>(), want);
+
+    // C opens what Rust seals, and Rust opens what C sealed.
+    let opened = c_open(&sealed);
+    for (((frame, counter), sealed), opened) in frames.iter().zip(&sealed).zip(opened) {
+        assert_eq!(opened.as_ref(), Some(&(frame.clone(), *counter)));
+        assert_eq!(
+            wire::open_auth(sealed, &key()),
+            Ok((frame.clone(), *counter))
+        );
+    }
+}
+
+#[test]
+fn auth_refusals_match_rust() {
+    let frame = big_request(1, 12);
+    let seal = |counter| wire::seal_auth(&frame, &key(), counter).unwrap();
+    // A flipped tag bit under a good checksum.
+    let sealed = seal(50);
+    let f = wire::unwrap_any(&sealed).unwrap();
+    let mut payload = f.payload.to_vec();
+    *payload.last_mut().unwrap() ^= 1;
+    let bad_tag = wire::wrap_v2(wire::TYPE_REQ, f.header.flags, 1, &payload);
+    let other_key = wire::seal_auth(&frame, &AuthKey::new(&[7; 32]).unwrap(), 51).unwrap();
+
+    let below = "counter below replay window";
+    let runs: [(&str, Vec<u8>, Result<u64, &str>); 11] = [
+        ("counter 0", seal(0), Err(below)),
+        ("first", seal(100), Ok(100)),
+        ("bad tag", bad_tag, Err("bad tag")),
+        ("other key", other_key, Err("bad tag")),
+        ("not authenticated", frame.clone(), Err("not authenticated")),
+        ("replayed", seal(100), Err("replayed counter")),
+        ("late", seal(37), Ok(37)),
+        ("below the window", seal(36), Err(below)),
+        ("late again", seal(37), Err("replayed counter")),
+        ("counter 0 again", seal(0), Err(below)),
+        ("next", seal(101), Ok(101)),
+    ];
+    let frames: Vec<_> = runs.iter().map(|(_, f, _)| f.clone()).collect();
+    let got = c_open(&frames);
+
+    let mut window = ReplayWindow::new();
+    for ((name, sealed, want), got) in runs.into_iter().zip(got) {
+        let rust = wire::open_auth(&sealed, &key())
+            .and_then(|(plain, counter)| window.check(counter).map(|()| (plain, counter)));
+        let want = want
+            .map(|counter| (frame.clone(), counter))
+            .map_err(FrameError::Auth);
+        assert_eq!(rust, want, "{name}");
+        assert_eq!(got, want.ok(), "{name}");
+    }
+}
+
+#[test]
+fn authenticated_exchange_matches_emulator() {
+    let mul = hex::decode("080210061807").unwrap();
+    let request =
+        |kind: ChecksumKind, seq| wire::wrap_v2(wire::TYPE_REQ, kind.flag_bits(), seq, &mul);
+    let seal = |frame: &[u8], counter| wire::seal_auth(frame, &key(), counter).unwrap();
+    // Padded with 900 bytes of field 15, so that it takes two fragments.
+    let mut padded = hex::decode("0802100618077A8407").unwrap();
+    padded.resize(padded.len() + 900, 0);
+    let big = seal(&wire::wrap_v2(wire::TYPE_REQ, 0, 9, &padded), 1002);
+    let mut frames = vec![
+        seal(&request(ChecksumKind::Crc32, 1), 1000),
+        seal(&request(ChecksumKind::Crc16Ccitt, 2), 1001),
+        seal(&request(ChecksumKind::Crc32, 3), 1000),
+        request(ChecksumKind::Crc32, 4),
+        seal(&request(ChecksumKind::None, 5), 999),
+        seal(&request(ChecksumKind::Crc32c, 6), 900),
+    ];
+    frames.extend(fragment::split(&big, fragment::DEFAULT_MTU).unwrap());
+
+    let r5 = R5Peer::new(R5Config {
+        auth: Some(key()),
+        ..Default::default()
+    });
+    let want: Vec<_> = frames.iter().map(|f| r5.handle_frame(f)).collect();
+    let lines: Vec<_> = frames.iter().map(hex::encode_upper).collect();
+    let got: Vec<_> = c_output(&["--key", &hex::encode(key_bytes())], &lines)
+        .lines()
+        .map(|line| line.split_once(' ').unwrap().1.to_string())
+        .map(|reply| (reply != "-").then(|| hex::decode(reply).unwrap()))
+        .collect();
+    assert_eq!(got, want);
+
+    let answered: Vec<_> = got.iter().map(Option::is_some).collect();
+    assert_eq!(
+        answered,
+        [true, true, false, false, true, false, false, true]
+    );
+    let (plain, counter) = wire::open_auth(got[7].as_ref().unwrap(), &key()).unwrap();
+    assert_eq!(counter, 1002);
+    assert_eq!(
+        linux_gateway::decode_calc_response(&plain).unwrap().result,
+        42
+    );
+}
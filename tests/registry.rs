@@ -0,0 +1,66 @@
+use linux_gateway::message;
+use linux_gateway::proto::{CalcRequest, CalcResponse, Op};
+use linux_gateway::{
+    decode_any, decode_frame, encode_frame, encode_frame_v2, wire, AnyMessage, FrameError,
+    WireMessage,
+};
+
+#[test]
+fn message_types_map_to_wire_bytes() {
+    assert_eq!(CalcRequest::TYPE, wire::TYPE_REQ);
+    assert_eq!(CalcResponse::TYPE, wire::TYPE_RESP);
+}
+
+#[test]
+fn generic_roundtrip_v1_and_v2() {
+    let req = CalcRequest {
+        op: Op::Sum as i32,
+        a: 300,
+        b: 70000,
+        trace: None,
+    };
+    assert_eq!(
+        decode_frame::<CalcRequest>(&encode_frame(&req)),
+        Ok(req.clone())
+    );
+    assert_eq!(
+        decode_frame::<CalcRequest>(&encode_frame_v2(&req, 5)),
+        Ok(req)
+    );
+}
+
+#[test]
+fn decode_any_dispatches_on_type_byte() {
+    let resp = CalcResponse {
+        result: 42,
+        ..Default::default()
+    };
+    let any = decode_any(&encode_frame_v2(&resp, 1)).expect("decode");
+    assert_eq!(any.wire_type(), wire::TYPE_RESP);
+    assert_eq!(any, AnyMessage::CalcResponse(resp.clone()));
+
+    assert_eq!(
+        decode_frame::<CalcRequest>(&encode_frame(&resp)),
+        Err(FrameError::UnknownType(wire::TYPE_RESP))
+    );
+    assert_eq!(
+        decode_any(&wire::wrap_v1(0x7E, b"")),
+        Err(FrameError::UnknownType(0x7E))
+    );
+}
+
+#[test]
+fn every_registered_type_has_its_schema() {
+    for typ in 0..=u8::MAX {
+        assert_eq!(message::is_registered(typ), message::schema(typ).is_some());
+        if let Some((name, _)) = message::schema(typ) {
+            assert_eq!(message::type_name(typ), Some(name));
+        }
+    }
+    let (_, fields) = message::schema(CalcRequest::TYPE).unwrap();
+    let names = |fields: &[linux_gateway::dissect::FieldDef]| {
+        fields.iter().map(|f| f.name).collect::<Vec<_>>()
+    };
+    assert_eq!(names(fields), names(CalcRequest::FIELDS));
+    assert_eq!(CalcResponse::FIELDS[0].name, "result");
+}
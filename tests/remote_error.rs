@@ -0,0 +1,42 @@
+use linux_gateway::proto::Status;
+use linux_gateway::{decode_any, decode_calc_response, encode_calc_error, AnyMessage, FrameError};
+
+#[test]
+fn error_frame_surfaces_as_remote_error() {
+    let frame = encode_calc_error(Status::UnsupportedOp, "unsupported op");
+    match decode_calc_response(&frame) {
+        Err(FrameError::RemoteError { code, detail }) => {
+            assert_eq!(code, Status::UnsupportedOp as i32);
+            assert_eq!(detail, "unsupported op");
+        }
+        other => panic!("expected RemoteError, got {other:?}"),
+    }
+    assert!(matches!(decode_any(&frame), Ok(AnyMessage::CalcError(_))));
+}
+
+#[test]
+fn decodes_error_frames_emitted_by_r5() {
+    // Captured from r5/frame_decode.c: an unsupported-op rejection and a garbage payload.
+    let unsupported =
+        hex::decode("A55A02030000121234B10802120E756E737570706F72746564206F702FF624E5").unwrap();
+    let bad_payload =
+        hex::decode("A55A020300001112340C0801120D656E642D6F662D73747265616D1C6A8728").unwrap();
+
+    assert_eq!(
+        decode_calc_response(&unsupported),
+        Err(FrameError::RemoteError {
+            code: Status::UnsupportedOp as i32,
+            detail: "unsupported op".into()
+        })
+    );
+    assert_eq!(
+        decode_calc_response(&bad_payload),
+        Err(FrameError::RemoteError {
+            code: Status::DecodeError as i32,
+            detail: "end-of-stream".into()
+        })
+    );
+
+    let ok = hex::decode("A55A020200000212343A082A2151BB52").unwrap();
+    assert_eq!(decode_calc_response(&ok).map(|r| r.result), Ok(42));
+}
@@ -0,0 +1,124 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use linux_gateway::remoteproc::{Remoteproc, RprocEvent, RprocState, Watcher};
+use linux_gateway::rpmsg::{EndpointManager, EndpointSpec, RpmsgIoctl};
+
+fn fake_rproc(root: &Path, state: &str) -> Remoteproc {
+    let dir = root.join("remoteproc0");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("state"), state).unwrap();
+    fs::write(dir.join("firmware"), "r5-calc.elf\n").unwrap();
+    fs::write(dir.join("recovery"), "enabled\n").unwrap();
+    Remoteproc::with_root(root, 0)
+}
+
+fn attr(rproc: &Remoteproc, name: &str) -> String {
+    fs::read_to_string(rproc.dir().join(name)).unwrap()
+}
+
+/// Creates `rpmsg0` in the fake sysfs root, as rpmsg_char would.
+struct FakeCtrl(PathBuf);
+
+impl RpmsgIoctl for FakeCtrl {
+    fn create_endpoint(&self, _ctrl: &Path, spec: &EndpointSpec) -> io::Result<()> {
+        let dir = self.0.join("rpmsg0");
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("name"), &spec.name)?;
+        fs::write(dir.join("src"), spec.src.to_string())?;
+        fs::write(dir.join("dst"), spec.dst.to_string())
+    }
+
+    fn destroy_endpoint(&self, _dev: &Path) -> io::Result<()> {
+        fs::remove_dir_all(self.0.join("rpmsg0"))
+    }
+}
+
+#[test]
+fn reads_and_drives_sysfs_attributes() {
+    let dir = tempfile::tempdir().unwrap();
+    let rproc = fake_rproc(dir.path(), "running\n");
+    assert_eq!(rproc.state().unwrap(), RprocState::Running);
+    assert_eq!(rproc.firmware().unwrap(), "r5-calc.elf");
+    assert!(rproc.recovery().unwrap());
+
+    rproc.set_recovery(false).unwrap();
+    assert_eq!(attr(&rproc, "recovery"), "disabled");
+    rproc.stop().unwrap();
+    assert_eq!(attr(&rproc, "state"), "stop");
+    rproc.start().unwrap();
+    assert_eq!(attr(&rproc, "state"), "start");
+}
+
+#[test]
+fn load_firmware_stops_selects_and_starts() {
+    let dir = tempfile::tempdir().unwrap();
+    let rproc = fake_rproc(dir.path(), "offline\n");
+    rproc.load_firmware("r5-calc-v2.elf").unwrap();
+    assert_eq!(attr(&rproc, "firmware"), "r5-calc-v2.elf");
+    assert_eq!(attr(&rproc, "state"), "start");
+    assert_eq!(
+        RprocState::parse("booting"),
+        RprocState::Other("booting".into())
+    );
+}
+
+#[tokio::test]
+async fn watcher_reports_crash_and_recreates_endpoints() {
+    let dir = tempfile::tempdir().unwrap();
+    let rproc = fake_rproc(dir.path(), "running\n");
+    let sysfs = dir.path().join("rpmsg");
+    fs::create_dir_all(&sysfs).unwrap();
+    let mgr = EndpointManager::new("/dev/rpmsg_ctrl0")
+        .with_roots(&sysfs, "/dev")
+        .with_ioctl(Arc::new(FakeCtrl(sysfs.clone())));
+    let spec = EndpointSpec {
+        name: "rpmsg-calc".into(),
+        src: 0x400,
+        dst: 0x401,
+    };
+
+    let watch = Watcher::new(rproc.clone())
+        .interval(Duration::from_millis(5))
+        .reestablish(Arc::new(mgr), vec![spec.clone()])
+        .spawn();
+    let mut events = watch.subscribe();
+    tokio::time::sleep(Duration::from_millis(30)).await;
+
+    fs::write(rproc.dir().join("state"), "crashed\n").unwrap();
+    assert_eq!(
+        recv(&mut events).await,
+        RprocEvent::State {
+            from: RprocState::Running,
+            to: RprocState::Crashed
+        }
+    );
+    assert_eq!(recv(&mut events).await, RprocEvent::Crashed);
+
+    fs::write(rproc.dir().join("state"), "running\n").unwrap();
+    assert_eq!(
+        recv(&mut events).await,
+        RprocEvent::State {
+            from: RprocState::Crashed,
+            to: RprocState::Running
+        }
+    );
+    match recv(&mut events).await {
+        RprocEvent::EndpointsReady(epts) => {
+            assert_eq!(epts.len(), 1);
+            assert_eq!(epts[0].spec, spec);
+            assert_eq!(epts[0].dev, Path::new("/dev/rpmsg0"));
+        }
+        other => panic!("unexpected event {other:?}"),
+    }
+}
+
+async fn recv(events: &mut tokio::sync::broadcast::Receiver<RprocEvent>) -> RprocEvent {
+    tokio::time::timeout(Duration::from_secs(2), events.recv())
+        .await
+        .unwrap()
+        .unwrap()
+}
@@ -0,0 +1,143 @@
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use linux_gateway::encode_calc_request;
+use linux_gateway::rpmsg::{
+    EndpointManager, EndpointSpec, RpmsgIoctl, RPMSG_ADDR_ANY, RPMSG_CREATE_EPT_IOCTL,
+    RPMSG_DESTROY_EPT_IOCTL,
+};
+use linux_gateway::transport::Transport;
+
+/// Stands in for rpmsg_char: create adds `rpmsgN` to the fake sysfs root and a
+/// FIFO under the fake /dev, destroy removes both.
+struct FakeCtrl {
+    sysfs: PathBuf,
+    dev: PathBuf,
+    next: Mutex<u32>,
+    calls: Mutex<Vec<String>>,
+}
+
+impl FakeCtrl {
+    fn new(root: &Path) -> Arc<Self> {
+        let sysfs = root.join("sys");
+        let dev = root.join("dev");
+        fs::create_dir_all(sysfs.join("rpmsg_ctrl0")).unwrap();
+        fs::create_dir_all(&dev).unwrap();
+        Arc::new(Self {
+            sysfs,
+            dev,
+            next: Mutex::new(0),
+            calls: Mutex::new(Vec::new()),
+        })
+    }
+
+    fn remove(&self, node: &str) {
+        fs::remove_dir_all(self.sysfs.join(node)).unwrap();
+        fs::remove_file(self.dev.join(node)).unwrap();
+    }
+}
+
+impl RpmsgIoctl for FakeCtrl {
+    fn create_endpoint(&self, ctrl: &Path, spec: &EndpointSpec) -> io::Result<()> {
+        spec.to_info()?;
+        let mut next = self.next.lock().unwrap();
+        let node = format!("rpmsg{}", *next);
+        *next += 1;
+        let dir = self.sysfs.join(&node);
+        fs::create_dir(&dir)?;
+        let src = if spec.src == RPMSG_ADDR_ANY {
+            1024
+        } else {
+            spec.src
+        };
+        fs::write(dir.join("name"), format!("{}\n", spec.name))?;
+        fs::write(dir.join("src"), format!("{src}\n"))?;
+        fs::write(dir.join("dst"), format!("{}\n", spec.dst))?;
+        let c = CString::new(self.dev.join(&node).as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(c.as_ptr(), 0o600) }, 0);
+        self.calls
+            .lock()
+            .unwrap()
+            .push(format!("create {} {}", ctrl.display(), spec.name));
+        Ok(())
+    }
+
+    fn destroy_endpoint(&self, dev: &Path) -> io::Result<()> {
+        let node = dev.file_name().unwrap().to_str().unwrap();
+        self.remove(node);
+        self.calls.lock().unwrap().push(format!("destroy {node}"));
+        Ok(())
+    }
+}
+
+fn manager(fake: &Arc<FakeCtrl>) -> EndpointManager {
+    EndpointManager::new("/dev/rpmsg_ctrl0")
+        .with_roots(&fake.sysfs, &fake.dev)
+        .with_ioctl(fake.clone())
+}
+
+fn spec() -> EndpointSpec {
+    EndpointSpec {
+        name: "rpmsg-calc".into(),
+        src: RPMSG_ADDR_ANY,
+        dst: 0x400,
+    }
+}
+
+#[test]
+fn ioctl_numbers_match_linux_header() {
+    assert_eq!(RPMSG_CREATE_EPT_IOCTL, 0x4028_b501);
+    assert_eq!(RPMSG_DESTROY_EPT_IOCTL, 0xb502);
+    let long = EndpointSpec {
+        name: "x".repeat(32),
+        ..spec()
+    };
+    assert_eq!(
+        long.to_info().unwrap_err().kind(),
+        io::ErrorKind::InvalidInput
+    );
+}
+
+#[test]
+fn create_finds_chardev_and_destroy_removes_it() {
+    let dir = tempfile::tempdir().unwrap();
+    let fake = FakeCtrl::new(dir.path());
+    let mgr = manager(&fake);
+
+    assert_eq!(mgr.find(&spec()).unwrap(), None);
+    let ept = mgr.create(&spec()).unwrap();
+    assert_eq!(ept.node, "rpmsg0");
+    assert_eq!(ept.dev, fake.dev.join("rpmsg0"));
+
+    // An existing endpoint is reused rather than created twice.
+    assert_eq!(mgr.create(&spec()).unwrap(), ept);
+
+    mgr.destroy(&ept).unwrap();
+    assert_eq!(mgr.find(&spec()).unwrap(), None);
+    assert_eq!(
+        *fake.calls.lock().unwrap(),
+        ["create /dev/rpmsg_ctrl0 rpmsg-calc", "destroy rpmsg0"]
+    );
+}
+
+#[tokio::test]
+async fn reopen_follows_endpoint_after_reset() {
+    let dir = tempfile::tempdir().unwrap();
+    let fake = FakeCtrl::new(dir.path());
+    let mgr = manager(&fake);
+    let mut ept = mgr.create(&spec()).unwrap();
+    mgr.open(&ept).unwrap();
+
+    // The remote reset tore the endpoint down; reopen brings up a new one.
+    fake.remove(&ept.node);
+    let t = mgr.reopen(&mut ept).unwrap();
+    assert_eq!(ept.node, "rpmsg1");
+
+    let frame = encode_calc_request(2, 3);
+    t.send(&frame).await.unwrap();
+    assert_eq!(t.recv().await.unwrap(), frame);
+}
@@ -0,0 +1,178 @@
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+use linux_gateway::client::{ClientConfig, GatewayClient};
+use linux_gateway::emulator::{R5Config, R5Peer};
+use linux_gateway::server::{router, AppState};
+
+async fn post(state: AppState, uri: &str, body: Value) -> (StatusCode, Value) {
+    let req = Request::post(uri)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let resp = router(state).oneshot(req).await.unwrap();
+    let status = resp.status();
+    let bytes = resp.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&bytes).unwrap())
+}
+
+fn emulated() -> AppState {
+    let (link, _r5) = R5Peer::new(R5Config::default()).loopback();
+    AppState::new(GatewayClient::new(link, ClientConfig::default()))
+}
+
+#[tokio::test]
+async fn calc_forwards_to_r5_with_trace_ids() {
+    let state = emulated();
+    let (status, body) = post(
+        state.clone(),
+        "/v1/calc",
+        json!({"op": "mul", "a": 6, "b": 7, "trace_id": "0af7651916cd43dd8448eb211c80319c"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["result"], 42);
+    assert_eq!(body["trace_id"], "0af7651916cd43dd8448eb211c80319c");
+    assert_eq!(body["trace_echoed"], true);
+
+    // Body ids the R5 could not carry are refused before anything is sent.
+    for (ids, why) in [
+        (json!({"trace_id": "0af7651916cd43dd"}), "trace.trace_id"),
+        (json!({"trace_id": "00".repeat(16)}), "trace.trace_id"),
+        (json!({"span_id": "b7ad6b71"}), "trace.span_id"),
+        (json!({"span_id": "00".repeat(8)}), "trace.span_id"),
+    ] {
+        let mut req = json!({"op": "mul", "a": 6, "b": 7});
+        req.as_object_mut()
+            .unwrap()
+            .extend(ids.as_object().unwrap().clone());
+        let (status, body) = post(state.clone(), "/v1/calc", req).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{ids} -> {body}");
+        assert_eq!(body["error"]["kind"], "bad_request");
+        let message = body["error"]["message"].as_str().unwrap();
+        assert!(message.contains(why), "{ids} -> {message}");
+    }
+
+    let (status, body) = post(state, "/v1/calc", json!({"op": "div", "a": 1, "b": 0})).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["error"]["kind"], "remote_error");
+    assert_eq!(body["error"]["code"], "STATUS_DIV_BY_ZERO");
+
+    let (status, body) = post(AppState::offline(), "/v1/calc", json!({"a": 1, "b": 2})).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["error"]["kind"], "no_link");
+}
+
+#[tokio::test]
+async fn encode_then_decode_roundtrips() {
+    let (status, body) = post(
+        AppState::offline(),
+        "/v1/frames/encode",
+        json!({
+            "message": "CalcRequest", "op": "sub", "a": 50, "b": 8, "seq": 9,
+            "trace": {"trace_id": "aa", "span_id": "bb", "flags": 1}
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let frame_hex = body["frame_hex"].as_str().unwrap().to_string();
+    assert!(frame_hex.starts_with("A55A02"));
+
+    let (status, body) = post(
+        AppState::offline(),
+        "/v1/frames/decode",
+        json!({ "frame_hex": frame_hex }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["seq"], 9);
+    assert_eq!(body["op"], "OP_SUB");
+    assert_eq!(body["a"], 50);
+    assert_eq!(body["trace"]["span_id"], "bb");
+
+    let (_, body) = post(
+        AppState::offline(),
+        "/v1/frames/encode",
+        json!({"message": "CalcResponse", "result": 42, "version": 1}),
+    )
+    .await;
+    assert_eq!(
+        body["frame_hex"],
+        hex::encode_upper(linux_gateway::encode_calc_response(42))
+    );
+}
+
+#[tokio::test]
+async fn errors_are_structured_json() {
+    let mut bad_crc = linux_gateway::encode_calc_response(1);
+    *bad_crc.last_mut().unwrap() ^= 1;
+    let cases = [
+        (
+            "/v1/frames/decode",
+            json!({"frame_hex": hex::encode(bad_crc)}),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "crc",
+        ),
+        (
+            "/v1/frames/decode",
+            json!({"frame_hex": "zz"}),
+            StatusCode::BAD_REQUEST,
+            "bad_request",
+        ),
+        (
+            "/v1/frames/decode",
+            json!({}),
+            StatusCode::BAD_REQUEST,
+            "bad_request",
+        ),
+        (
+            "/v1/frames/encode",
+            json!({"message": "CalcError", "code": "NOPE"}),
+            StatusCode::BAD_REQUEST,
+            "bad_request",
+        ),
+        (
+            "/v1/frames/encode",
+            json!({"message": "CalcResponse", "result": 1, "version": 7}),
+            StatusCode::BAD_REQUEST,
+            "bad_request",
+        ),
+    ];
+    for (uri, body, want_status, want_kind) in cases {
+        let (status, got) = post(AppState::offline(), uri, body.clone()).await;
+        assert_eq!(status, want_status, "{body} -> {got}");
+        assert_eq!(got["error"]["kind"], want_kind, "{body} -> {got}");
+        assert!(got["error"]["message"].is_string());
+    }
+}
+
+#[tokio::test]
+async fn encode_refuses_payloads_past_the_v2_length_field() {
+    let long = "x".repeat(70_000);
+    let cases = [
+        json!({"message": "CalcError", "code": "STATUS_DIV_BY_ZERO", "detail": long}),
+        json!({
+            "message": "CalcRequest", "a": 1, "b": 2,
+            "trace": {"trace_id": "aa", "span_id": "bb", "tracestate": long}
+        }),
+    ];
+    for body in cases {
+        let (status, got) = post(AppState::offline(), "/v1/frames/encode", body).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{got}");
+        assert_eq!(got["error"]["kind"], "too_long", "{got}");
+    }
+
+    // v1 has no length field, and a v2 payload just under it still fits.
+    for (version, len) in [(1, 70_000), (2, 65_000)] {
+        let body = json!({
+            "message": "CalcError", "code": "STATUS_DIV_BY_ZERO",
+            "detail": "x".repeat(len), "version": version
+        });
+        let (status, got) = post(AppState::offline(), "/v1/frames/encode", body).await;
+        assert_eq!(status, StatusCode::OK, "v{version}");
+        assert!(got["len"].as_u64().unwrap() > len as u64);
+    }
+}
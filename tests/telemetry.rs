@@ -0,0 +1,119 @@
+use std::sync::{Arc, Mutex};
+
+use axum::body::Bytes;
+use axum::routing::post;
+use axum::Router;
+use opentelemetry::trace::{TraceContextExt, TracerProvider as _};
+use opentelemetry_sdk::trace::TracerProvider;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+
+use linux_gateway::client::{ClientConfig, GatewayClient};
+use linux_gateway::emulator::{R5Config, R5Peer};
+use linux_gateway::proto::{CalcRequest, CalcResponse, Op, TraceCtx};
+use linux_gateway::telemetry;
+
+fn subscriber(provider: &TracerProvider) -> impl tracing::Subscriber + Send + Sync {
+    tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")))
+}
+
+#[test]
+fn trace_ctx_follows_the_current_span() {
+    assert_eq!(telemetry::current_trace_ctx(), None);
+    let provider = TracerProvider::builder().build();
+    tracing::subscriber::with_default(subscriber(&provider), || {
+        let span = tracing::info_span!("request");
+        let _guard = span.enter();
+        let sc = span.context().span().span_context().clone();
+        let trace = linux_gateway::new_trace_ctx();
+        assert_eq!(trace.trace_id, sc.trace_id().to_bytes());
+        assert_eq!(trace.span_id, sc.span_id().to_bytes());
+        assert_eq!(trace.flags, 1);
+    });
+}
+
+#[test]
+fn set_parent_continues_a_remote_trace() {
+    let parent = TraceCtx {
+        trace_id: vec![0x4b; 16],
+        span_id: vec![0xf0; 8],
+        flags: 1,
+        ..Default::default()
+    };
+    let provider = TracerProvider::builder().build();
+    tracing::subscriber::with_default(subscriber(&provider), || {
+        let span = tracing::info_span!("child");
+        telemetry::set_parent(&span, &parent);
+        let trace = span.in_scope(telemetry::current_trace_ctx).unwrap();
+        assert_eq!(trace.trace_id, parent.trace_id);
+        assert_ne!(trace.span_id, parent.span_id);
+    });
+}
+
+#[test]
+fn exports_request_and_r5_spans_to_otlp_collector() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let _rt = rt.enter();
+
+    // Mock collector: keeps every OTLP/HTTP protobuf body it is sent.
+    let received: Arc<Mutex<Vec<Bytes>>> = Arc::default();
+    let sink = received.clone();
+    let app = Router::new().route(
+        "/v1/traces",
+        post(move |body: Bytes| async move { sink.lock().unwrap().push(body) }),
+    );
+    let listener = rt
+        .block_on(tokio::net::TcpListener::bind("127.0.0.1:0"))
+        .unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    rt.spawn(async move { axum::serve(listener, app).await });
+
+    let provider = telemetry::otlp_provider("gateway-test", &endpoint).unwrap();
+    opentelemetry::global::set_tracer_provider(provider.clone());
+    let (link, _r5) = R5Peer::new(R5Config::default()).loopback();
+    let client = GatewayClient::new(link, ClientConfig::default());
+
+    let trace = tracing::subscriber::with_default(subscriber(&provider), || {
+        let span = tracing::info_span!("request");
+        let _guard = span.enter();
+        let trace = linux_gateway::new_trace_ctx();
+        let req = CalcRequest {
+            op: Op::Mul as i32,
+            a: 6,
+            b: 7,
+            trace: Some(trace.clone()),
+        };
+        let resp: CalcResponse = rt
+            .block_on(client.call(&req, client.call_options(true)))
+            .unwrap();
+        assert_eq!(resp.result, 42);
+        trace
+    });
+    // `calc` injects the current span itself.
+    let calc_trace = tracing::subscriber::with_default(subscriber(&provider), || {
+        let span = tracing::info_span!("calc");
+        let _guard = span.enter();
+        let resp = rt.block_on(client.calc(Op::Mul, 6, 7)).unwrap();
+        assert_eq!(resp.result, 42);
+        span.context().span().span_context().trace_id().to_bytes()
+    });
+    assert_ne!(calc_trace.to_vec(), trace.trace_id);
+    for res in provider.force_flush() {
+        res.unwrap();
+    }
+
+    let bodies = received.lock().unwrap();
+    let all: Vec<u8> = bodies.iter().flat_map(|b| b.to_vec()).collect();
+    let has = |needle: &[u8]| all.windows(needle.len()).any(|w| w == needle);
+    assert!(has(b"gateway-test"), "service name not exported");
+    assert!(has(b"request"), "request span not exported");
+    assert!(has(b"r5 CalcRequest"), "R5 span not exported");
+    assert!(has(&trace.trace_id), "spans not in the request's trace");
+    assert!(has(&calc_trace), "calc spans not in the caller's trace");
+    let r5_spans = all
+        .windows(b"r5 CalcRequest".len())
+        .filter(|w| *w == b"r5 CalcRequest")
+        .count();
+    assert_eq!(r5_spans, 2, "calc recorded no R5 span");
+}
@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use linux_gateway::client::{ClientConfig, GatewayClient};
+use linux_gateway::dissect::{dissect, Node};
+use linux_gateway::emulator::{R5Config, R5Peer};
+use linux_gateway::metrics::metrics;
+use linux_gateway::proto::{CalcResponse, Op};
+use linux_gateway::timing::{LatencyBreakdown, R5Timing};
+
+/// Reply from the `r5/` C build to `OP_MUL 6 7`, linked with a
+/// `calc_now_ns` that advances 250ns per call from 1000.
+const C_TIMED_REPLY: &str = "A55A020200000B0001F6082A10E20918D00F20FA01B6769CEE";
+
+fn find<'a>(nodes: &'a [Node], label: &str) -> &'a Node {
+    nodes
+        .iter()
+        .find(|n| n.label == label)
+        .unwrap_or_else(|| panic!("no {label:?} in {nodes:#?}"))
+}
+
+#[test]
+fn breakdown_splits_rtt_around_r5_readings() {
+    let untimed = CalcResponse {
+        result: 1,
+        ..Default::default()
+    };
+    assert_eq!(R5Timing::from_response(&untimed), None);
+    let backwards = CalcResponse {
+        r5_rx_ns: 10,
+        r5_tx_ns: 5,
+        ..Default::default()
+    };
+    assert_eq!(R5Timing::from_response(&backwards), None);
+
+    let resp = linux_gateway::decode_calc_response(&hex::decode(C_TIMED_REPLY).unwrap()).unwrap();
+    assert_eq!(resp.result, 42);
+    let r5 = R5Timing::from_response(&resp).unwrap();
+    assert_eq!(
+        r5,
+        R5Timing {
+            rx_ns: 1250,
+            tx_ns: 2000,
+            compute_ns: 250
+        }
+    );
+    let b = LatencyBreakdown::new(Duration::from_micros(10), &r5);
+    assert_eq!(b.processing, Duration::from_nanos(250));
+    assert_eq!(b.queueing, Duration::from_nanos(500));
+    assert_eq!(b.transit, Duration::from_nanos(9250));
+
+    // A round trip shorter than the R5 claims (clock drift) clamps to zero.
+    let b = LatencyBreakdown::new(Duration::from_nanos(100), &r5);
+    assert_eq!(b.transit, Duration::ZERO);
+}
+
+#[tokio::test]
+async fn emulator_queueing_shows_up_in_histograms() {
+    let queueing = metrics().latency.with_label_values(&["queueing"]);
+    let transit = metrics().latency.with_label_values(&["transit"]);
+    let (count_before, sum_before) = (queueing.get_sample_count(), queueing.get_sample_sum());
+    let transit_before = transit.get_sample_count();
+
+    let (link, _r5) = R5Peer::new(R5Config {
+        latency: Duration::from_millis(40),
+        clock: true,
+        ..Default::default()
+    })
+    .loopback();
+    let client = GatewayClient::new(link, ClientConfig::default());
+    let resp = client.calc(Op::Sum, 40, 2).await.unwrap();
+    assert_eq!(resp.result, 42);
+
+    let r5 = R5Timing::from_response(&resp).expect("emulator clock");
+    assert!(r5.on_r5() >= Duration::from_millis(40), "{r5:?}");
+    assert_eq!(queueing.get_sample_count() - count_before, 1);
+    assert!(queueing.get_sample_sum() - sum_before >= 0.04);
+    assert_eq!(transit.get_sample_count() - transit_before, 1);
+
+    // Without a clock the reply carries no timing and nothing is recorded.
+    let (link, _r5) = R5Peer::new(R5Config::default()).loopback();
+    let client = GatewayClient::new(link, ClientConfig::default());
+    let resp = client.calc(Op::Sum, 1, 2).await.unwrap();
+    assert_eq!(R5Timing::from_response(&resp), None);
+    assert_eq!(queueing.get_sample_count() - count_before, 1);
+}
+
+#[test]
+fn timing_fields_are_dissected_and_decoded() {
+    let frame = hex::decode(C_TIMED_REPLY).unwrap();
+    let d = dissect(&frame);
+    assert!(!d.has_problem());
+    let payload = find(&d.nodes, "payload");
+    assert_eq!(find(&payload.children, "r5_rx_ns (2)").value, "1250");
+    assert_eq!(find(&payload.children, "r5_tx_ns (3)").value, "2000");
+    assert_eq!(find(&payload.children, "r5_compute_ns (4)").value, "250");
+
+    let json = linux_gateway::server::frame_to_json(&frame).unwrap();
+    assert_eq!(json["r5_rx_ns"], 1250);
+    assert_eq!(json["r5_tx_ns"], 2000);
+    assert_eq!(json["r5_compute_ns"], 250);
+}
@@ -0,0 +1,98 @@
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use http_body_util::BodyExt;
+use serde_json::Value;
+use tower::ServiceExt;
+
+use linux_gateway::client::{ClientConfig, GatewayClient};
+use linux_gateway::emulator::{R5Config, R5Peer};
+use linux_gateway::proto::TraceCtx;
+use linux_gateway::server::{router, AppState};
+use linux_gateway::trace_context::{fit_tracestate, TraceparentError, TRACESTATE_MAX};
+
+const PARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+#[test]
+fn parses_and_formats_traceparent() {
+    let ctx = TraceCtx::from_w3c(PARENT, Some("congo=t61rcWkgMzE")).unwrap();
+    assert_eq!(
+        hex::encode(&ctx.trace_id),
+        "4bf92f3577b34da6a3ce929d0e0e4736"
+    );
+    assert_eq!(hex::encode(&ctx.span_id), "00f067aa0ba902b7");
+    assert!(ctx.is_sampled());
+    assert_eq!(ctx.tracestate, "congo=t61rcWkgMzE");
+    assert_eq!(ctx.traceparent(), PARENT);
+
+    // Unknown flag bits are dropped; later versions may carry extra fields.
+    let ctx = TraceCtx::from_w3c(&PARENT.replace("-01", "-fe"), None).unwrap();
+    assert_eq!(ctx.flags, 0);
+    assert!(TraceCtx::from_w3c(&format!("cc{}-what", &PARENT[2..]), None).is_ok());
+
+    let bad = [
+        (
+            format!("ff{}", &PARENT[2..]),
+            TraceparentError::Version(0xff),
+        ),
+        (PARENT.to_uppercase(), TraceparentError::Malformed),
+        (format!("{PARENT}-extra"), TraceparentError::Malformed),
+        (PARENT[..50].to_string(), TraceparentError::Malformed),
+        (
+            format!("00-{}-00f067aa0ba902b7-01", "0".repeat(32)),
+            TraceparentError::ZeroId,
+        ),
+    ];
+    for (header, want) in bad {
+        assert_eq!(TraceCtx::from_w3c(&header, None), Err(want), "{header}");
+    }
+}
+
+#[test]
+fn random_ids_and_tracestate_fitting() {
+    let a = TraceCtx::random(true);
+    let b = linux_gateway::new_trace_ctx();
+    assert_eq!((a.trace_id.len(), a.span_id.len()), (16, 8));
+    assert_ne!(a.trace_id, b.trace_id);
+    assert_ne!(a.span_id[..], a.trace_id[8..]);
+    assert!(a.is_sampled() && b.is_sampled());
+    assert!(!TraceCtx::random(false).is_sampled());
+    let child = a.child();
+    assert_eq!(child.trace_id, a.trace_id);
+    assert_ne!(child.span_id, a.span_id);
+
+    assert_eq!(fit_tracestate(" a=1 ,, b=2,junk"), "a=1,b=2");
+    let long = format!("big={},x=1", "v".repeat(200));
+    assert_eq!(fit_tracestate(&long), "x=1");
+    let many: Vec<String> = (0..20).map(|i| format!("k{i:02}=value")).collect();
+    let fitted = fit_tracestate(&many.join(","));
+    assert!(fitted.len() <= TRACESTATE_MAX);
+    assert!(fitted.starts_with("k00=value,k01=value") && !fitted.contains("k19"));
+}
+
+#[tokio::test]
+async fn http_trace_context_carries_through_the_r5() {
+    let (link, _r5) = R5Peer::new(R5Config::default()).loopback();
+    let state = AppState::new(GatewayClient::new(link, ClientConfig::default()));
+    let req = Request::post("/v1/calc")
+        .header("content-type", "application/json")
+        .header("traceparent", PARENT)
+        .header("tracestate", "congo=t61rcWkgMzE")
+        .body(Body::from(r#"{"op":"sum","a":40,"b":2}"#))
+        .unwrap();
+    let resp = router(state).oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let header = |name: &str| resp.headers()[name].to_str().unwrap().to_string();
+    let (traceparent, tracestate) = (header("traceparent"), header("tracestate"));
+    let back = TraceCtx::from_w3c(&traceparent, Some(&tracestate)).unwrap();
+    assert_eq!(hex::encode(&back.trace_id), &PARENT[3..35]);
+    assert_ne!(hex::encode(&back.span_id), &PARENT[36..52]);
+    assert!(back.is_sampled());
+    assert_eq!(back.tracestate, "congo=t61rcWkgMzE");
+
+    let body = resp.into_body().collect().await.unwrap().to_bytes();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["result"], 42);
+    assert_eq!(body["trace_echoed"], true);
+    assert_eq!(body["traceparent"], traceparent);
+}
@@ -1,20 +1,16 @@
-use prost::Message;
+use linux_gateway::emulator::{Firmware, R5Config, R5Peer};
 
 #[test]
 fn trace_roundtrips_linux_to_r5_to_linux() {
     // Linux: create request frame with trace
     let (req_frame, sent_trace) = linux_gateway::encode_calc_request_with_trace_ctx(7, 35);
 
-    // --- R5 side emulation (decode -> compute -> encode, echoing TraceCtx) ---
-    let req = linux_gateway::decode_calc_request(&req_frame).expect("decode req");
-    let resp = linux_gateway::proto::CalcResponse {
-        result: req.a + req.b,    // pretend R5 did the math
-        trace: req.trace.clone(), // echo/propagate trace
-    };
-    let mut payload = Vec::new();
-    resp.encode(&mut payload).unwrap();
-    let resp_frame = linux_gateway::wire::wrap_v1_resp(&payload);
-    // -------------------------------------------------------------------------
+    // R5 side: the original v1 firmware decodes, adds and echoes TraceCtx
+    let r5 = R5Peer::new(R5Config {
+        firmware: Firmware::Legacy,
+        ..Default::default()
+    });
+    let resp_frame = r5.handle_frame(&req_frame).expect("R5 reply");
 
     // Linux: decode response and assert the trace matches
     let got_resp = linux_gateway::decode_calc_response(&resp_frame).expect("decode resp");
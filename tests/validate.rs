@@ -0,0 +1,147 @@
+use linux_gateway::client::{ClientConfig, ClientError, GatewayClient};
+use linux_gateway::emulator::{R5Config, R5Peer};
+use linux_gateway::proto::{CalcError, CalcRequest, CalcResponse, Op, TraceCtx};
+use linux_gateway::validate::{Validate, Validation, ValidationError};
+use linux_gateway::{
+    decode_any_with_limits, decode_calc_request, decode_reply_with_limits, encode_frame,
+    encode_frame_v2, AnyMessage, DecodeLimits, FrameError,
+};
+
+fn with(validation: Validation) -> DecodeLimits {
+    DecodeLimits {
+        validation,
+        ..Default::default()
+    }
+}
+
+fn bad_request() -> CalcRequest {
+    CalcRequest {
+        op: 7,
+        a: 1,
+        b: 2,
+        trace: Some(TraceCtx {
+            trace_id: vec![1, 2, 3],
+            span_id: vec![0; 8],
+            flags: 0x83,
+            tracestate: String::new(),
+        }),
+    }
+}
+
+#[test]
+fn problems_are_listed_field_by_field() {
+    let want = vec![
+        ValidationError::UnknownEnum {
+            field: "op",
+            value: 7,
+        },
+        ValidationError::Length {
+            field: "trace.trace_id",
+            len: 3,
+            expected: 16,
+        },
+        ValidationError::ZeroId {
+            field: "trace.span_id",
+        },
+        ValidationError::UndefinedFlags {
+            field: "trace.flags",
+            bits: 0x82,
+        },
+    ];
+    assert_eq!(bad_request().validate(), Err(want.clone()));
+
+    let err = decode_calc_request(&encode_frame(&bad_request())).unwrap_err();
+    assert_eq!(err, FrameError::Invalid(want));
+    assert_eq!(err.kind(), "invalid");
+    assert_eq!(
+        err.to_string(),
+        "invalid message: op 7 is not in the enum; trace.trace_id is 3 bytes, expected 16; \
+         trace.span_id is all zero; trace.flags has undefined bits 0x82"
+    );
+
+    let good = CalcRequest {
+        op: Op::Div as i32,
+        trace: Some(TraceCtx::random(true)),
+        ..Default::default()
+    };
+    assert_eq!(good.validate(), Ok(()));
+    assert_eq!(decode_calc_request(&encode_frame(&good)), Ok(good));
+}
+
+#[test]
+fn permissive_and_off_pass_messages_on() {
+    let frame = encode_frame_v2(&bad_request(), 3);
+    for mode in [Validation::Permissive, Validation::Off] {
+        let msg = decode_any_with_limits(&frame, &with(mode)).unwrap();
+        assert_eq!(msg, AnyMessage::CalcRequest(bad_request()));
+    }
+    assert!(matches!(
+        decode_any_with_limits(&frame, &with(Validation::Strict)),
+        Err(FrameError::Invalid(_))
+    ));
+
+    // A CalcError with a status this side does not know.
+    let err = CalcError {
+        code: 42,
+        detail: "new in firmware 9".into(),
+        trace: None,
+    };
+    let frame = encode_frame(&err);
+    let strict = decode_reply_with_limits::<CalcResponse>(&frame, &with(Validation::Strict));
+    assert_eq!(
+        strict,
+        Err(FrameError::Invalid(vec![ValidationError::UnknownEnum {
+            field: "code",
+            value: 42
+        }]))
+    );
+    let permissive =
+        decode_reply_with_limits::<CalcResponse>(&frame, &with(Validation::Permissive));
+    assert!(matches!(
+        permissive,
+        Err(FrameError::RemoteError { code: 42, .. })
+    ));
+}
+
+#[tokio::test]
+async fn client_validates_replies_per_its_limits() {
+    // The R5 echoes the request's trace, short trace_id and all.
+    let req = CalcRequest {
+        op: Op::Sum as i32,
+        a: 40,
+        b: 2,
+        trace: Some(TraceCtx {
+            trace_id: vec![0xAA; 3],
+            span_id: vec![0xBB; 8],
+            flags: 1,
+            tracestate: String::new(),
+        }),
+    };
+    for (validation, ok) in [(Validation::Strict, false), (Validation::Permissive, true)] {
+        let (link, _r5) = R5Peer::new(R5Config::default()).loopback();
+        let config = ClientConfig {
+            limits: with(validation),
+            ..Default::default()
+        };
+        let client = GatewayClient::new(link, config);
+        let reply = client
+            .call::<_, CalcResponse>(&req, client.call_options(false))
+            .await;
+        match reply {
+            Ok(resp) => {
+                assert!(ok, "{validation:?}");
+                assert_eq!(resp.result, 42);
+            }
+            Err(ClientError::Frame(FrameError::Invalid(errors))) => {
+                assert!(!ok, "{validation:?}");
+                assert_eq!(errors.len(), 1, "{errors:?}");
+            }
+            Err(e) => panic!("{e:?}"),
+        }
+    }
+
+    // Decoding a capture lists the problems instead of failing.
+    let json = linux_gateway::server::frame_to_json(&encode_frame(&bad_request())).unwrap();
+    assert_eq!(json["a"], 1);
+    assert_eq!(json["invalid"].as_array().unwrap().len(), 4);
+}
@@ -0,0 +1,84 @@
+use linux_gateway::wire::{self, DecodeEvent, FrameDecoder};
+use linux_gateway::{encode_calc_response, FrameError};
+
+#[test]
+fn v2_roundtrip_carries_seq_and_flags() {
+    let frame = wire::wrap_v2(wire::TYPE_REQ, 0x04, 0xBEEF, b"\x10\x07\x18\x23");
+    assert_eq!(frame.len(), wire::V2_HEADER_LEN + 4 + 4);
+    assert_eq!(&frame[..3], &[0xA5, 0x5A, wire::PROTO_VERSION_V2]);
+
+    let f = wire::unwrap_v2_req(&frame).expect("unwrap");
+    assert_eq!(f.header.version, 2);
+    assert_eq!(f.header.flags, 0x04);
+    assert_eq!(f.header.seq, 0xBEEF);
+    assert_eq!(f.payload, b"\x10\x07\x18\x23");
+    assert_eq!(
+        wire::unwrap_v2_resp(&frame).unwrap_err(),
+        FrameError::UnknownType(wire::TYPE_REQ)
+    );
+}
+
+#[test]
+fn v2_unwrap_accepts_v1_frames() {
+    let v1 = encode_calc_response(42);
+    let f = wire::unwrap_v2_resp(&v1).expect("v1 frame");
+    assert_eq!(f.header.version, wire::PROTO_VERSION);
+    assert_eq!(f.header.seq, 0);
+    assert_eq!(f.payload, &v1[2..v1.len() - 4]);
+}
+
+#[test]
+fn set_seq_rewrites_header_crc() {
+    let mut frame = wire::wrap_v2_resp(7, b"\x08\x2a");
+    assert_eq!(wire::set_seq(&mut frame, 0x0102), Ok(7));
+    assert_eq!(frame, wire::wrap_v2_resp(0x0102, b"\x08\x2a"));
+
+    let mut v1 = encode_calc_response(42);
+    assert!(wire::set_seq(&mut v1, 1).is_err());
+}
+
+#[test]
+fn v2_detects_truncation_and_header_corruption() {
+    let frame = wire::wrap_v2_resp(7, b"\x08\x2a");
+
+    let truncated = &frame[..frame.len() - 1];
+    assert_eq!(
+        wire::unwrap_v2_resp(truncated).unwrap_err(),
+        FrameError::TooShort
+    );
+
+    let mut bad_len = frame.clone();
+    bad_len[6] ^= 0x01;
+    assert_eq!(
+        wire::unwrap_v2_resp(&bad_len).unwrap_err(),
+        FrameError::HeaderCrc
+    );
+
+    let mut bad_payload = frame.clone();
+    bad_payload[wire::V2_HEADER_LEN] ^= 0xFF;
+    assert_eq!(
+        wire::unwrap_v2_resp(&bad_payload).unwrap_err(),
+        FrameError::Crc
+    );
+}
+
+#[test]
+fn decoder_handles_mixed_v1_and_v2_streams() {
+    let v1 = encode_calc_response(1);
+    let v2 = wire::wrap_v2_resp(9, b"\x08\x02");
+    let mut stream = wire::wrap_sync(&v1);
+    stream.extend(&v2);
+    stream.extend(wire::wrap_v2_resp(10, &[0u8; 600]));
+
+    let mut dec = FrameDecoder::new();
+    let events = dec.feed(&stream);
+    assert_eq!(events[0], DecodeEvent::Frame(v1));
+    assert_eq!(events[1], DecodeEvent::Frame(v2));
+    assert!(matches!(
+        events[2],
+        DecodeEvent::Skipped {
+            reason: FrameError::TooLong(614),
+            ..
+        }
+    ));
+}